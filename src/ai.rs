@@ -0,0 +1,200 @@
+//! Movement rules for entities that walk around on their own. Decisions only
+//! depend on positions and a tile cost function, so they can be run without a screen.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::entity::{Direction, Position};
+use crate::pathfinding;
+
+const DIRECTIONS: [Direction; 4] = [Direction::Up, Direction::Down, Direction::Left, Direction::Right];
+
+/// Chance that a wandering entity stays put for a turn instead of stepping.
+const WANDER_IDLE_CHANCE: f64 = 0.4;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Behaviour {
+    /// Steps in a random direction now and then
+    Wander,
+    /// Walks between waypoints in order, looping back to the first
+    Patrol { route: Vec<(i32, i32)>, next: usize },
+    /// Heads for the player while they are within `radius` tiles, wanders otherwise
+    Chase { radius: i32 },
+    /// Runs from the player while they are within `radius` tiles, wanders otherwise
+    Flee { radius: i32 },
+    /// Walks to wherever its schedule says to be at this time of day, and waits there
+    Schedule { stops: Vec<Stop> },
+}
+
+/// Where an NPC keeps to from `from` (minutes after midnight) until the next
+/// stop of its schedule.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Stop {
+    pub from: u32,
+    pub position: (i32, i32),
+}
+
+/// The stop a schedule, sorted by time, has an NPC at `minute` of the day.
+/// Before the first stop of the day, the last one of the day before holds.
+pub fn scheduled_stop(stops: &[Stop], minute: f64) -> Option<Stop> {
+    stops.iter().rev().find(|stop| f64::from(stop.from) <= minute).or(stops.last()).copied()
+}
+
+/// When an entity gets to move.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Pace {
+    /// Once every time the player takes a step
+    Turn,
+    /// Once every `interval` seconds
+    Timer { interval: f64 },
+}
+
+/// Gives an entity a mind of its own.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Ai {
+    pub behaviour: Behaviour,
+    pub pace: Pace,
+    /// Moves left to sit out, e.g. after the player escaped from it
+    pub rest: u32,
+    elapsed: f64,
+}
+
+impl Ai {
+    pub fn new(behaviour: Behaviour, pace: Pace) -> Self {
+        Ai {
+            behaviour,
+            pace,
+            rest: 0,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Advances the timer and returns true if a timed entity should move now.
+    pub fn tick(&mut self, dt: f64) -> bool {
+        let Pace::Timer { interval } = self.pace else {
+            return false;
+        };
+
+        self.elapsed += dt;
+        if self.elapsed < interval {
+            return false;
+        }
+        self.elapsed = 0.0;
+        true
+    }
+
+    /// True if the entity is chasing the player and has caught up with them.
+    pub fn has_caught(&self, position: Position, player: Position) -> bool {
+        self.rest == 0 && matches!(self.behaviour, Behaviour::Chase { .. }) && distance(position, player) == 1
+    }
+
+    /// Picks the next step, if any, at `minute` of the day. `cost` is what it
+    /// takes the entity to enter a tile, `None` if it can't.
+    pub fn next_step(
+        &mut self,
+        position: Position,
+        player: Position,
+        minute: f64,
+        rng: &mut impl Rng,
+        cost: impl Fn(i32, i32) -> Option<u32>,
+    ) -> Option<Direction> {
+        if self.rest > 0 {
+            self.rest -= 1;
+            return None;
+        }
+
+        match &mut self.behaviour {
+            Behaviour::Wander => wander(position, rng, cost),
+            Behaviour::Patrol { route, next } => {
+                let &target = route.get(*next)?;
+                let here = (position.x, position.y);
+                if here == target {
+                    *next = (*next + 1) % route.len();
+                }
+
+                let step = step_towards(position, route[*next], cost);
+                if step.is_none() && here != route[*next] {
+                    // Skip waypoints that can't be reached right now
+                    *next = (*next + 1) % route.len();
+                }
+                step
+            }
+            Behaviour::Chase { radius } => {
+                if distance(position, player) <= *radius {
+                    // The player's tile is the goal even though nobody may enter it
+                    let goal = (player.x, player.y);
+                    step_towards(position, goal, |x, y| if (x, y) == goal { Some(1) } else { cost(x, y) })
+                        .filter(|direction| direction.delta() != (player.x - position.x, player.y - position.y))
+                } else {
+                    wander(position, rng, cost)
+                }
+            }
+            Behaviour::Flee { radius } => {
+                if distance(position, player) <= *radius {
+                    step_away(position, player, *radius, cost)
+                } else {
+                    wander(position, rng, cost)
+                }
+            }
+            Behaviour::Schedule { stops } => {
+                let target = scheduled_stop(stops, minute)?.position;
+                if (position.x, position.y) == target {
+                    return None;
+                }
+                step_towards(position, target, cost)
+            }
+        }
+    }
+}
+
+/// Number of orthogonal steps between two tiles, ignoring obstacles.
+pub fn distance(a: Position, b: Position) -> i32 {
+    (a.x - b.x).abs() + (a.y - b.y).abs()
+}
+
+fn wander(position: Position, rng: &mut impl Rng, cost: impl Fn(i32, i32) -> Option<u32>) -> Option<Direction> {
+    if rng.gen_bool(WANDER_IDLE_CHANCE) {
+        return None;
+    }
+
+    let free: Vec<Direction> = DIRECTIONS
+        .into_iter()
+        .filter(|direction| {
+            let (dx, dy) = direction.delta();
+            cost(position.x + dx, position.y + dy).is_some()
+        })
+        .collect();
+    if free.is_empty() {
+        return None;
+    }
+    Some(free[rng.gen_range(0..free.len())])
+}
+
+/// First step of the cheapest path to `target`.
+fn step_towards(position: Position, target: (i32, i32), cost: impl Fn(i32, i32) -> Option<u32>) -> Option<Direction> {
+    let path = pathfinding::find_path((position.x, position.y), target, cost)?;
+    let &(x, y) = path.first()?;
+    Direction::from_delta(x - position.x, y - position.y)
+}
+
+/// Steps to the neighbouring tile that takes the threat longest to walk to,
+/// if that is further than where the entity stands.
+fn step_away(position: Position, threat: Position, radius: i32, cost: impl Fn(i32, i32) -> Option<u32>) -> Option<Direction> {
+    let here = (position.x, position.y);
+    let passable = |x: i32, y: i32| (x, y) == here || cost(x, y).is_some();
+    let field = pathfinding::distance_field(&[(threat.x, threat.y)], passable, Some(radius as u32 * 2));
+    // Tiles beyond the searched area are further than anything in it
+    let steps_from_threat = |tile| field.get(&tile).copied().unwrap_or(u32::MAX);
+
+    let current = steps_from_threat(here);
+    DIRECTIONS
+        .into_iter()
+        .filter_map(|direction| {
+            let (dx, dy) = direction.delta();
+            let next = (position.x + dx, position.y + dy);
+            cost(next.0, next.1)?;
+            Some((steps_from_threat(next), direction))
+        })
+        .filter(|(steps, _)| *steps > current)
+        .max_by_key(|(steps, _)| *steps)
+        .map(|(_, direction)| direction)
+}
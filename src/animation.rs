@@ -0,0 +1,206 @@
+//! Frame animations defined in data, and per-entity playback state. Nothing
+//! here draws; it only decides which sprite is showing at a given time.
+
+use serde::Deserialize;
+use crate::ai;
+use crate::entity::Position;
+
+/// Time an entity takes to slide from one tile to the next.
+pub const STEP_DURATION: f64 = 0.12;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoopMode {
+    /// Starts over after the last frame
+    Loop,
+    /// Stops on the last frame
+    Once,
+    /// Plays forwards, then backwards, and so on
+    PingPong,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Frame {
+    pub sprite: String,
+    /// Seconds the frame stays up
+    pub duration: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AnimationDef {
+    pub id: String,
+    pub mode: LoopMode,
+    pub frames: Vec<Frame>,
+}
+
+impl AnimationDef {
+    pub fn length(&self) -> f64 {
+        self.frames.iter().map(|frame| frame.duration).sum()
+    }
+
+    /// Sprite showing `time` seconds after the animation started.
+    pub fn sprite_at(&self, time: f64) -> &str {
+        let length = self.length();
+        let time = match self.mode {
+            LoopMode::Loop => time.rem_euclid(length),
+            LoopMode::Once => time.min(length),
+            LoopMode::PingPong => {
+                let time = time.rem_euclid(length * 2.0);
+                if time < length { time } else { length * 2.0 - time }
+            }
+        };
+
+        let mut start = 0.0;
+        for frame in &self.frames {
+            start += frame.duration;
+            if time < start {
+                return &frame.sprite;
+            }
+        }
+        &self.frames[self.frames.len() - 1].sprite
+    }
+}
+
+/// Every animation, loaded from `assets/data/animations.json`.
+#[derive(Debug)]
+pub struct AnimationDb {
+    animations: Vec<AnimationDef>,
+}
+
+impl AnimationDb {
+    pub fn builtin() -> Self {
+        AnimationDb::from_json(include_str!("../assets/data/animations.json"))
+            .unwrap_or_else(|e| panic!("Invalid built-in animations: {}", e))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let animations: Vec<AnimationDef> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, animation) in animations.iter().enumerate() {
+            if animation.frames.is_empty() {
+                return Err(format!("animation '{}' has no frames", animation.id));
+            }
+            if animation.frames.iter().any(|frame| frame.duration <= 0.0) {
+                return Err(format!("animation '{}' has a frame without a positive duration", animation.id));
+            }
+            if animations[..index].iter().any(|other| other.id == animation.id) {
+                return Err(format!("animation '{}' is defined twice", animation.id));
+            }
+        }
+
+        Ok(AnimationDb { animations })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&AnimationDef> {
+        self.animations.iter().find(|animation| animation.id == id)
+    }
+}
+
+/// What an entity is doing, for picking its animation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pose {
+    Idle,
+    Walk,
+    Interact,
+}
+
+impl Pose {
+    pub fn name(self) -> &'static str {
+        match self {
+            Pose::Idle => "idle",
+            Pose::Walk => "walk",
+            Pose::Interact => "interact",
+        }
+    }
+}
+
+/// Playback state of one entity. Watches the entity's position to notice
+/// steps, so whatever moved it doesn't need to know about animation.
+pub struct Animator {
+    pose: Pose,
+    /// Time spent in the current pose
+    elapsed: f64,
+    /// How long the current pose lasts, for poses that end on their own
+    pose_length: Option<f64>,
+    position: Position,
+    /// Tile the entity is sliding in from, and how far along it is (0 to 1)
+    slide: Option<(Position, f64)>,
+    since_step: f64,
+}
+
+impl Animator {
+    pub fn new(position: Position) -> Self {
+        Animator {
+            pose: Pose::Idle,
+            elapsed: 0.0,
+            pose_length: None,
+            position,
+            slide: None,
+            since_step: 0.0,
+        }
+    }
+
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    fn set_pose(&mut self, pose: Pose, length: Option<f64>) {
+        self.pose = pose;
+        self.pose_length = length;
+        self.elapsed = 0.0;
+    }
+
+    /// Plays the interact pose for `length` seconds.
+    pub fn interact(&mut self, length: f64) {
+        self.set_pose(Pose::Interact, Some(length));
+    }
+
+    /// Advances by `dt` seconds with the entity now at `position`. A single
+    /// step starts a slide over from the old tile; longer jumps don't.
+    pub fn update(&mut self, position: Position, dt: f64) {
+        self.elapsed += dt;
+        self.since_step += dt;
+
+        if position != self.position {
+            let stepped = ai::distance(position, self.position) == 1;
+            self.slide = stepped.then_some((self.position, 0.0));
+            self.position = position;
+            self.since_step = 0.0;
+            // Keep the walk cycle going through consecutive steps
+            if stepped && self.pose != Pose::Walk {
+                self.set_pose(Pose::Walk, None);
+            }
+        }
+
+        if let Some((_, progress)) = &mut self.slide {
+            *progress += dt / STEP_DURATION;
+            if *progress >= 1.0 {
+                self.slide = None;
+            }
+        }
+
+        let finished = match self.pose {
+            Pose::Walk => self.slide.is_none() && self.since_step > STEP_DURATION * 2.0,
+            _ => self.pose_length.is_some_and(|length| self.elapsed >= length),
+        };
+        if finished {
+            self.set_pose(Pose::Idle, None);
+        }
+    }
+
+    /// Where to draw the entity, in grid coordinates that may fall between tiles.
+    pub fn draw_position(&self) -> (f64, f64) {
+        let (x, y) = (self.position.x as f64, self.position.y as f64);
+        match self.slide {
+            Some((from, progress)) => (
+                from.x as f64 + (x - from.x as f64) * progress,
+                from.y as f64 + (y - from.y as f64) * progress,
+            ),
+            None => (x, y),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! The in-game clock, which runs on from the update tick at its own pace and
+//! drives the tint of day and night and the schedules NPCs keep.
+
+use graphics::types::Color;
+use serde::{Deserialize, Serialize};
+
+pub const MINUTES_PER_DAY: f64 = 24.0 * 60.0;
+/// Game minutes that pass every real second, so a day lasts 12 minutes.
+pub const DEFAULT_TIME_SCALE: f64 = 2.0;
+/// The first day starts in the morning.
+const START_TIME: f64 = 8.0 * 60.0;
+
+const NIGHT_TINT: Color = [0.02, 0.02, 0.15, 0.55];
+const DAWN_TINT: Color = [0.9, 0.5, 0.3, 0.18];
+const DAY_TINT: Color = [0.0, 0.0, 0.0, 0.0];
+const DUSK_TINT: Color = [0.8, 0.35, 0.15, 0.25];
+/// Tint over the map at each hour, blended in between.
+const TINTS: [(f64, Color); 8] = [
+    (0.0, NIGHT_TINT),
+    (5.0, NIGHT_TINT),
+    (6.5, DAWN_TINT),
+    (8.0, DAY_TINT),
+    (17.0, DAY_TINT),
+    (19.0, DUSK_TINT),
+    (21.0, NIGHT_TINT),
+    (24.0, NIGHT_TINT),
+];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Clock {
+    /// Game minutes since midnight before the first day
+    minutes: f64,
+    /// Game minutes that pass every real second; 0 stops the clock
+    pub scale: f64,
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Clock {
+            minutes: START_TIME,
+            scale: DEFAULT_TIME_SCALE,
+        }
+    }
+
+    /// Runs the clock on by `dt` real seconds.
+    pub fn advance(&mut self, dt: f64) {
+        self.minutes += dt * self.scale;
+    }
+
+    /// Counting from day 1.
+    pub fn day(&self) -> u32 {
+        (self.minutes / MINUTES_PER_DAY) as u32 + 1
+    }
+
+    /// Minutes since midnight today.
+    pub fn minute_of_day(&self) -> f64 {
+        self.minutes.rem_euclid(MINUTES_PER_DAY)
+    }
+
+    /// Winds the clock forward to the next time it reads `hour:minute`.
+    /// Time never runs backwards, so an earlier time means tomorrow.
+    pub fn set_time(&mut self, hour: u32, minute: u32) {
+        let target = f64::from(hour * 60 + minute) % MINUTES_PER_DAY;
+        self.minutes += (target - self.minute_of_day()).rem_euclid(MINUTES_PER_DAY);
+    }
+
+    /// The time for the HUD, such as `DAY 2  07:45`.
+    pub fn label(&self) -> String {
+        let minute = self.minute_of_day() as u32;
+        format!("DAY {}  {:02}:{:02}", self.day(), minute / 60, minute % 60)
+    }
+
+    /// Colour laid over the map at this time of day.
+    pub fn tint(&self) -> Color {
+        let hour = self.minute_of_day() / 60.0;
+        for pair in TINTS.windows(2) {
+            let [(start, from), (end, to)] = [pair[0], pair[1]];
+            if (start..end).contains(&hour) {
+                let t = ((hour - start) / (end - start)) as f32;
+                return [0, 1, 2, 3].map(|i| from[i] + (to[i] - from[i]) * t);
+            }
+        }
+        NIGHT_TINT
+    }
+}
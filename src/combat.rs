@@ -0,0 +1,465 @@
+//! Turn-based combat rules, independent of rendering and input so a battle can be
+//! driven entirely from code.
+
+use std::collections::VecDeque;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+use crate::items::{Inventory, ItemCategory, ItemDb, ItemStack};
+use crate::stats::{Character, Stats};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DropDef {
+    pub item: String,
+    pub count: u32,
+    /// Probability between 0 and 1 that the drop is awarded
+    pub chance: f64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnemyDef {
+    pub id: String,
+    pub name: String,
+    pub level: u32,
+    pub stats: Stats,
+    pub xp: u32,
+    #[serde(default)]
+    pub drops: Vec<DropDef>,
+}
+
+/// Every enemy the game knows about, loaded from `assets/data/enemies.json`.
+#[derive(Debug)]
+pub struct EnemyDb {
+    enemies: Vec<EnemyDef>,
+}
+
+impl EnemyDb {
+    pub fn builtin() -> Self {
+        EnemyDb::from_json(include_str!("../assets/data/enemies.json"))
+            .unwrap_or_else(|e| panic!("Invalid built-in enemies: {}", e))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let enemies: Vec<EnemyDef> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, enemy) in enemies.iter().enumerate() {
+            if enemy.stats.max_hp <= 0 {
+                return Err(format!("enemy '{}' must have positive max_hp", enemy.id));
+            }
+            if let Some(drop) = enemy.drops.iter().find(|drop| !(0.0..=1.0).contains(&drop.chance)) {
+                return Err(format!("enemy '{}' has drop '{}' with a chance outside 0..1", enemy.id, drop.item));
+            }
+            if enemies[..index].iter().any(|other| other.id == enemy.id) {
+                return Err(format!("enemy '{}' is defined twice", enemy.id));
+            }
+        }
+
+        Ok(EnemyDb { enemies })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&EnemyDef> {
+        self.enemies.iter().find(|enemy| enemy.id == id)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SkillDef {
+    pub id: String,
+    pub name: String,
+    /// Player level at which the skill is learned
+    pub level: u32,
+    pub mp_cost: i32,
+    pub power: i32,
+}
+
+/// Every skill the player can learn, loaded from `assets/data/skills.json`.
+#[derive(Debug)]
+pub struct SkillDb {
+    skills: Vec<SkillDef>,
+}
+
+impl SkillDb {
+    pub fn builtin() -> Self {
+        SkillDb::from_json(include_str!("../assets/data/skills.json"))
+            .unwrap_or_else(|e| panic!("Invalid built-in skills: {}", e))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let skills: Vec<SkillDef> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, skill) in skills.iter().enumerate() {
+            if skill.mp_cost < 0 {
+                return Err(format!("skill '{}' has a negative MP cost", skill.id));
+            }
+            if skills[..index].iter().any(|other| other.id == skill.id) {
+                return Err(format!("skill '{}' is defined twice", skill.id));
+            }
+        }
+
+        Ok(SkillDb { skills })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&SkillDef> {
+        self.skills.iter().find(|skill| skill.id == id)
+    }
+
+    /// Skills known at the given level, in data file order.
+    pub fn known_at(&self, level: u32) -> impl Iterator<Item = &SkillDef> {
+        self.skills.iter().filter(move |skill| skill.level <= level)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Combatant {
+    Player,
+    Enemy(usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Action {
+    Attack { target: usize },
+    Skill { skill: String, target: usize },
+    Item { item: String },
+    Flee,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// `defeated` holds the ids of the enemies beaten
+    Victory { xp: u32, drops: Vec<ItemStack>, defeated: Vec<String> },
+    Defeat,
+    Fled,
+}
+
+pub struct Battle {
+    pub player: Character,
+    pub enemies: Vec<Character>,
+    enemy_defs: Vec<EnemyDef>,
+    /// Turns left in the current round, fastest first
+    queue: VecDeque<Combatant>,
+    pub log: Vec<String>,
+    pub outcome: Option<Outcome>,
+    rng: StdRng,
+}
+
+impl Battle {
+    /// Starts a battle and runs enemy turns until the player gets to act.
+    pub fn new(player: Character, enemy_defs: Vec<EnemyDef>, seed: u64) -> Self {
+        let enemies = enemy_defs
+            .iter()
+            .map(|def| Character {
+                name: def.name.clone(),
+                level: def.level,
+                xp: 0,
+                hp: def.stats.max_hp,
+                mp: def.stats.max_mp,
+                stats: def.stats,
+            })
+            .collect::<Vec<_>>();
+
+        let names = enemies.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", ");
+        let mut battle = Battle {
+            player,
+            enemies,
+            enemy_defs,
+            queue: VecDeque::new(),
+            log: vec![format!("{} appeared!", names)],
+            outcome: None,
+            rng: StdRng::seed_from_u64(seed),
+        };
+        battle.advance();
+        battle
+    }
+
+    pub fn is_player_turn(&self) -> bool {
+        self.outcome.is_none() && self.queue.front() == Some(&Combatant::Player)
+    }
+
+    pub fn living_enemies(&self) -> impl Iterator<Item = (usize, &Character)> {
+        self.enemies.iter().enumerate().filter(|(_, enemy)| enemy.is_alive())
+    }
+
+    /// Performs the player's action. Invalid actions (dead target, not enough MP,
+    /// missing item) are rejected without using up the turn.
+    pub fn player_action(&mut self, action: Action, items: &ItemDb, skills: &SkillDb, inventory: &mut Inventory) -> Result<(), String> {
+        if !self.is_player_turn() {
+            return Err("It is not your turn.".to_string());
+        }
+
+        match action {
+            Action::Attack { target } => {
+                let enemy = self.target(target)?;
+                let (name, defense) = (enemy.name.clone(), enemy.stats.defense);
+                let damage = self.roll_damage(self.player.stats.attack, defense, 0);
+                self.enemies[target].take_damage(damage);
+                self.log.push(format!("{} attacks {} for {} damage.", self.player.name, name, damage));
+            }
+            Action::Skill { skill, target } => {
+                let skill = skills.get(&skill).ok_or("Unknown skill.")?.clone();
+                if skill.level > self.player.level {
+                    return Err(format!("{} has not been learned yet.", skill.name));
+                }
+                if self.player.mp < skill.mp_cost {
+                    return Err(format!("Not enough MP for {}.", skill.name));
+                }
+                let enemy = self.target(target)?;
+                let (name, defense) = (enemy.name.clone(), enemy.stats.defense);
+                self.player.mp -= skill.mp_cost;
+                let damage = self.roll_damage(self.player.stats.attack, defense, skill.power);
+                self.enemies[target].take_damage(damage);
+                self.log.push(format!("{} uses {} on {} for {} damage.", self.player.name, skill.name, name, damage));
+            }
+            Action::Item { item } => {
+                let def = items.get(&item).ok_or("Unknown item.")?;
+                let effect = match (def.category, def.effect) {
+                    (ItemCategory::Consumable, Some(effect)) => effect,
+                    _ => return Err(format!("The {} can't be used in battle.", def.name)),
+                };
+                if inventory.count(&item) == 0 {
+                    return Err(format!("You have no {} left.", def.name));
+                }
+                // Healing nothing changes nothing, so the item and the turn are kept
+                let healed = self.player.heal(effect.heal_hp);
+                let restored = self.player.restore_mp(effect.restore_mp);
+                if healed == 0 && restored == 0 {
+                    return Err(format!("The {} would have no effect.", def.name));
+                }
+                inventory.remove(&item, 1);
+                self.log.push(format!("{} uses the {}. +{} HP, +{} MP", self.player.name, def.name, healed, restored));
+            }
+            Action::Flee => {
+                if self.rng.gen_bool(self.flee_chance()) {
+                    self.log.push(format!("{} got away safely.", self.player.name));
+                    self.outcome = Some(Outcome::Fled);
+                    return Ok(());
+                }
+                self.log.push(format!("{} couldn't escape!", self.player.name));
+            }
+        }
+
+        self.queue.pop_front();
+        self.advance();
+        Ok(())
+    }
+
+    /// Chance of escaping, better the faster the player is compared to the enemies.
+    pub fn flee_chance(&self) -> f64 {
+        let (total, count) = self
+            .living_enemies()
+            .fold((0, 0), |(total, count), (_, enemy)| (total + enemy.stats.speed, count + 1));
+        if count == 0 {
+            return 1.0;
+        }
+        let enemy_speed = (total as f64 / count as f64).max(1.0);
+        let player_speed = self.player.stats.speed.max(1) as f64;
+        (0.25 + 0.5 * player_speed / (player_speed + enemy_speed)).min(0.95)
+    }
+
+    fn target(&self, index: usize) -> Result<&Character, String> {
+        self.enemies
+            .get(index)
+            .filter(|enemy| enemy.is_alive())
+            .ok_or_else(|| "That target is not standing.".to_string())
+    }
+
+    /// Damage of one hit: twice the attack plus skill power, minus defense,
+    /// with a little random variance. Always at least 1.
+    fn roll_damage(&mut self, attack: i32, defense: i32, power: i32) -> i32 {
+        let base = (attack * 2 + power - defense).max(1) as f64;
+        let variance = self.rng.gen_range(0.85..=1.0);
+        ((base * variance).round() as i32).max(1)
+    }
+
+    /// Runs turns until it is the player's move or the battle is decided.
+    fn advance(&mut self) {
+        loop {
+            if self.check_outcome() {
+                return;
+            }
+
+            match self.queue.front().copied() {
+                None => self.start_round(),
+                Some(Combatant::Player) => return,
+                Some(Combatant::Enemy(index)) => {
+                    self.queue.pop_front();
+                    if self.enemies[index].is_alive() {
+                        self.enemy_turn(index);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Orders everyone still standing by speed. The player wins ties.
+    fn start_round(&mut self) {
+        let mut order = vec![(self.player.stats.speed, Combatant::Player)];
+        order.extend(self.living_enemies().map(|(index, enemy)| (enemy.stats.speed, Combatant::Enemy(index))));
+        order.sort_by_key(|(speed, _)| std::cmp::Reverse(*speed));
+        self.queue = order.into_iter().map(|(_, combatant)| combatant).collect();
+    }
+
+    fn enemy_turn(&mut self, index: usize) {
+        let attack = self.enemies[index].stats.attack;
+        let damage = self.roll_damage(attack, self.player.stats.defense, 0);
+        self.player.take_damage(damage);
+        self.log.push(format!("{} attacks {} for {} damage.", self.enemies[index].name, self.player.name, damage));
+    }
+
+    fn check_outcome(&mut self) -> bool {
+        if self.outcome.is_some() {
+            return true;
+        }
+
+        if !self.player.is_alive() {
+            self.log.push(format!("{} has fallen...", self.player.name));
+            self.outcome = Some(Outcome::Defeat);
+        } else if self.living_enemies().next().is_none() {
+            let xp = self.enemy_defs.iter().map(|def| def.xp).sum();
+            let mut drops: Vec<ItemStack> = Vec::new();
+            for drop in self.enemy_defs.iter().flat_map(|def| def.drops.iter()) {
+                if self.rng.gen_bool(drop.chance) {
+                    drops.push(ItemStack { item: drop.item.clone(), count: drop.count });
+                }
+            }
+            self.log.push(format!("Victory! Gained {} XP.", xp));
+            let defeated = self.enemy_defs.iter().map(|def| def.id.clone()).collect();
+            self.outcome = Some(Outcome::Victory { xp, drops, defeated });
+        }
+
+        self.outcome.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEMS: &str = r#"[
+        { "id": "apple", "name": "Apple", "description": "", "stack_size": 10,
+          "category": "consumable", "effect": { "heal_hp": 10 } },
+        { "id": "stone", "name": "Stone", "description": "", "stack_size": 20, "category": "material" }
+    ]"#;
+    const SKILLS: &str = r#"[
+        { "id": "strike", "name": "Strike", "level": 1, "mp_cost": 3, "power": 6 },
+        { "id": "fire", "name": "Fire", "level": 5, "mp_cost": 5, "power": 12 }
+    ]"#;
+
+    fn stats(max_hp: i32, attack: i32, defense: i32, speed: i32) -> Stats {
+        Stats { max_hp, max_mp: 10, attack, defense, speed }
+    }
+
+    fn player(stats: Stats) -> Character {
+        Character { name: "HERO".to_string(), level: 1, xp: 0, hp: stats.max_hp, mp: stats.max_mp, stats }
+    }
+
+    fn enemy(id: &str, stats: Stats) -> EnemyDef {
+        EnemyDef { id: id.to_string(), name: id.to_uppercase(), level: 1, stats, xp: 5, drops: Vec::new() }
+    }
+
+    fn dbs() -> (ItemDb, SkillDb) {
+        (ItemDb::from_json(ITEMS).unwrap(), SkillDb::from_json(SKILLS).unwrap())
+    }
+
+    #[test]
+    fn faster_enemies_act_before_the_player() {
+        let battle = Battle::new(player(stats(30, 5, 0, 3)), vec![enemy("wolf", stats(10, 5, 0, 9))], 1);
+        assert!(battle.is_player_turn());
+        assert!(battle.player.hp < 30);
+
+        let battle = Battle::new(player(stats(30, 5, 0, 9)), vec![enemy("slug", stats(10, 5, 0, 3))], 1);
+        assert!(battle.is_player_turn());
+        assert_eq!(battle.player.hp, 30);
+    }
+
+    #[test]
+    fn every_hit_does_at_least_one_damage() {
+        let (items, skills) = dbs();
+        let mut battle = Battle::new(player(stats(30, 0, 0, 9)), vec![enemy("golem", stats(50, 0, 100, 1))], 1);
+        battle.player_action(Action::Attack { target: 0 }, &items, &skills, &mut Inventory::default()).unwrap();
+        assert_eq!(battle.enemies[0].hp, 49);
+        assert_eq!(battle.player.hp, 29);
+    }
+
+    #[test]
+    fn invalid_actions_keep_the_turn() {
+        let (items, skills) = dbs();
+        let mut inventory = Inventory::default();
+        let mut battle = Battle::new(player(stats(30, 5, 0, 9)), vec![enemy("slug", stats(50, 5, 0, 1))], 1);
+        let actions = [
+            Action::Skill { skill: "nothing".to_string(), target: 0 },
+            Action::Skill { skill: "fire".to_string(), target: 0 },
+            Action::Attack { target: 3 },
+            Action::Item { item: "stone".to_string() },
+            Action::Item { item: "apple".to_string() },
+        ];
+        let refuse = |action, battle: &mut Battle, inventory: &mut Inventory| {
+            let log_len = battle.log.len();
+            assert!(battle.player_action(action, &items, &skills, inventory).is_err());
+            assert!(battle.is_player_turn());
+            assert_eq!(battle.log.len(), log_len);
+            assert_eq!(battle.player.mp, 10);
+        };
+        for action in actions {
+            refuse(action, &mut battle, &mut inventory);
+        }
+
+        // An apple at full health would be wasted
+        inventory.add(&items, "apple", 1);
+        refuse(Action::Item { item: "apple".to_string() }, &mut battle, &mut inventory);
+        assert_eq!(inventory.count("apple"), 1);
+
+        battle.player.hp -= 5;
+        battle.player_action(Action::Item { item: "apple".to_string() }, &items, &skills, &mut inventory).unwrap();
+        assert_eq!(inventory.count("apple"), 0);
+    }
+
+    #[test]
+    fn fleeing_depends_on_the_seed() {
+        let (items, skills) = dbs();
+        let flee = |seed| {
+            let mut battle = Battle::new(player(stats(30, 5, 0, 1)), vec![enemy("wolf", stats(10, 5, 0, 9))], seed);
+            let hp = battle.player.hp;
+            battle.player_action(Action::Flee, &items, &skills, &mut Inventory::default()).unwrap();
+            (battle.outcome, hp - battle.player.hp)
+        };
+
+        let results: Vec<_> = (0..20).map(flee).collect();
+        let escaped = results.iter().find(|(outcome, _)| *outcome == Some(Outcome::Fled)).expect("no seed escaped");
+        let caught = results.iter().find(|(outcome, _)| outcome.is_none()).expect("no seed failed to escape");
+        assert_eq!(escaped.1, 0);
+        // A failed escape uses up the turn, so the enemy gets to hit again
+        assert!(caught.1 > 0);
+        assert_eq!(flee(7), flee(7));
+    }
+
+    #[test]
+    fn winning_gives_xp_and_drops() {
+        let (items, skills) = dbs();
+        let mut slime = enemy("slime", stats(1, 1, 0, 1));
+        slime.drops = vec![
+            DropDef { item: "apple".to_string(), count: 2, chance: 1.0 },
+            DropDef { item: "stone".to_string(), count: 1, chance: 0.0 },
+        ];
+        let mut battle = Battle::new(player(stats(30, 5, 0, 9)), vec![slime, enemy("rat", stats(1, 1, 0, 1))], 1);
+        battle.player_action(Action::Attack { target: 0 }, &items, &skills, &mut Inventory::default()).unwrap();
+        assert!(battle.outcome.is_none());
+        battle.player_action(Action::Attack { target: 1 }, &items, &skills, &mut Inventory::default()).unwrap();
+        assert_eq!(
+            battle.outcome,
+            Some(Outcome::Victory {
+                xp: 10,
+                drops: vec![ItemStack { item: "apple".to_string(), count: 2 }],
+                defeated: vec!["slime".to_string(), "rat".to_string()],
+            })
+        );
+    }
+
+    #[test]
+    fn falling_gives_defeat() {
+        let mut hero = player(stats(30, 5, 0, 1));
+        hero.hp = 1;
+        let battle = Battle::new(hero, vec![enemy("ogre", stats(50, 20, 0, 9))], 1);
+        assert_eq!(battle.outcome, Some(Outcome::Defeat));
+        assert!(!battle.is_player_turn());
+    }
+}
@@ -0,0 +1,541 @@
+//! Cutscenes: scripted sequences that take control away from the player
+//! until they finish or are skipped.
+//!
+//! Cutscenes live in `assets/data/cutscenes.txt`, laid out like scripts: an
+//! `@name` header followed by one step per line. Unlike a script, which runs
+//! all at once, the steps play out over time:
+//!
+//! ```text
+//! @wren_greeting
+//! move WREN 10 6 9 6
+//! face player right
+//! popup "WREN: Oh! A visitor." 2
+//! fade out 0.5
+//! ```
+//!
+//! A [`CutscenePlayer`] only reaches the game through [`CutsceneHost`], so it
+//! can be run against anything that implements it, a window or not.
+
+use std::collections::HashMap;
+use crate::entity::Direction;
+use crate::script::{self, parse_int, parse_text, ScriptHost, Value};
+
+/// Seconds an actor takes to walk one tile.
+const STEP_INTERVAL: f64 = 0.25;
+/// Seconds a fade takes when the step doesn't say.
+const DEFAULT_FADE: f64 = 0.5;
+
+/// Who a step moves or turns.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Actor {
+    Player,
+    /// The NPC of this name on the current map
+    Npc(String),
+}
+
+impl Actor {
+    fn parse(token: &str) -> Actor {
+        match token {
+            "player" => Actor::Player,
+            name => Actor::Npc(name.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    /// Walks an actor to each waypoint in turn, a tile at a time, going
+    /// across before going up or down
+    Move { actor: Actor, path: Vec<(i32, i32)> },
+    Face { actor: Actor, direction: Direction },
+    /// Shows a text box and waits until it goes away
+    Popup { text: String, duration: f64 },
+    Wait(f64),
+    /// Darkens the screen to `to`, from 0 (clear) to 1 (black)
+    Fade { to: f64, duration: f64 },
+    /// Goes to a map file, at its spawn point unless a position is given
+    Map { name: String, position: Option<(i32, i32)> },
+    Set { name: String, value: Value },
+    Clear(String),
+}
+
+pub type Cutscene = Vec<Step>;
+
+/// What cutscenes can do to the game, on top of what scripts can.
+pub trait CutsceneHost: ScriptHost {
+    fn actor_position(&self, actor: &Actor) -> Option<(i32, i32)>;
+    /// Puts an actor on a tile, facing the way it went. Returns false if the
+    /// tile is blocked.
+    fn place_actor(&mut self, actor: &Actor, position: (i32, i32), facing: Direction) -> bool;
+    fn face_actor(&mut self, actor: &Actor, direction: Direction);
+    fn go_to_map(&mut self, name: &str, position: Option<(i32, i32)>);
+}
+
+#[derive(Debug, Default)]
+pub struct CutsceneLibrary {
+    cutscenes: HashMap<String, Cutscene>,
+}
+
+impl CutsceneLibrary {
+    pub fn builtin() -> Self {
+        CutsceneLibrary::parse(include_str!("../assets/data/cutscenes.txt"))
+            .unwrap_or_else(|e| panic!("Invalid built-in cutscenes: {}", e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        Ok(CutsceneLibrary {
+            cutscenes: script::parse_sections(source, parse_step)?,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Cutscene> {
+        self.cutscenes.get(name)
+    }
+}
+
+fn parse_seconds(token: Option<&String>, default: f64, what: &str) -> Result<f64, String> {
+    match token {
+        Some(secs) => match secs.parse::<f64>() {
+            Ok(secs) if secs >= 0.0 => Ok(secs),
+            _ => Err(format!("invalid {}", what)),
+        },
+        None => Ok(default),
+    }
+}
+
+fn parse_step(tokens: &[String]) -> Result<Step, String> {
+    let Some(keyword) = tokens.first() else {
+        return Err("empty step".to_string());
+    };
+    let args = &tokens[1..];
+
+    let step = match keyword.as_str() {
+        "move" => {
+            let actor = Actor::parse(&parse_text(args.first(), "actor")?);
+            let coords = &args[1.min(args.len())..];
+            if coords.is_empty() || !coords.len().is_multiple_of(2) {
+                return Err("move needs one or more x y waypoints".to_string());
+            }
+            let path = coords
+                .chunks(2)
+                .map(|pair| Ok((parse_int(pair.first(), "x")?, parse_int(pair.get(1), "y")?)))
+                .collect::<Result<_, String>>()?;
+            Step::Move { actor, path }
+        }
+        "face" => Step::Face {
+            actor: Actor::parse(&parse_text(args.first(), "actor")?),
+            direction: args
+                .get(1)
+                .and_then(|d| Direction::from_name(d))
+                .ok_or("expected up, down, left or right")?,
+        },
+        "popup" => Step::Popup {
+            text: parse_text(args.first(), "popup text")?,
+            duration: parse_seconds(args.get(1), 2.0, "popup duration")?,
+        },
+        "wait" => Step::Wait(parse_seconds(args.first(), 1.0, "wait")?),
+        "fade" => Step::Fade {
+            to: match args.first().map(String::as_str) {
+                Some("out") => 1.0,
+                Some("in") => 0.0,
+                _ => return Err("expected fade out or fade in".to_string()),
+            },
+            duration: parse_seconds(args.get(1), DEFAULT_FADE, "fade duration")?,
+        },
+        "map" => Step::Map {
+            name: parse_text(args.first(), "map name")?,
+            position: match args.len() {
+                1 => None,
+                _ => Some((parse_int(args.get(1), "x")?, parse_int(args.get(2), "y")?)),
+            },
+        },
+        "set" => Step::Set {
+            name: parse_text(args.first(), "variable name")?,
+            value: args.get(1).map_or(Value::Bool(true), |token| Value::parse(token)),
+        },
+        "clear" => Step::Clear(parse_text(args.first(), "variable name")?),
+        other => return Err(format!("unknown step '{}'", other)),
+    };
+
+    Ok(step)
+}
+
+/// The way to go from one tile towards another: across first, then up or down.
+fn heading(from: (i32, i32), to: (i32, i32)) -> Option<Direction> {
+    if from.0 != to.0 {
+        Direction::from_delta((to.0 - from.0).signum(), 0)
+    } else {
+        Direction::from_delta(0, (to.1 - from.1).signum())
+    }
+}
+
+/// Plays one cutscene. It holds where the cutscene is up to; everything it
+/// changes goes through the host.
+pub struct CutscenePlayer {
+    steps: Cutscene,
+    index: usize,
+    /// Seconds spent on the current step, or since the last tile of a move
+    elapsed: f64,
+    /// Waypoint of the current move being walked to
+    waypoint: usize,
+    /// Whether the current step has done what it does on starting
+    started: bool,
+    /// How dark the screen is, from 0 to 1
+    fade: f64,
+    fade_from: f64,
+}
+
+impl CutscenePlayer {
+    pub fn new(steps: Cutscene) -> Self {
+        CutscenePlayer {
+            steps,
+            index: 0,
+            elapsed: 0.0,
+            waypoint: 0,
+            started: false,
+            fade: 0.0,
+            fade_from: 0.0,
+        }
+    }
+
+    pub fn fade(&self) -> f64 {
+        self.fade
+    }
+
+    /// Plays `dt` seconds of the cutscene. Returns false once it is over.
+    pub fn update(&mut self, dt: f64, host: &mut dyn CutsceneHost) -> bool {
+        self.elapsed += dt;
+
+        while let Some(step) = self.steps.get(self.index).cloned() {
+            if !self.started {
+                self.start(&step, host);
+            }
+            if !self.play(&step, host) {
+                return true;
+            }
+            self.index += 1;
+            self.waypoint = 0;
+            self.started = false;
+        }
+
+        // Control goes back to the player with the screen clear
+        self.fade = 0.0;
+        false
+    }
+
+    /// Jumps to the end, keeping what lasts, such as where actors end up and
+    /// the flags set, but not popups, waits or fades.
+    pub fn skip(&mut self, host: &mut dyn CutsceneHost) {
+        for step in self.steps.split_off(self.index) {
+            match &step {
+                Step::Move { actor, path } => {
+                    let Some(&end) = path.last() else {
+                        continue;
+                    };
+                    let from = match path.len() {
+                        1 => host.actor_position(actor),
+                        len => Some(path[len - 2]),
+                    };
+                    let facing = from.and_then(|from| heading(from, end));
+                    if !host.place_actor(actor, end, facing.unwrap_or(Direction::Down)) {
+                        eprintln!("Cutscene could not put {:?} at {:?}", actor, end);
+                    }
+                }
+                Step::Popup { .. } | Step::Wait(_) | Step::Fade { .. } => {}
+                _ => self.start(&step, host),
+            }
+        }
+        self.fade = 0.0;
+    }
+
+    /// Does what a step does as it begins. Steps that take no time do all of
+    /// it here.
+    fn start(&mut self, step: &Step, host: &mut dyn CutsceneHost) {
+        self.started = true;
+        match step {
+            Step::Face { actor, direction } => host.face_actor(actor, *direction),
+            Step::Popup { text, duration } => host.show_popup(text, *duration),
+            Step::Fade { .. } => self.fade_from = self.fade,
+            Step::Map { name, position } => host.go_to_map(name, *position),
+            Step::Set { name, value } => host.set_var(name, value.clone()),
+            Step::Clear(name) => host.set_flag(name, false),
+            Step::Move { .. } | Step::Wait(_) => {}
+        }
+    }
+
+    /// Carries on with a step, using up the time it takes. Returns whether
+    /// the step is finished.
+    fn play(&mut self, step: &Step, host: &mut dyn CutsceneHost) -> bool {
+        let length = match step {
+            Step::Move { actor, path } => return self.walk(actor, path, host),
+            Step::Popup { duration, .. } => *duration,
+            Step::Wait(duration) => *duration,
+            Step::Fade { to, duration } => {
+                let progress = if *duration > 0.0 { (self.elapsed / duration).min(1.0) } else { 1.0 };
+                self.fade = self.fade_from + (to - self.fade_from) * progress;
+                *duration
+            }
+            _ => 0.0,
+        };
+        if self.elapsed < length {
+            return false;
+        }
+        self.elapsed -= length;
+        true
+    }
+
+    /// Walks an actor a tile for every `STEP_INTERVAL` that has passed.
+    /// An actor that is missing or gets blocked gives up on the move.
+    fn walk(&mut self, actor: &Actor, path: &[(i32, i32)], host: &mut dyn CutsceneHost) -> bool {
+        loop {
+            let Some(position) = host.actor_position(actor) else {
+                eprintln!("Cutscene actor {:?} is not on the map", actor);
+                return true;
+            };
+            while path.get(self.waypoint) == Some(&position) {
+                self.waypoint += 1;
+            }
+            let Some(&target) = path.get(self.waypoint) else {
+                return true;
+            };
+            if self.elapsed < STEP_INTERVAL {
+                return false;
+            }
+            self.elapsed -= STEP_INTERVAL;
+
+            let Some(direction) = heading(position, target) else {
+                return true;
+            };
+            let (dx, dy) = direction.delta();
+            if !host.place_actor(actor, (position.0 + dx, position.1 + dy), direction) {
+                eprintln!("Cutscene actor {:?} is blocked at {:?}", actor, position);
+                return true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{Entity, EntityId, Npc, World};
+    use crate::script::Flags;
+
+    /// A game with nothing but entities on an open field and story flags.
+    struct TestHost {
+        world: World,
+        player: EntityId,
+        flags: Flags,
+        popups: Vec<String>,
+        maps: Vec<(String, Option<(i32, i32)>)>,
+    }
+
+    impl TestHost {
+        fn new() -> Self {
+            let mut world = World::new();
+            let player = world.spawn(Entity::new(0, 0).with_facing(Direction::Down).with_collider());
+            TestHost { world, player, flags: Flags::default(), popups: Vec::new(), maps: Vec::new() }
+        }
+
+        fn with_npc(mut self, name: &str, x: i32, y: i32) -> Self {
+            let npc = Npc { name: name.to_string(), dialogue: String::new() };
+            self.world.spawn(Entity::new(x, y).with_facing(Direction::Down).with_collider().with_npc(npc));
+            self
+        }
+
+        fn actor_id(&self, actor: &Actor) -> Option<EntityId> {
+            match actor {
+                Actor::Player => Some(self.player),
+                Actor::Npc(name) => self
+                    .world
+                    .iter()
+                    .find(|(_, entity)| entity.npc.as_ref().is_some_and(|npc| &npc.name == name))
+                    .map(|(id, _)| id),
+            }
+        }
+
+        fn facing(&self, actor: &Actor) -> Option<Direction> {
+            self.world.get(self.actor_id(actor)?)?.facing
+        }
+    }
+
+    impl ScriptHost for TestHost {
+        fn show_popup(&mut self, text: &str, _duration: f64) {
+            self.popups.push(text.to_string());
+        }
+        fn move_player(&mut self, x: i32, y: i32) {
+            self.place_actor(&Actor::Player, (x, y), Direction::Down);
+        }
+        fn face_player(&mut self, direction: Direction) {
+            self.face_actor(&Actor::Player, direction);
+        }
+        fn set_flag(&mut self, flag: &str, value: bool) {
+            self.flags.set(flag, value);
+        }
+        fn flag(&self, flag: &str) -> bool {
+            self.flags.get(flag)
+        }
+        fn set_var(&mut self, name: &str, value: Value) {
+            self.flags.set_value(name, value);
+        }
+        fn var(&self, name: &str) -> Option<Value> {
+            self.flags.value(name).cloned()
+        }
+        fn spawn_npc(&mut self, _x: i32, _y: i32, _name: &str, _dialogue: &str) {}
+        fn spawn_portal(&mut self, _x: i32, _y: i32, _destination: (i32, i32)) {}
+        fn change_map(&mut self, _destination: Option<(i32, i32)>) {}
+        fn give_xp(&mut self, _amount: u32) {}
+        fn start_battle(&mut self, _enemies: &[String]) {}
+        fn reset_puzzle(&mut self) {}
+        fn start_quest(&mut self, _quest: &str) {}
+        fn play_cutscene(&mut self, _name: &str) {}
+        fn set_time(&mut self, _hour: u32, _minute: u32) {}
+        fn set_time_scale(&mut self, _scale: f64) {}
+    }
+
+    impl CutsceneHost for TestHost {
+        fn actor_position(&self, actor: &Actor) -> Option<(i32, i32)> {
+            let entity = self.world.get(self.actor_id(actor)?)?;
+            Some((entity.position.x, entity.position.y))
+        }
+
+        fn place_actor(&mut self, actor: &Actor, (x, y): (i32, i32), facing: Direction) -> bool {
+            let Some(id) = self.actor_id(actor) else {
+                return false;
+            };
+            if self.world.is_blocked(x, y, Some(id)) {
+                return false;
+            }
+            let entity = self.world.get_mut(id).expect("actor missing from world");
+            entity.position.x = x;
+            entity.position.y = y;
+            entity.facing = Some(facing);
+            true
+        }
+
+        fn face_actor(&mut self, actor: &Actor, direction: Direction) {
+            if let Some(entity) = self.actor_id(actor).and_then(|id| self.world.get_mut(id)) {
+                entity.facing = Some(direction);
+            }
+        }
+
+        fn go_to_map(&mut self, name: &str, position: Option<(i32, i32)>) {
+            self.maps.push((name.to_string(), position));
+        }
+    }
+
+    fn player_for(source: &str) -> CutscenePlayer {
+        let library = CutsceneLibrary::parse(source).unwrap();
+        CutscenePlayer::new(library.get("scene").unwrap().clone())
+    }
+
+    fn wren() -> Actor {
+        Actor::Npc("WREN".to_string())
+    }
+
+    #[test]
+    fn builtin_cutscenes_parse() {
+        assert!(CutsceneLibrary::builtin().get("grove_arrival").is_some());
+    }
+
+    #[test]
+    fn move_walks_a_tile_per_step_along_the_path() {
+        let mut host = TestHost::new().with_npc("WREN", 2, 0);
+        let mut cutscene = player_for("@scene\nmove WREN 4 0 4 2\n");
+
+        assert!(cutscene.update(0.1, &mut host));
+        assert_eq!(host.actor_position(&wren()), Some((2, 0)));
+
+        let expected = [(3, 0), (4, 0), (4, 1), (4, 2)];
+        for (step, position) in expected.into_iter().enumerate() {
+            let playing = cutscene.update(STEP_INTERVAL, &mut host);
+            assert_eq!(host.actor_position(&wren()), Some(position));
+            // The last tile reached ends the move, and the cutscene with it
+            assert_eq!(playing, step + 1 < expected.len());
+        }
+        assert_eq!(host.facing(&wren()), Some(Direction::Up));
+    }
+
+    #[test]
+    fn blocked_move_gives_up() {
+        let mut host = TestHost::new().with_npc("WREN", 2, 0).with_npc("ODO", 3, 0);
+        let mut cutscene = player_for("@scene\nmove WREN 5 0\nset done\n");
+        assert!(!cutscene.update(STEP_INTERVAL * 4.0, &mut host));
+        assert_eq!(host.actor_position(&wren()), Some((2, 0)));
+        assert!(host.flag("done"));
+    }
+
+    #[test]
+    fn face_wait_and_fade_take_their_time() {
+        let mut host = TestHost::new();
+        let mut cutscene = player_for("@scene\nface player left\nwait 1\nfade out 0.5\nwait 0.5\n");
+
+        assert!(cutscene.update(0.0, &mut host));
+        assert_eq!(host.facing(&Actor::Player), Some(Direction::Left));
+
+        assert!(cutscene.update(0.75, &mut host));
+        assert_eq!(cutscene.fade(), 0.0);
+        // Time left over from the wait goes towards the fade
+        assert!(cutscene.update(0.5, &mut host));
+        assert_eq!(cutscene.fade(), 0.5);
+        assert!(cutscene.update(0.25, &mut host));
+        assert_eq!(cutscene.fade(), 1.0);
+        assert!(cutscene.update(0.25, &mut host));
+        assert_eq!(cutscene.fade(), 1.0);
+
+        assert!(!cutscene.update(0.25, &mut host));
+        assert_eq!(cutscene.fade(), 0.0);
+    }
+
+    #[test]
+    fn popups_wait_for_their_duration() {
+        let mut host = TestHost::new();
+        let mut cutscene = player_for("@scene\npopup \"Hello\" 2\nset said_hello\n");
+        assert!(cutscene.update(1.5, &mut host));
+        assert_eq!(host.popups, vec!["Hello".to_string()]);
+        assert!(!host.flag("said_hello"));
+        assert!(!cutscene.update(0.5, &mut host));
+        assert!(host.flag("said_hello"));
+        assert_eq!(host.popups.len(), 1);
+    }
+
+    #[test]
+    fn set_and_clear_change_variables() {
+        let mut host = TestHost::new();
+        host.set_flag("gone", true);
+        let mut cutscene = player_for("@scene\nset seen\nset visits 3\nclear gone\n");
+        assert!(!cutscene.update(0.0, &mut host));
+        assert!(host.flag("seen"));
+        assert_eq!(host.var("visits"), Some(Value::Int(3)));
+        assert!(!host.flag("gone"));
+    }
+
+    #[test]
+    fn skip_jumps_to_how_the_cutscene_ends() {
+        let mut host = TestHost::new().with_npc("WREN", 2, 0);
+        let mut cutscene = player_for(
+            "@scene\n\
+             fade out 1\n\
+             popup \"Wait for it\" 5\n\
+             move WREN 4 0 4 3\n\
+             face player up\n\
+             map grove 9 6\n\
+             set done\n\
+             wait 10\n",
+        );
+        assert!(cutscene.update(1.5, &mut host));
+        assert_eq!(cutscene.fade(), 1.0);
+
+        cutscene.skip(&mut host);
+        assert_eq!(cutscene.fade(), 0.0);
+        assert_eq!(host.actor_position(&wren()), Some((4, 3)));
+        assert_eq!(host.facing(&wren()), Some(Direction::Up));
+        assert_eq!(host.facing(&Actor::Player), Some(Direction::Up));
+        assert_eq!(host.maps, vec![("grove".to_string(), Some((9, 6)))]);
+        assert!(host.flag("done"));
+        // The popup skipped over is not shown again
+        assert_eq!(host.popups.len(), 1);
+
+        assert!(!cutscene.update(0.0, &mut host));
+    }
+}
@@ -0,0 +1,136 @@
+//! Doors that open and close when interacted with, some of them locked
+//! until the player brings the right key or has set the right story flag.
+//! A closed door blocks the way and the view (see `Entity::set_door`).
+
+use crate::entity::{Door, EntityId, Lock, World};
+use crate::interact::{Interact, InteractHost};
+use crate::state::GameState;
+
+impl Interact for Door {
+    fn interact(&self, id: EntityId, host: &mut dyn InteractHost) -> bool {
+        host.operate_door(id);
+        true
+    }
+}
+
+/// Opens or closes a door, unlocking it first if the player has what its
+/// lock needs. A key is used up in the lock, a flag stays set. Returns what
+/// to tell the player, if anything, or why the door stayed as it was.
+pub fn operate(world: &mut World, id: EntityId, state: &mut GameState) -> Result<Option<String>, String> {
+    let Some(entity) = world.get(id) else {
+        return Ok(None);
+    };
+    let Some(mut door) = entity.door.clone() else {
+        return Ok(None);
+    };
+    let (x, y) = (entity.position.x, entity.position.y);
+
+    let mut message = None;
+    if door.open {
+        if world.find_at(x, y, |entity| entity.door.is_none()).is_some() {
+            return Err("Something is in the way.".to_string());
+        }
+        door.open = false;
+    } else if let Some(Lock { key, flag }) = door.lock.clone() {
+        message = Some(if flag.as_deref().is_some_and(|flag| state.flags.get(flag)) {
+            "The door is unlocked now.".to_string()
+        } else if let Some(key) = key.filter(|key| state.inventory.remove(key, 1)) {
+            format!("The {} turns in the lock.", state.items.name(&key))
+        } else {
+            return Err("The door is locked.".to_string());
+        });
+        door.lock = None;
+        door.open = true;
+    } else {
+        door.open = true;
+    }
+
+    if let Some(entity) = world.get_mut(id) {
+        entity.set_door(door);
+    }
+    Ok(message)
+}
+
+/// Sprite name of a door as it stands.
+pub fn sprite_kind(door: &Door) -> &'static str {
+    match door {
+        Door { open: true, .. } => "door_open",
+        Door { lock: Some(_), .. } => "door_locked",
+        Door { .. } => "door_closed",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{Entity, Prop};
+
+    fn door_world(door: Door) -> (World, EntityId) {
+        let mut world = World::new();
+        let id = world.spawn(Entity::new(0, 0).with_interactable().with_door(door));
+        (world, id)
+    }
+
+    fn locked(key: Option<&str>, flag: Option<&str>) -> Door {
+        let lock = Lock { key: key.map(str::to_string), flag: flag.map(str::to_string) };
+        Door { open: false, lock: Some(lock) }
+    }
+
+    fn door(world: &World, id: EntityId) -> Door {
+        world.get(id).and_then(|entity| entity.door.clone()).unwrap()
+    }
+
+    #[test]
+    fn doors_open_and_close_and_block_while_closed() {
+        let mut state = GameState::new();
+        let (mut world, id) = door_world(Door { open: false, lock: None });
+        assert!(world.is_blocked(0, 0, None));
+
+        assert_eq!(operate(&mut world, id, &mut state), Ok(None));
+        assert!(door(&world, id).open);
+        assert!(!world.is_blocked(0, 0, None));
+
+        assert_eq!(operate(&mut world, id, &mut state), Ok(None));
+        assert!(!door(&world, id).open);
+        assert!(world.is_blocked(0, 0, None));
+    }
+
+    #[test]
+    fn open_doors_do_not_shut_on_what_stands_in_them() {
+        let mut state = GameState::new();
+        let (mut world, id) = door_world(Door { open: true, lock: None });
+        world.spawn(Entity::new(0, 0).with_prop(Prop { kind: "barrel".to_string() }));
+        assert_eq!(operate(&mut world, id, &mut state), Err("Something is in the way.".to_string()));
+        assert!(door(&world, id).open);
+    }
+
+    #[test]
+    fn keys_are_used_up_in_their_lock() {
+        let mut state = GameState::new();
+        let (mut world, id) = door_world(locked(Some("old_key"), None));
+        assert_eq!(operate(&mut world, id, &mut state), Err("The door is locked.".to_string()));
+        assert_eq!(door(&world, id), locked(Some("old_key"), None));
+
+        state.inventory.add(&state.items, "old_key", 1);
+        let message = operate(&mut world, id, &mut state).unwrap().unwrap();
+        assert!(message.ends_with("turns in the lock."), "{}", message);
+        assert_eq!(state.inventory.count("old_key"), 0);
+        assert_eq!(door(&world, id), Door { open: true, lock: None });
+
+        // Unlocked for good
+        operate(&mut world, id, &mut state).unwrap();
+        assert_eq!(operate(&mut world, id, &mut state), Ok(None));
+    }
+
+    #[test]
+    fn flags_open_their_lock_and_stay_set() {
+        let mut state = GameState::new();
+        let (mut world, id) = door_world(locked(Some("old_key"), Some("mayor_agreed")));
+        assert!(operate(&mut world, id, &mut state).is_err());
+
+        state.flags.set("mayor_agreed", true);
+        assert_eq!(operate(&mut world, id, &mut state), Ok(Some("The door is unlocked now.".to_string())));
+        assert!(state.flags.get("mayor_agreed"));
+        assert!(door(&world, id).open);
+    }
+}
@@ -0,0 +1,235 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::combat::EnemyDb;
+
+/// Steps after a battle during which no new encounter can happen.
+const GRACE_STEPS: u32 = 4;
+/// Steps after the grace period until the table's nominal rate is reached.
+/// The chance keeps climbing to twice the rate so a fight eventually happens.
+const RAMP_STEPS: u32 = 8;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EnemyGroup {
+    pub enemies: Vec<String>,
+    pub weight: u32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EncounterTable {
+    pub id: String,
+    /// Nominal chance per step of starting a battle
+    pub rate: f64,
+    pub groups: Vec<EnemyGroup>,
+}
+
+/// Every encounter table, loaded from `assets/data/encounters.json`.
+#[derive(Debug)]
+pub struct EncounterDb {
+    tables: Vec<EncounterTable>,
+}
+
+impl EncounterDb {
+    pub fn builtin(enemies: &EnemyDb) -> Self {
+        EncounterDb::from_json(include_str!("../assets/data/encounters.json"), enemies)
+            .unwrap_or_else(|e| panic!("Invalid built-in encounter tables: {}", e))
+    }
+
+    /// Parses encounter tables, checking that every enemy they mention exists.
+    pub fn from_json(source: &str, enemies: &EnemyDb) -> Result<Self, String> {
+        let tables: Vec<EncounterTable> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, table) in tables.iter().enumerate() {
+            if !(0.0..=1.0).contains(&table.rate) {
+                return Err(format!("encounter table '{}' has a rate outside 0..1", table.id));
+            }
+            if table.groups.iter().all(|group| group.weight == 0) {
+                return Err(format!("encounter table '{}' has no weighted groups", table.id));
+            }
+            for group in &table.groups {
+                if let Some(id) = group.enemies.iter().find(|id| enemies.get(id).is_none()) {
+                    return Err(format!("encounter table '{}' uses unknown enemy '{}'", table.id, id));
+                }
+            }
+            if tables[..index].iter().any(|other| other.id == table.id) {
+                return Err(format!("encounter table '{}' is defined twice", table.id));
+            }
+        }
+
+        Ok(EncounterDb { tables })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&EncounterTable> {
+        self.tables.iter().find(|table| table.id == id)
+    }
+}
+
+/// A rectangle of tiles that uses an encounter table.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EncounterZone {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+    pub table: String,
+}
+
+impl EncounterZone {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+}
+
+/// Decides when walking around starts a battle.
+///
+/// Every roll is derived from the seed and the total number of steps taken,
+/// so the same walk with the same seed always meets the same enemies.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncounterRoller {
+    seed: u64,
+    steps_taken: u64,
+    steps_since_battle: u32,
+}
+
+impl EncounterRoller {
+    pub fn new(seed: u64) -> Self {
+        EncounterRoller {
+            seed,
+            steps_taken: 0,
+            steps_since_battle: 0,
+        }
+    }
+
+    /// Chance of a battle on the next step for a table with the given rate.
+    pub fn chance(&self, rate: f64) -> f64 {
+        let ramp = self.steps_since_battle.saturating_sub(GRACE_STEPS) as f64 / RAMP_STEPS as f64;
+        (rate * ramp.min(2.0)).min(1.0)
+    }
+
+    /// Counts a step through `table` and returns the enemy group to fight, if any.
+    pub fn step(&mut self, table: &EncounterTable) -> Option<Vec<String>> {
+        self.steps_taken += 1;
+        self.steps_since_battle += 1;
+
+        let mut rng = StdRng::seed_from_u64(self.seed ^ self.steps_taken.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        if !rng.gen_bool(self.chance(table.rate)) {
+            return None;
+        }
+
+        let total: u32 = table.groups.iter().map(|group| group.weight).sum();
+        let mut pick = rng.gen_range(0..total);
+        for group in &table.groups {
+            if pick < group.weight {
+                return Some(group.enemies.clone());
+            }
+            pick -= group.weight;
+        }
+        None
+    }
+
+    /// Restarts rate smoothing, whatever started the battle.
+    pub fn battle_started(&mut self) {
+        self.steps_since_battle = 0;
+    }
+
+    /// A seed for the battle about to start, so its rolls are reproducible too.
+    pub fn battle_seed(&self) -> u64 {
+        self.seed.wrapping_add(self.steps_taken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(rate: f64) -> EncounterTable {
+        EncounterTable {
+            id: "test".to_string(),
+            rate,
+            groups: vec![
+                EnemyGroup { enemies: vec!["rat".to_string()], weight: 3 },
+                EnemyGroup { enemies: vec!["slime".to_string(), "slime".to_string()], weight: 1 },
+                EnemyGroup { enemies: vec!["dragon".to_string()], weight: 0 },
+            ],
+        }
+    }
+
+    /// Takes `steps` steps, starting a battle whenever one comes up, and
+    /// returns the step and enemies of each.
+    fn walk(roller: &mut EncounterRoller, table: &EncounterTable, steps: u32) -> Vec<(u32, Vec<String>)> {
+        let mut battles = Vec::new();
+        for step in 1..=steps {
+            if let Some(enemies) = roller.step(table) {
+                roller.battle_started();
+                battles.push((step, enemies));
+            }
+        }
+        battles
+    }
+
+    #[test]
+    fn same_seed_and_walk_meet_the_same_battles() {
+        let table = table(0.2);
+        let first = walk(&mut EncounterRoller::new(42), &table, 500);
+        let again = walk(&mut EncounterRoller::new(42), &table, 500);
+        assert!(!first.is_empty());
+        assert_eq!(first, again);
+        assert_ne!(first, walk(&mut EncounterRoller::new(43), &table, 500));
+
+        // A roller picked up from a save carries on the same way
+        let mut roller = EncounterRoller::new(42);
+        let before = walk(&mut roller, &table, 250);
+        let mut restored: EncounterRoller = serde_json::from_str(&serde_json::to_string(&roller).unwrap()).unwrap();
+        let after: Vec<_> =
+            walk(&mut restored, &table, 250).into_iter().map(|(step, enemies)| (step + 250, enemies)).collect();
+        assert_eq!([before, after].concat(), first);
+    }
+
+    #[test]
+    fn no_battle_during_the_grace_steps() {
+        let table = table(1.0);
+        for seed in 0..100 {
+            let mut roller = EncounterRoller::new(seed);
+            let battles = walk(&mut roller, &table, 200);
+            let steps: Vec<u32> = battles.iter().map(|(step, _)| *step).collect();
+            assert!(steps[0] > GRACE_STEPS);
+            for pair in steps.windows(2) {
+                assert!(pair[1] - pair[0] > GRACE_STEPS, "seed {}: battles at {:?}", seed, pair);
+            }
+        }
+    }
+
+    #[test]
+    fn chance_ramps_up_to_twice_the_rate() {
+        let mut roller = EncounterRoller::new(0);
+        assert_eq!(roller.chance(0.1), 0.0);
+
+        let never = table(0.0);
+        for _ in 0..GRACE_STEPS + RAMP_STEPS / 2 {
+            roller.step(&never);
+        }
+        assert!((roller.chance(0.1) - 0.05).abs() < 1e-9);
+        for _ in 0..RAMP_STEPS / 2 {
+            roller.step(&never);
+        }
+        assert!((roller.chance(0.1) - 0.1).abs() < 1e-9);
+
+        for _ in 0..100 {
+            roller.step(&never);
+        }
+        assert!((roller.chance(0.1) - 0.2).abs() < 1e-9);
+        assert_eq!(roller.chance(0.8), 1.0);
+
+        roller.battle_started();
+        assert_eq!(roller.chance(0.1), 0.0);
+    }
+
+    #[test]
+    fn groups_are_picked_by_weight() {
+        let table = table(1.0);
+        let mut roller = EncounterRoller::new(7);
+        let battles = walk(&mut roller, &table, 5000);
+        let rats = battles.iter().filter(|(_, enemies)| enemies == &["rat"]).count();
+        let slimes = battles.iter().filter(|(_, enemies)| enemies == &["slime", "slime"]).count();
+        assert_eq!(rats + slimes, battles.len());
+        assert!(rats > slimes * 2, "{} rats, {} slimes", rats, slimes);
+    }
+}
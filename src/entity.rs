@@ -0,0 +1,375 @@
+use std::collections::BTreeMap;
+use graphics::types::Color;
+use serde::{Deserialize, Serialize};
+use crate::ai::Ai;
+use crate::mapgen::MapGen;
+use crate::script::Hooks;
+
+pub type EntityId = usize;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn label(self) -> &'static str {
+        match self {
+            Direction::Up => "FACING: UP",
+            Direction::Down => "FACING: DOWN",
+            Direction::Left => "FACING: LEFT",
+            Direction::Right => "FACING: RIGHT",
+        }
+    }
+
+    /// Lower-case name, as used in sprite and animation names.
+    pub fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        }
+    }
+
+    /// Grid offset of one step in this direction (Y grows upwards).
+    pub fn delta(self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, 1),
+            Direction::Down => (0, -1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Direction> {
+        match name {
+            "up" => Some(Direction::Up),
+            "down" => Some(Direction::Down),
+            "left" => Some(Direction::Left),
+            "right" => Some(Direction::Right),
+            _ => None,
+        }
+    }
+
+    pub fn from_delta(dx: i32, dy: i32) -> Option<Direction> {
+        match (dx, dy) {
+            (0, 1) => Some(Direction::Up),
+            (0, -1) => Some(Direction::Down),
+            (-1, 0) => Some(Direction::Left),
+            (1, 0) => Some(Direction::Right),
+            _ => None,
+        }
+    }
+}
+
+// Components
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Sprite {
+    /// Filled circle centered on the tile
+    Marker { color: Color },
+    /// Triangle pointing in the entity's facing direction
+    Arrow { color: Color },
+}
+
+/// Marks an entity as blocking movement onto its tile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Collider;
+
+/// Marks an entity as something the player can interact with by facing it and pressing E.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Interactable;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Portal {
+    /// Name of the map file to load, or `None` for a freshly generated map
+    pub destination_map: Option<String>,
+    pub destination_position: Option<(i32, i32)>,
+    /// How to generate the map when there is no `destination_map`; open
+    /// fields, different every time, if `None`
+    pub generator: Option<MapGen>,
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Npc {
+    pub name: String,
+    pub dialogue: String,
+}
+
+/// An item lying on the map, picked up by interacting with it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Pickup {
+    pub item: String,
+    pub count: u32,
+}
+
+/// A roaming enemy that starts a battle against `enemies` when it meets the player.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Foe {
+    pub enemies: Vec<String>,
+}
+
+/// Scenery such as a torch, drawn with the sprite or animation named `kind`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Prop {
+    pub kind: String,
+}
+
+/// A door that opens and closes when interacted with. Closed doors block the
+/// way and the view.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Door {
+    pub open: bool,
+    /// What it takes to open; `None` once unlocked, or if it never was locked
+    pub lock: Option<Lock>,
+}
+
+/// Opened by a key item in the inventory, or by a story flag being set,
+/// whichever the player has first.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Lock {
+    pub key: Option<String>,
+    pub flag: Option<String>,
+}
+
+/// Something the player can shove one tile along by walking into it, such
+/// as a crate. `home` is where it started, for when a puzzle is reset.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Pushable {
+    pub home: (i32, i32),
+}
+
+/// A working part of a puzzle. The plates and levers sharing a `link` make up
+/// a circuit, which is complete while every one of them is down, and the
+/// gates and bridges on the link follow it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Mechanism {
+    /// Pressed down by anything solid standing on it
+    Plate { link: String, pressed: bool },
+    /// Flipped by interacting with it
+    Lever { link: String, on: bool },
+    /// Opens the door of its entity while the circuit is complete
+    Gate { link: String },
+    /// Spans the water or gap under it while the circuit is complete
+    Bridge { link: String, raised: bool },
+}
+
+impl Mechanism {
+    pub fn link(&self) -> &str {
+        match self {
+            Mechanism::Plate { link, .. }
+            | Mechanism::Lever { link, .. }
+            | Mechanism::Gate { link }
+            | Mechanism::Bridge { link, .. } => link,
+        }
+    }
+}
+
+/// A doorway that takes the player elsewhere as soon as they step onto it.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Doorway {
+    /// Into a house, whose inside is generated from `seed`. `size` is the
+    /// house's outer width and height, `outside` the tile in front of the door.
+    Inside { seed: u64, size: (i32, i32), outside: (i32, i32) },
+    /// Back out of a house, to the map it was entered from
+    Outside,
+}
+
+/// A thing on the map. Behaviour comes from whichever components are present.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entity {
+    pub position: Position,
+    pub facing: Option<Direction>,
+    pub sprite: Option<Sprite>,
+    pub collider: Option<Collider>,
+    pub interactable: Option<Interactable>,
+    pub portal: Option<Portal>,
+    pub npc: Option<Npc>,
+    pub pickup: Option<Pickup>,
+    pub hooks: Option<Hooks>,
+    pub ai: Option<Ai>,
+    pub foe: Option<Foe>,
+    pub prop: Option<Prop>,
+    pub door: Option<Door>,
+    pub doorway: Option<Doorway>,
+    pub pushable: Option<Pushable>,
+    pub mechanism: Option<Mechanism>,
+}
+
+impl Entity {
+    pub fn new(x: i32, y: i32) -> Self {
+        Entity {
+            position: Position { x, y },
+            facing: None,
+            sprite: None,
+            collider: None,
+            interactable: None,
+            portal: None,
+            npc: None,
+            pickup: None,
+            hooks: None,
+            ai: None,
+            foe: None,
+            prop: None,
+            door: None,
+            doorway: None,
+            pushable: None,
+            mechanism: None,
+        }
+    }
+
+    pub fn with_facing(mut self, facing: Direction) -> Self {
+        self.facing = Some(facing);
+        self
+    }
+
+    pub fn with_sprite(mut self, sprite: Sprite) -> Self {
+        self.sprite = Some(sprite);
+        self
+    }
+
+    pub fn with_collider(mut self) -> Self {
+        self.collider = Some(Collider);
+        self
+    }
+
+    pub fn with_interactable(mut self) -> Self {
+        self.interactable = Some(Interactable);
+        self
+    }
+
+    pub fn with_portal(mut self, portal: Portal) -> Self {
+        self.portal = Some(portal);
+        self
+    }
+
+    pub fn with_npc(mut self, npc: Npc) -> Self {
+        self.npc = Some(npc);
+        self
+    }
+
+    pub fn with_pickup(mut self, pickup: Pickup) -> Self {
+        self.pickup = Some(pickup);
+        self
+    }
+
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.hooks = Some(hooks);
+        self
+    }
+
+    pub fn with_ai(mut self, ai: Ai) -> Self {
+        self.ai = Some(ai);
+        self
+    }
+
+    pub fn with_foe(mut self, foe: Foe) -> Self {
+        self.foe = Some(foe);
+        self
+    }
+
+    pub fn with_prop(mut self, prop: Prop) -> Self {
+        self.prop = Some(prop);
+        self
+    }
+
+    pub fn with_door(mut self, door: Door) -> Self {
+        self.set_door(door);
+        self
+    }
+
+    /// Changes the state of a door, which blocks the tile only while closed.
+    pub fn set_door(&mut self, door: Door) {
+        self.collider = (!door.open).then_some(Collider);
+        self.door = Some(door);
+    }
+
+    pub fn with_doorway(mut self, doorway: Doorway) -> Self {
+        self.doorway = Some(doorway);
+        self
+    }
+
+    pub fn with_pushable(mut self) -> Self {
+        self.pushable = Some(Pushable {
+            home: (self.position.x, self.position.y),
+        });
+        self
+    }
+
+    pub fn with_mechanism(mut self, mechanism: Mechanism) -> Self {
+        self.mechanism = Some(mechanism);
+        self
+    }
+
+    pub fn is_at(&self, x: i32, y: i32) -> bool {
+        self.position.x == x && self.position.y == y
+    }
+}
+
+/// Owns every entity on the current map, keyed by a stable id.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct World {
+    entities: BTreeMap<EntityId, Entity>,
+    next_id: EntityId,
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            entities: BTreeMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn spawn(&mut self, entity: Entity) -> EntityId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entities.insert(id, entity);
+        id
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        self.entities.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        self.entities.get_mut(&id)
+    }
+
+    pub fn despawn(&mut self, id: EntityId) -> Option<Entity> {
+        self.entities.remove(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.entities.iter().map(|(id, entity)| (*id, entity))
+    }
+
+    /// Removes every entity for which `keep` returns false.
+    pub fn retain(&mut self, mut keep: impl FnMut(EntityId, &Entity) -> bool) {
+        self.entities.retain(|id, entity| keep(*id, entity));
+    }
+
+    /// First entity at the given tile that satisfies `filter`.
+    pub fn find_at(&self, x: i32, y: i32, filter: impl Fn(&Entity) -> bool) -> Option<EntityId> {
+        self.iter()
+            .find(|(_, entity)| entity.is_at(x, y) && filter(entity))
+            .map(|(id, _)| id)
+    }
+
+    /// True if an entity with a collider other than `ignore` occupies the tile.
+    pub fn is_blocked(&self, x: i32, y: i32, ignore: Option<EntityId>) -> bool {
+        self.iter().any(|(id, entity)| {
+            Some(id) != ignore && entity.collider.is_some() && entity.is_at(x, y)
+        })
+    }
+}
@@ -0,0 +1,184 @@
+//! Field of view by recursive shadowcasting, and the fog of war built on it.
+
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+
+/// Transforms mapping the first octant onto each of the eight around the origin,
+/// as (xx, xy, yx, yy).
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, -1),
+    (0, 1, -1, 0),
+    (0, 1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, -1, 1, 0),
+    (1, 0, 0, 1),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Visibility {
+    /// Never seen
+    Unknown,
+    /// Seen before, but out of sight now
+    Remembered,
+    /// In sight right now
+    Visible,
+}
+
+/// Tiles visible from `origin` within `radius`. Tiles that block sight are
+/// visible themselves but hide what lies behind them.
+pub fn visible_tiles(origin: (i32, i32), radius: i32, blocks_sight: impl Fn(i32, i32) -> bool) -> HashSet<(i32, i32)> {
+    let mut caster = Caster {
+        origin,
+        radius,
+        blocks_sight,
+        visible: HashSet::from([origin]),
+    };
+    for octant in OCTANTS {
+        caster.cast(1, 1.0, 0.0, octant);
+    }
+    caster.visible
+}
+
+struct Caster<F> {
+    origin: (i32, i32),
+    radius: i32,
+    blocks_sight: F,
+    visible: HashSet<(i32, i32)>,
+}
+
+impl<F: Fn(i32, i32) -> bool> Caster<F> {
+    /// Scans one octant row by row between two slopes, recursing around
+    /// anything that blocks sight.
+    fn cast(&mut self, row: i32, mut start: f64, end: f64, octant: (i32, i32, i32, i32)) {
+        if start < end {
+            return;
+        }
+        let (xx, xy, yx, yy) = octant;
+        let mut new_start = 0.0;
+
+        for distance in row..=self.radius {
+            let dy = -distance;
+            let mut blocked = false;
+
+            for dx in -distance..=0 {
+                let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+
+                let x = self.origin.0 + dx * xx + dy * xy;
+                let y = self.origin.1 + dx * yx + dy * yy;
+                if dx * dx + dy * dy <= self.radius * self.radius {
+                    self.visible.insert((x, y));
+                }
+
+                let opaque = (self.blocks_sight)(x, y);
+                if blocked {
+                    if opaque {
+                        new_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = new_start;
+                    }
+                } else if opaque && distance < self.radius {
+                    blocked = true;
+                    self.cast(distance + 1, start, left_slope, octant);
+                    new_start = right_slope;
+                }
+            }
+
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+/// What the player has seen of the current map.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct FogOfWar {
+    explored: HashSet<(i32, i32)>,
+    #[serde(skip)]
+    visible: HashSet<(i32, i32)>,
+}
+
+impl FogOfWar {
+    /// Replaces what is in sight, remembering it from now on.
+    pub fn update(&mut self, visible: HashSet<(i32, i32)>) {
+        self.explored.extend(visible.iter().copied());
+        self.visible = visible;
+    }
+
+    pub fn visibility(&self, x: i32, y: i32) -> Visibility {
+        if self.visible.contains(&(x, y)) {
+            Visibility::Visible
+        } else if self.explored.contains(&(x, y)) {
+            Visibility::Remembered
+        } else {
+            Visibility::Unknown
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_ground_is_seen_out_to_the_radius() {
+        let visible = visible_tiles((0, 0), 4, |_, _| false);
+        for (x, y) in [(4, 0), (-4, 0), (0, 4), (0, -4), (2, 3), (-3, -2)] {
+            assert!(visible.contains(&(x, y)), "({}, {}) should be visible", x, y);
+        }
+        for (x, y) in [(5, 0), (0, -5), (3, 3), (-4, 4)] {
+            assert!(!visible.contains(&(x, y)), "({}, {}) is out of range", x, y);
+        }
+        assert!(visible.iter().all(|&(x, y)| x * x + y * y <= 16));
+    }
+
+    #[test]
+    fn walls_hide_what_is_behind_them() {
+        // A wall running north to south two tiles east of the origin
+        let visible = visible_tiles((0, 0), 8, |x, _| x == 2);
+        assert!(visible.contains(&(1, 0)));
+        assert!(visible.contains(&(2, 0)));
+        assert!(visible.contains(&(2, 1)));
+        for x in 3..=8 {
+            assert!(!visible.contains(&(x, 0)), "({}, 0) is behind the wall", x);
+            assert!(!visible.contains(&(x, 2)), "({}, 2) is behind the wall", x);
+        }
+        // The other way is clear
+        assert!(visible.contains(&(-8, 0)));
+    }
+
+    #[test]
+    fn a_single_pillar_casts_a_shadow() {
+        let visible = visible_tiles((0, 0), 8, |x, y| (x, y) == (0, 2));
+        assert!(visible.contains(&(0, 2)));
+        assert!(!visible.contains(&(0, 3)));
+        assert!(!visible.contains(&(0, 6)));
+        assert!(visible.contains(&(3, 3)));
+    }
+
+    #[test]
+    fn fog_remembers_what_went_out_of_sight() {
+        let mut fog = FogOfWar::default();
+        assert_eq!(fog.visibility(0, 0), Visibility::Unknown);
+
+        fog.update(HashSet::from([(0, 0), (1, 0)]));
+        assert_eq!(fog.visibility(0, 0), Visibility::Visible);
+        assert_eq!(fog.visibility(1, 0), Visibility::Visible);
+        assert_eq!(fog.visibility(2, 0), Visibility::Unknown);
+
+        fog.update(HashSet::from([(1, 0), (2, 0)]));
+        assert_eq!(fog.visibility(0, 0), Visibility::Remembered);
+        assert_eq!(fog.visibility(1, 0), Visibility::Visible);
+        assert_eq!(fog.visibility(2, 0), Visibility::Visible);
+        assert_eq!(fog.visibility(5, 5), Visibility::Unknown);
+    }
+}
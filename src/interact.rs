@@ -0,0 +1,177 @@
+//! What happens when the player faces something and presses E. Every
+//! component that answers to it implements `Interact`, next to the rest of
+//! its behaviour, and `interact` asks an entity's components in turn until
+//! one of them handles it. A new kind of map object brings its own impl
+//! rather than another branch in the game screen.
+
+use crate::entity::{Entity, EntityId, Npc, Pickup, Portal};
+
+/// What interacting with something can do to the game around it.
+pub trait InteractHost {
+    /// Shows a message for `duration` seconds.
+    fn tell(&mut self, text: &str, duration: f64);
+    /// Takes the player through a portal to the map it leads to.
+    fn travel(&mut self, portal: &Portal);
+    fn pick_up(&mut self, id: EntityId, pickup: &Pickup);
+    fn operate_door(&mut self, id: EntityId);
+    fn pull_lever(&mut self, id: EntityId);
+    /// Lets the quests know the player talked to somebody.
+    fn talked_to(&mut self, name: &str);
+    /// Runs a script once the interaction is over.
+    fn queue_script(&mut self, name: &str);
+}
+
+pub trait Interact {
+    /// Responds to the player interacting with entity `id`. Returns false to
+    /// leave it to the entity's other components.
+    fn interact(&self, id: EntityId, host: &mut dyn InteractHost) -> bool;
+}
+
+impl Interact for Portal {
+    fn interact(&self, _id: EntityId, host: &mut dyn InteractHost) -> bool {
+        host.travel(self);
+        true
+    }
+}
+
+impl Interact for Pickup {
+    fn interact(&self, id: EntityId, host: &mut dyn InteractHost) -> bool {
+        host.pick_up(id, self);
+        true
+    }
+}
+
+impl Interact for Npc {
+    fn interact(&self, _id: EntityId, host: &mut dyn InteractHost) -> bool {
+        host.tell(&format!("{}: {}", self.name, self.dialogue), 3.0);
+        true
+    }
+}
+
+/// Interacts with an entity: the first of its components to respond gets
+/// to, then its `on_interact` script runs.
+pub fn interact(id: EntityId, entity: &Entity, host: &mut dyn InteractHost) {
+    let on_interact = entity.hooks.as_ref().and_then(|hooks| hooks.on_interact.as_deref());
+
+    // NPCs with a script leave the talking to it
+    let components: [Option<&dyn Interact>; 5] = [
+        entity.portal.as_ref().map(|portal| portal as &dyn Interact),
+        entity.pickup.as_ref().map(|pickup| pickup as &dyn Interact),
+        entity.mechanism.as_ref().map(|mechanism| mechanism as &dyn Interact),
+        entity.door.as_ref().map(|door| door as &dyn Interact),
+        entity.npc.as_ref().filter(|_| on_interact.is_none()).map(|npc| npc as &dyn Interact),
+    ];
+    for component in components.into_iter().flatten() {
+        if component.interact(id, host) {
+            break;
+        }
+    }
+
+    // Quests hear about the talk before the NPC's script runs, so the
+    // script can already tell whether it finished one
+    if let Some(npc) = &entity.npc {
+        host.talked_to(&npc.name);
+    }
+    if let Some(name) = on_interact {
+        host.queue_script(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::{Door, Mechanism};
+    use crate::script::Hooks;
+
+    /// Writes down everything asked of it.
+    #[derive(Default)]
+    struct LogHost {
+        log: Vec<String>,
+    }
+
+    impl InteractHost for LogHost {
+        fn tell(&mut self, text: &str, _duration: f64) {
+            self.log.push(format!("tell {}", text));
+        }
+
+        fn travel(&mut self, portal: &Portal) {
+            self.log.push(format!("travel {:?}", portal.destination_map));
+        }
+
+        fn pick_up(&mut self, id: EntityId, pickup: &Pickup) {
+            self.log.push(format!("pick up {} {}", id, pickup.item));
+        }
+
+        fn operate_door(&mut self, id: EntityId) {
+            self.log.push(format!("door {}", id));
+        }
+
+        fn pull_lever(&mut self, id: EntityId) {
+            self.log.push(format!("lever {}", id));
+        }
+
+        fn talked_to(&mut self, name: &str) {
+            self.log.push(format!("talked {}", name));
+        }
+
+        fn queue_script(&mut self, name: &str) {
+            self.log.push(format!("script {}", name));
+        }
+    }
+
+    fn log(entity: &Entity) -> Vec<String> {
+        let mut host = LogHost::default();
+        interact(7, entity, &mut host);
+        host.log
+    }
+
+    fn npc(name: &str) -> Npc {
+        Npc { name: name.to_string(), dialogue: "Hello.".to_string() }
+    }
+
+    fn with_script(entity: Entity, script: &str) -> Entity {
+        entity.with_hooks(Hooks { on_interact: Some(script.to_string()), on_step: None })
+    }
+
+    #[test]
+    fn each_component_does_its_own_thing() {
+        let portal = Portal { destination_map: Some("grove".to_string()), destination_position: None, generator: None };
+        assert_eq!(log(&Entity::new(0, 0).with_portal(portal)), ["travel Some(\"grove\")"]);
+
+        let pickup = Pickup { item: "apple".to_string(), count: 2 };
+        assert_eq!(log(&Entity::new(0, 0).with_pickup(pickup)), ["pick up 7 apple"]);
+
+        let door = Door { open: false, lock: None };
+        assert_eq!(log(&Entity::new(0, 0).with_door(door)), ["door 7"]);
+
+        let lever = Mechanism::Lever { link: "a".to_string(), on: false };
+        assert_eq!(log(&Entity::new(0, 0).with_mechanism(lever)), ["lever 7"]);
+
+        assert_eq!(log(&Entity::new(0, 0).with_npc(npc("ODO"))), ["tell ODO: Hello.", "talked ODO"]);
+        assert!(log(&Entity::new(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn gates_answer_before_the_door_they_are() {
+        let gate = Entity::new(0, 0)
+            .with_door(Door { open: false, lock: None })
+            .with_mechanism(Mechanism::Gate { link: "a".to_string() });
+        assert_eq!(log(&gate), ["tell It won't open by hand."]);
+
+        // Plates and bridges don't answer, so a door among them still would
+        let bridge = Entity::new(0, 0)
+            .with_door(Door { open: true, lock: None })
+            .with_mechanism(Mechanism::Bridge { link: "a".to_string(), raised: false });
+        assert_eq!(log(&bridge), ["door 7"]);
+    }
+
+    #[test]
+    fn scripts_run_last_and_speak_for_npcs() {
+        let scripted = with_script(Entity::new(0, 0).with_npc(npc("ODO")), "odo_talk");
+        assert_eq!(log(&scripted), ["talked ODO", "script odo_talk"]);
+
+        let pickup = Pickup { item: "herb".to_string(), count: 1 };
+        let scripted = with_script(Entity::new(0, 0).with_pickup(pickup), "herb_found");
+        assert_eq!(log(&scripted), ["pick up 7 herb", "script herb_found"]);
+    }
+}
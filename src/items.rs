@@ -0,0 +1,154 @@
+use serde::{Deserialize, Serialize};
+
+/// Number of distinct stacks the player can carry.
+pub const INVENTORY_SLOTS: usize = 20;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemCategory {
+    Consumable,
+    Material,
+    Key,
+}
+
+impl ItemCategory {
+    pub fn label(self) -> &'static str {
+        match self {
+            ItemCategory::Consumable => "CONSUMABLE",
+            ItemCategory::Material => "MATERIAL",
+            ItemCategory::Key => "KEY ITEM",
+        }
+    }
+}
+
+/// What using a consumable does to the player.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+pub struct ItemEffect {
+    #[serde(default)]
+    pub heal_hp: i32,
+    #[serde(default)]
+    pub restore_mp: i32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub stack_size: u32,
+    pub category: ItemCategory,
+    #[serde(default)]
+    pub effect: Option<ItemEffect>,
+}
+
+/// Every item definition the game knows about, loaded from `assets/data/items.json`.
+#[derive(Debug)]
+pub struct ItemDb {
+    items: Vec<ItemDef>,
+}
+
+impl ItemDb {
+    pub fn builtin() -> Self {
+        ItemDb::from_json(include_str!("../assets/data/items.json"))
+            .unwrap_or_else(|e| panic!("Invalid built-in items: {}", e))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let items: Vec<ItemDef> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, item) in items.iter().enumerate() {
+            if item.stack_size == 0 {
+                return Err(format!("item '{}' has a stack size of 0", item.id));
+            }
+            if items[..index].iter().any(|other| other.id == item.id) {
+                return Err(format!("item '{}' is defined twice", item.id));
+            }
+        }
+
+        Ok(ItemDb { items })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&ItemDef> {
+        self.items.iter().find(|item| item.id == id)
+    }
+
+    /// Display name for an item id, falling back to the id for unknown items.
+    pub fn name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.get(id).map(|item| item.name.as_str()).unwrap_or(id)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    stacks: Vec<ItemStack>,
+}
+
+impl Inventory {
+    pub fn stacks(&self) -> &[ItemStack] {
+        &self.stacks
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// Adds items, topping up existing stacks before opening new slots.
+    /// Returns how many did not fit.
+    pub fn add(&mut self, items: &ItemDb, item: &str, count: u32) -> u32 {
+        let stack_size = items.get(item).map(|def| def.stack_size).unwrap_or(1);
+        let mut remaining = count;
+
+        for stack in self.stacks.iter_mut().filter(|stack| stack.item == item) {
+            let space = stack_size.saturating_sub(stack.count);
+            let moved = space.min(remaining);
+            stack.count += moved;
+            remaining -= moved;
+        }
+
+        while remaining > 0 && self.stacks.len() < INVENTORY_SLOTS {
+            let moved = stack_size.min(remaining);
+            self.stacks.push(ItemStack { item: item.to_string(), count: moved });
+            remaining -= moved;
+        }
+
+        remaining
+    }
+
+    /// Removes `count` of an item across stacks. Removes nothing if there are not enough.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        if self.count(item) < count {
+            return false;
+        }
+
+        let mut remaining = count;
+        for stack in self.stacks.iter_mut().rev().filter(|stack| stack.item == item) {
+            let taken = stack.count.min(remaining);
+            stack.count -= taken;
+            remaining -= taken;
+        }
+        self.stacks.retain(|stack| stack.count > 0);
+        true
+    }
+
+    /// Takes up to `count` items out of one slot.
+    pub fn take_from_slot(&mut self, slot: usize, count: u32) -> Option<ItemStack> {
+        let stack = self.stacks.get_mut(slot)?;
+        let taken = stack.count.min(count);
+        stack.count -= taken;
+        let item = stack.item.clone();
+        if stack.count == 0 {
+            self.stacks.remove(slot);
+        }
+        Some(ItemStack { item, count: taken })
+    }
+}
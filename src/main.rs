@@ -9,11 +9,40 @@ use piston::event_loop::{EventSettings, Events};
 use piston::input::*;
 use piston::window::WindowSettings;
 use std::env;
-use std::path::PathBuf;
+use std::rc::Rc;
 
+mod ai;
+mod animation;
+mod clock;
+mod combat;
+mod cutscene;
+mod doors;
+mod encounters;
+mod entity;
+mod fov;
+mod interact;
+mod items;
+mod map;
+mod mapgen;
+mod mechanisms;
+mod pathfinding;
+mod quests;
+mod save;
 mod screens;
+mod script;
+mod sprites;
+mod state;
+mod stats;
+mod tiled;
+mod triggers;
 use screens::{ScreenManager, ScreenState};
+use screens::battle::BattleScreen;
+use screens::character::CharacterScreen;
 use screens::game::GameScreen;
+use screens::inventory::InventoryScreen;
+use screens::quests::QuestLogScreen;
+use sprites::{SpriteData, SpriteSheet};
+use state::GameState;
 
 fn main() {
     // Initialize OpenGL
@@ -49,9 +78,18 @@ fn main() {
         TextureSettings::new(),
     ).expect("Could not load font.");
 
-    // Initialize the screen manager and add the game screen
+    // Load sprite atlases, falling back to plain shapes for any that are missing
+    let sprites = Rc::new(SpriteSheet::load(&SpriteData::builtin(), &exe_dir.join("assets")));
+
+    // Initialize the screen manager and add the game screens, which share the game state
+    let state = GameState::shared();
     let mut screen_manager = ScreenManager::new();
-    screen_manager.add_screen(ScreenState::Game, Box::new(GameScreen::new()));
+    let maps_dir = exe_dir.join("assets").join("maps");
+    screen_manager.add_screen(ScreenState::Game, Box::new(GameScreen::new(state.clone(), sprites, maps_dir)));
+    screen_manager.add_screen(ScreenState::Inventory, Box::new(InventoryScreen::new(state.clone())));
+    screen_manager.add_screen(ScreenState::Character, Box::new(CharacterScreen::new(state.clone())));
+    screen_manager.add_screen(ScreenState::Quests, Box::new(QuestLogScreen::new(state.clone())));
+    screen_manager.add_screen(ScreenState::Battle, Box::new(BattleScreen::new(state)));
 
     // Create an event loop
     let mut events = Events::new(EventSettings::new());
@@ -61,7 +99,7 @@ fn main() {
         // Handle rendering
         if let Some(args) = e.render_args() {
             gl.draw(args.viewport(), |c, g| {
-                screen_manager.draw(&c, g, &mut glyphs, [args.window_size[0] as f64, args.window_size[1] as f64]);
+                screen_manager.draw(&c, g, &mut glyphs, args.window_size);
             });
         }
 
@@ -77,9 +115,23 @@ fn main() {
             }));
         }
 
+        // Button releases, for holding the mouse down to paint in the editor
+        if let Some(input) = e.release_args() {
+            screen_manager.handle_input(&Input::Button(ButtonArgs {
+                state: ButtonState::Release,
+                button: input,
+                scancode: None,
+            }));
+        }
+
         // Handle mouse movement inputs
         if let Some(pos) = e.mouse_cursor_args() {
             screen_manager.handle_input(&Input::Move(Motion::MouseCursor(pos)));
         }
+
+        // Handle mouse wheel inputs
+        if let Some(scroll) = e.mouse_scroll_args() {
+            screen_manager.handle_input(&Input::Move(Motion::MouseScroll(scroll)));
+        }
     }
 }
\ No newline at end of file
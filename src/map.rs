@@ -0,0 +1,184 @@
+//! The tile map: stacked layers of tiles, plus a collision layer saying which
+//! tiles can't be walked on whatever is drawn there.
+
+use serde::{Deserialize, Serialize};
+
+// Tile values, shared by every layer
+pub const EMPTY: u8 = 0;
+pub const FLOOR: u8 = 1;
+pub const WALL: u8 = 2;
+pub const TALL_GRASS: u8 = 3;
+pub const WATER: u8 = 4;
+pub const FLOWERS: u8 = 5;
+/// Tree trunk; its crown is a `CANOPY` on the overhead layer above it
+pub const TREE: u8 = 6;
+pub const ROOF: u8 = 7;
+pub const CANOPY: u8 = 8;
+/// Packed earth running between the houses of a town
+pub const ROAD: u8 = 9;
+
+/// Sprite (or animation) name of a tile, `None` for `EMPTY`.
+pub fn tile_name(tile: u8) -> Option<&'static str> {
+    match tile {
+        FLOOR => Some("floor"),
+        WALL => Some("wall"),
+        TALL_GRASS => Some("tall_grass"),
+        WATER => Some("water"),
+        FLOWERS => Some("flowers"),
+        TREE => Some("tree"),
+        ROOF => Some("roof"),
+        CANOPY => Some("canopy"),
+        ROAD => Some("road"),
+        _ => None,
+    }
+}
+
+/// The tile with the given name, the inverse of `tile_name`.
+pub fn tile_from_name(name: &str) -> Option<u8> {
+    (FLOOR..=ROAD).find(|&tile| tile_name(tile) == Some(name))
+}
+
+/// Whether a tile on the ground or decoration layer keeps walkers off.
+pub fn is_solid_tile(tile: u8) -> bool {
+    matches!(tile, WALL | WATER | TREE)
+}
+
+/// Tile layers, bottom to top. Entities stand between decoration and overhead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layer {
+    /// Floor, walls, water and grass; always filled
+    Ground,
+    /// Things lying on the ground, such as flowers and tree trunks
+    Decoration,
+    /// Drawn above entities, such as roofs and tree canopies
+    Overhead,
+}
+
+impl Layer {
+    fn index(self) -> usize {
+        match self {
+            Layer::Ground => 0,
+            Layer::Decoration => 1,
+            Layer::Overhead => 2,
+        }
+    }
+}
+
+/// A rectangle of tiles in grid coordinates, Y growing upwards. Tiles are
+/// stored row by row from the top.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TileMap {
+    left: i32,
+    top: i32,
+    width: usize,
+    height: usize,
+    layers: [Vec<u8>; 3],
+    collision: Vec<bool>,
+}
+
+impl TileMap {
+    /// A map of open floor whose top-left tile is at (`left`, `top`).
+    pub fn new(left: i32, top: i32, width: usize, height: usize) -> Self {
+        let size = width * height;
+        TileMap {
+            left,
+            top,
+            width,
+            height,
+            layers: [vec![FLOOR; size], vec![EMPTY; size], vec![EMPTY; size]],
+            collision: vec![false; size],
+        }
+    }
+
+    pub fn left(&self) -> i32 {
+        self.left
+    }
+
+    pub fn top(&self) -> i32 {
+        self.top
+    }
+
+    pub fn right(&self) -> i32 {
+        self.left + self.width as i32 - 1
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.top - self.height as i32 + 1
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_some()
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        let column = usize::try_from(x - self.left).ok().filter(|&column| column < self.width)?;
+        let row = usize::try_from(self.top - y).ok().filter(|&row| row < self.height)?;
+        Some(row * self.width + column)
+    }
+
+    /// Every tile position, row by row from the top.
+    pub fn positions(&self) -> impl Iterator<Item = (i32, i32)> {
+        let (left, top, width) = (self.left, self.top, self.width as i32);
+        (0..self.height as i32).flat_map(move |row| (0..width).map(move |column| (left + column, top - row)))
+    }
+
+    /// The tile on `layer`, `EMPTY` off the map.
+    pub fn tile(&self, layer: Layer, x: i32, y: i32) -> u8 {
+        self.index(x, y).map_or(EMPTY, |index| self.layers[layer.index()][index])
+    }
+
+    pub fn ground(&self, x: i32, y: i32) -> u8 {
+        self.tile(Layer::Ground, x, y)
+    }
+
+    /// Sets a tile, ignoring positions off the map.
+    pub fn set_tile(&mut self, layer: Layer, x: i32, y: i32, tile: u8) {
+        if let Some(index) = self.index(x, y) {
+            self.layers[layer.index()][index] = tile;
+        }
+    }
+
+    /// Whether the collision layer blocks the tile. Everything off the map is blocked.
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).is_none_or(|index| self.collision[index])
+    }
+
+    pub fn set_solid(&mut self, x: i32, y: i32, solid: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.collision[index] = solid;
+        }
+    }
+
+    /// Whether the tile hides what lies behind it. Everything off the map does.
+    pub fn blocks_sight(&self, x: i32, y: i32) -> bool {
+        !self.contains(x, y) || self.ground(x, y) == WALL
+    }
+
+    /// Fills the collision layer from the ground and decoration tiles.
+    pub fn derive_collision(&mut self) {
+        for index in 0..self.collision.len() {
+            self.derive_collision_at(index);
+        }
+    }
+
+    /// Like `derive_collision`, for a single tile.
+    pub fn update_collision(&mut self, x: i32, y: i32) {
+        if let Some(index) = self.index(x, y) {
+            self.derive_collision_at(index);
+        }
+    }
+
+    fn derive_collision_at(&mut self, index: usize) {
+        let ground = self.layers[Layer::Ground.index()][index];
+        let decoration = self.layers[Layer::Decoration.index()][index];
+        self.collision[index] = is_solid_tile(ground) || is_solid_tile(decoration);
+    }
+}
@@ -0,0 +1,663 @@
+//! Procedural maps. Each way of laying out a map is a `Generator`, and a
+//! `MapGen` names one along with the seed it runs with, so the same `MapGen`
+//! always makes the same map.
+
+use std::collections::HashSet;
+use std::ops::Range;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::map::{Layer, TileMap, CANOPY, EMPTY, FLOOR, FLOWERS, ROAD, ROOF, TALL_GRASS, TREE, WALL, WATER};
+use crate::pathfinding;
+
+// Generated maps span these grid coordinates on both axes
+const GRID_MIN: i32 = -20;
+const GRID_MAX: i32 = 20;
+const MAP_SIZE: usize = (GRID_MAX - GRID_MIN + 1) as usize;
+
+// The drunkard's walk gives up after this many steps, however little it dug
+const MAX_WALK_STEPS: u32 = 20_000;
+
+// What a house can be furnished with, each the sprite of its prop
+const FURNITURE: [&str; 4] = ["bed", "table", "shelf", "barrel"];
+
+// Houses stamped on every open field
+const FIELD_HOUSES: [House; 2] = [
+    House { min: (-10, 5), max: (-5, 10), entrance: (-7, 5) },
+    House { min: (5, -10), max: (10, -5), entrance: (7, -10) },
+];
+
+/// A walled house under a roof, with a gap in the wall for its door.
+#[derive(Clone, Copy, Debug)]
+pub struct House {
+    /// Corners of the outer walls
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+    pub entrance: (i32, i32),
+}
+
+impl House {
+    /// Tile just outside the entrance.
+    pub fn outside(&self) -> (i32, i32) {
+        let (x, y) = self.entrance;
+        if y == self.min.1 {
+            (x, y - 1)
+        } else if y == self.max.1 {
+            (x, y + 1)
+        } else if x == self.min.0 {
+            (x - 1, y)
+        } else {
+            (x + 1, y)
+        }
+    }
+
+    /// Whether a tile is under the roof, walls included.
+    pub fn covers(&self, x: i32, y: i32) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+
+    fn stamp(&self, map: &mut TileMap) {
+        for y in self.min.1..=self.max.1 {
+            for x in self.min.0..=self.max.0 {
+                // A roof over the whole house, hiding what is inside
+                map.set_tile(Layer::Overhead, x, y, ROOF);
+
+                // Walls on the edges, except where the door goes
+                let edge = y == self.min.1 || y == self.max.1 || x == self.min.0 || x == self.max.0;
+                if edge && (x, y) != self.entrance {
+                    map.set_tile(Layer::Ground, x, y, WALL);
+                }
+            }
+        }
+    }
+}
+
+/// A generated map and what else the game needs to know to fill it.
+pub struct Layout {
+    pub map: TileMap,
+    /// Where the player arrives. Everything walkable can be reached from here.
+    pub spawn: (i32, i32),
+    pub houses: Vec<House>,
+}
+
+/// A way of laying out a map. Everything random comes from `rng`, so a
+/// generator given the same seed twice makes the same map.
+pub trait Generator {
+    fn generate(&self, rng: &mut StdRng) -> Layout;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    /// Open land with ponds, grass, trees and a couple of houses
+    #[default]
+    Fields,
+    /// Rooms joined by corridors, split up with a BSP tree
+    Rooms,
+    /// Cellular automaton caves
+    Caves,
+    /// Winding passages dug by a drunkard's walk
+    Tunnels,
+    /// Houses along a grid of roads, their doors facing the road
+    Town,
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 5] = [
+        GeneratorKind::Fields,
+        GeneratorKind::Rooms,
+        GeneratorKind::Caves,
+        GeneratorKind::Tunnels,
+        GeneratorKind::Town,
+    ];
+
+    /// Name of the generator in map files.
+    pub fn name(self) -> &'static str {
+        match self {
+            GeneratorKind::Fields => "fields",
+            GeneratorKind::Rooms => "rooms",
+            GeneratorKind::Caves => "caves",
+            GeneratorKind::Tunnels => "tunnels",
+            GeneratorKind::Town => "town",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        GeneratorKind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    pub fn generator(self) -> Box<dyn Generator> {
+        match self {
+            GeneratorKind::Fields => Box::new(Fields),
+            GeneratorKind::Rooms => Box::new(Rooms { min_leaf: 7 }),
+            GeneratorKind::Caves => Box::new(Caves { fill: 0.45, smoothing: 5 }),
+            GeneratorKind::Tunnels => Box::new(DrunkardsWalk { open: 0.4 }),
+            GeneratorKind::Town => Box::new(Town { block: 12 }),
+        }
+    }
+}
+
+/// Which generator makes a map, and with what seed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct MapGen {
+    pub kind: GeneratorKind,
+    /// `None` makes a different map every time
+    pub seed: Option<u64>,
+}
+
+impl MapGen {
+    /// Runs the generator. Also returns the random number generator it used,
+    /// so what gets placed on the map can follow from the same seed.
+    pub fn generate(&self) -> (Layout, StdRng) {
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let layout = self.kind.generator().generate(&mut rng);
+        (layout, rng)
+    }
+}
+
+pub struct Fields;
+
+impl Generator for Fields {
+    fn generate(&self, rng: &mut StdRng) -> Layout {
+        let mut map = walled_map(FLOOR);
+        for house in FIELD_HOUSES {
+            house.stamp(&mut map);
+        }
+
+        // Randomly add obstacles, leaving the houses be
+        let obstacle_count = rng.gen_range(50..150);
+        for _ in 0..obstacle_count {
+            let (x, y) = random_tile(rng);
+            if is_open_floor(&map, x, y) {
+                map.set_tile(Layer::Ground, x, y, WALL);
+            }
+        }
+
+        // A pond or two, and patches of tall grass where random battles happen
+        let pond_count = rng.gen_range(1..3);
+        add_patches(&mut map, rng, WATER, pond_count, 1..4, 1.0);
+        let patch_count = rng.gen_range(3..7);
+        add_patches(&mut map, rng, TALL_GRASS, patch_count, 2..5, 0.8);
+
+        let tree_count = rng.gen_range(8..16);
+        plant_trees(&mut map, rng, tree_count);
+
+        // Keep the doorsteps clear, or the houses end up sealed off
+        for house in FIELD_HOUSES {
+            let (x, y) = house.outside();
+            map.set_tile(Layer::Ground, x, y, FLOOR);
+            map.set_tile(Layer::Decoration, x, y, EMPTY);
+        }
+
+        let spawn = (0, 0);
+        finish(&mut map, spawn);
+        let flower_count = rng.gen_range(20..40);
+        scatter_flowers(&mut map, rng, flower_count);
+
+        Layout { map, spawn, houses: FIELD_HOUSES.to_vec() }
+    }
+}
+
+pub struct Rooms {
+    /// Smallest piece the map is split into, each holding one room
+    pub min_leaf: i32,
+}
+
+impl Rooms {
+    /// Splits an area in two until the pieces are too small to split again,
+    /// puts a room in every piece and joins the two halves of each split
+    /// with a corridor. Returns the middle of one of the rooms in the area.
+    fn split(&self, map: &mut TileMap, rng: &mut StdRng, min: (i32, i32), max: (i32, i32)) -> (i32, i32) {
+        let (width, height) = (max.0 - min.0 + 1, max.1 - min.1 + 1);
+        let split_across = match (width >= self.min_leaf * 2, height >= self.min_leaf * 2) {
+            (false, false) => return carve_room(map, rng, min, max),
+            (true, true) if width == height => rng.gen_bool(0.5),
+            (true, true) => width > height,
+            (across, _) => across,
+        };
+
+        let (first, second) = if split_across {
+            let x = rng.gen_range(min.0 + self.min_leaf..=max.0 - self.min_leaf + 1);
+            (self.split(map, rng, min, (x - 1, max.1)), self.split(map, rng, (x, min.1), max))
+        } else {
+            let y = rng.gen_range(min.1 + self.min_leaf..=max.1 - self.min_leaf + 1);
+            (self.split(map, rng, min, (max.0, y - 1)), self.split(map, rng, (min.0, y), max))
+        };
+        carve_corridor(map, rng, first, second);
+
+        if rng.gen_bool(0.5) { first } else { second }
+    }
+}
+
+impl Generator for Rooms {
+    fn generate(&self, rng: &mut StdRng) -> Layout {
+        let mut map = walled_map(WALL);
+        let spawn = self.split(&mut map, rng, (GRID_MIN + 1, GRID_MIN + 1), (GRID_MAX - 1, GRID_MAX - 1));
+
+        // Overgrown corners, to give the rooms something to hide in
+        let patch_count = rng.gen_range(2..5);
+        add_patches(&mut map, rng, TALL_GRASS, patch_count, 1..3, 0.6);
+
+        finish(&mut map, spawn);
+        Layout { map, spawn, houses: Vec::new() }
+    }
+}
+
+pub struct Caves {
+    /// Chance of each tile starting out as rock
+    pub fill: f64,
+    /// Rounds of smoothing the rock into cave walls
+    pub smoothing: u32,
+}
+
+impl Generator for Caves {
+    fn generate(&self, rng: &mut StdRng) -> Layout {
+        let mut map = walled_map(FLOOR);
+        let inside: Vec<(i32, i32)> = map.positions().filter(|&(x, y)| is_inside(x, y)).collect();
+        for &(x, y) in &inside {
+            if rng.gen_bool(self.fill) {
+                map.set_tile(Layer::Ground, x, y, WALL);
+            }
+        }
+
+        // A tile turns to rock when most of the tiles around it are rock
+        for _ in 0..self.smoothing {
+            let rock: Vec<bool> = inside
+                .iter()
+                .map(|&(x, y)| {
+                    let neighbours = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)));
+                    neighbours.filter(|&(x, y)| map.ground(x, y) != FLOOR).count() >= 5
+                })
+                .collect();
+            for (&(x, y), rock) in inside.iter().zip(rock) {
+                map.set_tile(Layer::Ground, x, y, if rock { WALL } else { FLOOR });
+            }
+        }
+
+        let pool_count = rng.gen_range(2..4);
+        add_patches(&mut map, rng, WATER, pool_count, 1..3, 1.0);
+
+        // Start in the biggest cave; the others get sealed off
+        let spawn = middle_of_largest_area(&map);
+        finish(&mut map, spawn);
+        Layout { map, spawn, houses: Vec::new() }
+    }
+}
+
+pub struct DrunkardsWalk {
+    /// Share of the map dug out before the walk stops
+    pub open: f64,
+}
+
+impl Generator for DrunkardsWalk {
+    fn generate(&self, rng: &mut StdRng) -> Layout {
+        let mut map = walled_map(WALL);
+        let target = ((MAP_SIZE - 2).pow(2) as f64 * self.open) as usize;
+
+        let spawn = (0, 0);
+        let (mut x, mut y) = spawn;
+        let mut dug = 0;
+        for _ in 0..MAX_WALK_STEPS {
+            if map.ground(x, y) == WALL {
+                map.set_tile(Layer::Ground, x, y, FLOOR);
+                dug += 1;
+                if dug >= target {
+                    break;
+                }
+            }
+            let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0..4)];
+            x = (x + dx).clamp(GRID_MIN + 1, GRID_MAX - 1);
+            y = (y + dy).clamp(GRID_MIN + 1, GRID_MAX - 1);
+        }
+
+        let patch_count = rng.gen_range(3..6);
+        add_patches(&mut map, rng, TALL_GRASS, patch_count, 2..4, 0.7);
+
+        finish(&mut map, spawn);
+        Layout { map, spawn, houses: Vec::new() }
+    }
+}
+
+pub struct Town {
+    /// Distance between parallel roads, give or take a tile
+    pub block: i32,
+}
+
+impl Town {
+    /// Whether a house and the tiles around it are free for building.
+    fn has_room_for(&self, map: &TileMap, house: &House) -> bool {
+        (house.min.1 - 1..=house.max.1 + 1).all(|y| {
+            (house.min.0 - 1..=house.max.0 + 1).all(|x| is_inside(x, y) && is_open_floor(map, x, y))
+        })
+    }
+}
+
+impl Generator for Town {
+    fn generate(&self, rng: &mut StdRng) -> Layout {
+        let mut map = walled_map(FLOOR);
+
+        // Streets run east to west and avenues north to south, the first a little way in from the edge
+        let mut streets = Vec::new();
+        let mut y = GRID_MAX - rng.gen_range(5..8);
+        while y > GRID_MIN + 4 {
+            streets.push(y);
+            y -= self.block + rng.gen_range(-1..=1);
+        }
+        let mut avenues = Vec::new();
+        let mut x = GRID_MIN + rng.gen_range(5..8);
+        while x < GRID_MAX - 4 {
+            avenues.push(x);
+            x += self.block + rng.gen_range(-1..=1);
+        }
+        for (x, y) in map.positions().collect::<Vec<_>>() {
+            if is_inside(x, y) && (streets.contains(&y) || avenues.contains(&x)) {
+                map.set_tile(Layer::Ground, x, y, ROAD);
+            }
+        }
+
+        // Rows of houses on both sides of every street, set back a tile
+        // with a path from the door to the street
+        let mut houses = Vec::new();
+        for &street in &streets {
+            for side in [1, -1] {
+                let mut x = GRID_MIN + 2;
+                while x < GRID_MAX - 2 {
+                    let width = rng.gen_range(5..=7);
+                    let depth = rng.gen_range(4..=5);
+                    let (front, back) = (street + side * 2, street + side * (depth + 1));
+                    let house = House {
+                        min: (x, front.min(back)),
+                        max: (x + width - 1, front.max(back)),
+                        entrance: (rng.gen_range(x + 1..x + width - 1), front),
+                    };
+
+                    if rng.gen_bool(0.8) && self.has_room_for(&map, &house) {
+                        house.stamp(&mut map);
+                        map.set_tile(Layer::Ground, house.entrance.0, street + side, ROAD);
+                        houses.push(house);
+                        x += width + rng.gen_range(1..3);
+                    } else {
+                        x += 2;
+                    }
+                }
+            }
+        }
+
+        let tree_count = rng.gen_range(10..20);
+        plant_trees(&mut map, rng, tree_count);
+
+        // Arrive on the road closest to the middle of town
+        let spawn = map
+            .positions()
+            .filter(|&(x, y)| map.ground(x, y) == ROAD)
+            .min_by_key(|&(x, y)| x * x + y * y)
+            .unwrap_or((0, 0));
+        finish(&mut map, spawn);
+        let flower_count = rng.gen_range(20..40);
+        scatter_flowers(&mut map, rng, flower_count);
+
+        Layout { map, spawn, houses }
+    }
+}
+
+/// The inside of a house: a single room, roomier than the house looks from
+/// outside, with furniture along the walls and the way out in the bottom wall.
+pub struct Interior {
+    pub layout: Layout,
+    /// Sprite name of each piece of furniture, by position
+    pub furniture: Vec<((i32, i32), &'static str)>,
+    /// Gap in the wall that leads back outside
+    pub exit: (i32, i32),
+    /// Floor clear of the walls and furniture, where people can stand
+    pub floor: Vec<(i32, i32)>,
+}
+
+/// Lays out the inside of a house whose outer walls measure `size`. The
+/// room's bottom-left corner sits at (0, 0).
+pub fn interior(size: (i32, i32), rng: &mut StdRng) -> Interior {
+    let (width, height) = (size.0 + 4, size.1 + 3);
+    let mut map = TileMap::new(0, height - 1, width as usize, height as usize);
+    let is_wall = |x: i32, y: i32| x == 0 || y == 0 || x == width - 1 || y == height - 1;
+    for (x, y) in map.positions().collect::<Vec<_>>() {
+        if is_wall(x, y) {
+            map.set_tile(Layer::Ground, x, y, WALL);
+        }
+    }
+    let exit = (width / 2, 0);
+    map.set_tile(Layer::Ground, exit.0, exit.1, FLOOR);
+    map.derive_collision();
+
+    // Furniture stands against the walls, clear of the door; everything
+    // further in stays free so the whole room can be walked
+    let by_wall = |x: i32, y: i32| {
+        !is_wall(x, y) && (is_wall(x - 1, y) || is_wall(x + 1, y) || is_wall(x, y - 1) || is_wall(x, y + 1))
+    };
+    let mut spots: Vec<(i32, i32)> = map
+        .positions()
+        .filter(|&(x, y)| by_wall(x, y) && (x - exit.0).abs() > 1)
+        .collect();
+    spots.shuffle(rng);
+    let count = rng.gen_range(3..=6).min(spots.len());
+    let furniture = spots[..count]
+        .iter()
+        .map(|&position| (position, FURNITURE[rng.gen_range(0..FURNITURE.len())]))
+        .collect();
+    let floor = map.positions().filter(|&(x, y)| !is_wall(x, y) && !by_wall(x, y)).collect();
+
+    let spawn = (exit.0, exit.1 + 1);
+    Interior {
+        layout: Layout { map, spawn, houses: Vec::new() },
+        furniture,
+        exit,
+        floor,
+    }
+}
+
+/// A map filled with `fill` inside a ring of walls.
+fn walled_map(fill: u8) -> TileMap {
+    let mut map = TileMap::new(GRID_MIN, GRID_MAX, MAP_SIZE, MAP_SIZE);
+    for (x, y) in map.positions().collect::<Vec<_>>() {
+        map.set_tile(Layer::Ground, x, y, if is_inside(x, y) { fill } else { WALL });
+    }
+    map
+}
+
+/// Whether a tile lies inside the ring of walls around the map.
+fn is_inside(x: i32, y: i32) -> bool {
+    (GRID_MIN + 1..GRID_MAX).contains(&x) && (GRID_MIN + 1..GRID_MAX).contains(&y)
+}
+
+fn random_tile(rng: &mut StdRng) -> (i32, i32) {
+    (rng.gen_range(GRID_MIN + 1..GRID_MAX), rng.gen_range(GRID_MIN + 1..GRID_MAX))
+}
+
+/// Plain floor with nothing over it.
+fn is_open_floor(map: &TileMap, x: i32, y: i32) -> bool {
+    map.ground(x, y) == FLOOR && map.tile(Layer::Overhead, x, y) == EMPTY
+}
+
+/// Round patches of a ground tile on open floor, covering each tile of the
+/// patch with the chance `density`.
+fn add_patches(map: &mut TileMap, rng: &mut StdRng, tile: u8, count: u32, radius: Range<i32>, density: f64) {
+    for _ in 0..count {
+        let (center_x, center_y) = random_tile(rng);
+        let radius = rng.gen_range(radius.clone());
+
+        for y in center_y - radius..=center_y + radius {
+            for x in center_x - radius..=center_x + radius {
+                let in_circle = (x - center_x).pow(2) + (y - center_y).pow(2) <= radius * radius;
+                if in_circle && is_open_floor(map, x, y) && rng.gen_bool(density) {
+                    map.set_tile(Layer::Ground, x, y, tile);
+                }
+            }
+        }
+    }
+}
+
+/// Trees, their crowns hanging over the tile behind the trunk.
+fn plant_trees(map: &mut TileMap, rng: &mut StdRng, count: u32) {
+    for _ in 0..count {
+        let (x, y) = random_tile(rng);
+        if is_open_floor(map, x, y) && is_open_floor(map, x, y + 1) && map.tile(Layer::Decoration, x, y) == EMPTY {
+            map.set_tile(Layer::Decoration, x, y, TREE);
+            map.set_tile(Layer::Overhead, x, y + 1, CANOPY);
+        }
+    }
+}
+
+/// Flowers on some of the open floor.
+fn scatter_flowers(map: &mut TileMap, rng: &mut StdRng, count: u32) {
+    for _ in 0..count {
+        let (x, y) = random_tile(rng);
+        if map.ground(x, y) == FLOOR && map.tile(Layer::Decoration, x, y) == EMPTY && !map.is_solid(x, y) {
+            map.set_tile(Layer::Decoration, x, y, FLOWERS);
+        }
+    }
+}
+
+/// Digs a room somewhere in an area, keeping a wall between it and the
+/// area's edge. Returns its middle.
+fn carve_room(map: &mut TileMap, rng: &mut StdRng, min: (i32, i32), max: (i32, i32)) -> (i32, i32) {
+    const MIN_ROOM: i32 = 3;
+    let width = rng.gen_range(MIN_ROOM..=max.0 - min.0 - 1);
+    let height = rng.gen_range(MIN_ROOM..=max.1 - min.1 - 1);
+    let left = rng.gen_range(min.0 + 1..=max.0 - width);
+    let bottom = rng.gen_range(min.1 + 1..=max.1 - height);
+
+    for y in bottom..bottom + height {
+        for x in left..left + width {
+            map.set_tile(Layer::Ground, x, y, FLOOR);
+        }
+    }
+    (left + width / 2, bottom + height / 2)
+}
+
+/// Digs an L-shaped corridor between two tiles, turning either way.
+fn carve_corridor(map: &mut TileMap, rng: &mut StdRng, from: (i32, i32), to: (i32, i32)) {
+    let corner = if rng.gen_bool(0.5) { (to.0, from.1) } else { (from.0, to.1) };
+    for (a, b) in [(from, corner), (corner, to)] {
+        for y in a.1.min(b.1)..=a.1.max(b.1) {
+            for x in a.0.min(b.0)..=a.0.max(b.0) {
+                map.set_tile(Layer::Ground, x, y, FLOOR);
+            }
+        }
+    }
+}
+
+/// The floor tile nearest the middle of the map among those in the largest
+/// stretch of connected floor.
+fn middle_of_largest_area(map: &TileMap) -> (i32, i32) {
+    let mut seen = HashSet::new();
+    let mut largest = Vec::new();
+    for (x, y) in map.positions() {
+        if map.ground(x, y) != FLOOR || seen.contains(&(x, y)) {
+            continue;
+        }
+        let area: Vec<(i32, i32)> =
+            pathfinding::distance_field(&[(x, y)], |x, y| map.ground(x, y) == FLOOR, None).into_keys().collect();
+        seen.extend(area.iter().copied());
+        if area.len() > largest.len() {
+            largest = area;
+        }
+    }
+
+    // Ties broken by position, as the area comes out in no particular order
+    largest.into_iter().min_by_key(|&(x, y)| (x * x + y * y, x, y)).unwrap_or((0, 0))
+}
+
+/// Fills in collision, then walls off every open tile that can't be walked
+/// to from the spawn point, so nothing gets placed out of reach.
+fn finish(map: &mut TileMap, spawn: (i32, i32)) {
+    map.derive_collision();
+
+    let (spawn_x, spawn_y) = spawn;
+    if map.is_solid(spawn_x, spawn_y) {
+        map.set_tile(Layer::Ground, spawn_x, spawn_y, FLOOR);
+        map.set_tile(Layer::Decoration, spawn_x, spawn_y, EMPTY);
+        map.set_solid(spawn_x, spawn_y, false);
+    }
+
+    let reachable = pathfinding::distance_field(&[spawn], |x, y| !map.is_solid(x, y), None);
+    for (x, y) in map.positions().collect::<Vec<_>>() {
+        if !reachable.contains_key(&(x, y)) && !map.is_solid(x, y) {
+            map.set_tile(Layer::Ground, x, y, WALL);
+            map.set_tile(Layer::Decoration, x, y, EMPTY);
+            map.set_solid(x, y, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every layer and the collision of each tile, row by row.
+    fn tiles(map: &TileMap) -> Vec<[u8; 4]> {
+        map.positions()
+            .map(|(x, y)| {
+                let layers = [Layer::Ground, Layer::Decoration, Layer::Overhead].map(|layer| map.tile(layer, x, y));
+                [layers[0], layers[1], layers[2], u8::from(map.is_solid(x, y))]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_map() {
+        for kind in GeneratorKind::ALL {
+            let gen = MapGen { kind, seed: Some(1234) };
+            let (first, mut first_rng) = gen.generate();
+            let (again, mut again_rng) = gen.generate();
+            assert!(tiles(&first.map) == tiles(&again.map), "{} differs", kind.name());
+            assert_eq!(first.spawn, again.spawn);
+            let corners = |layout: &Layout| layout.houses.iter().map(|house| (house.min, house.max)).collect::<Vec<_>>();
+            assert_eq!(corners(&first), corners(&again));
+            // What gets placed on the map afterwards follows the seed too
+            assert_eq!(first_rng.gen::<u64>(), again_rng.gen::<u64>());
+
+            let (other, _) = MapGen { kind, seed: Some(4321) }.generate();
+            assert!(tiles(&first.map) != tiles(&other.map), "{} ignores the seed", kind.name());
+        }
+    }
+
+    #[test]
+    fn everything_walkable_can_be_reached_from_the_spawn() {
+        for kind in GeneratorKind::ALL {
+            for seed in 0..20 {
+                let (layout, _) = MapGen { kind, seed: Some(seed) }.generate();
+                let map = &layout.map;
+                let (spawn_x, spawn_y) = layout.spawn;
+                assert!(!map.is_solid(spawn_x, spawn_y), "{} seed {}: spawn is solid", kind.name(), seed);
+
+                let reachable = pathfinding::distance_field(&[layout.spawn], |x, y| !map.is_solid(x, y), None);
+                for (x, y) in map.positions() {
+                    assert!(
+                        map.is_solid(x, y) || reachable.contains_key(&(x, y)),
+                        "{} seed {}: ({}, {}) is cut off",
+                        kind.name(),
+                        seed,
+                        x,
+                        y
+                    );
+                }
+                for house in &layout.houses {
+                    assert!(reachable.contains_key(&house.entrance), "{} seed {}: house sealed off", kind.name(), seed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interiors_can_be_walked_from_the_door() {
+        let mut rng = StdRng::seed_from_u64(5);
+        let interior = interior((6, 5), &mut rng);
+        let map = &interior.layout.map;
+        assert!(!map.is_solid(interior.exit.0, interior.exit.1));
+        let reachable = pathfinding::distance_field(&[interior.layout.spawn], |x, y| !map.is_solid(x, y), None);
+        assert!(reachable.contains_key(&interior.exit));
+        assert!(interior.floor.iter().all(|tile| reachable.contains_key(tile)));
+        for (position, _) in &interior.furniture {
+            assert!(!interior.floor.contains(position));
+        }
+    }
+}
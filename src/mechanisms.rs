@@ -0,0 +1,241 @@
+//! Puzzles made of plates, levers, gates and bridges (see `Mechanism`). The
+//! plates and levers on a link make up a circuit, and its gates and bridges
+//! follow whether the circuit is complete. Crates pushed onto plates weigh
+//! them down like anything else solid.
+
+use std::collections::{HashMap, HashSet};
+use crate::entity::{Door, Entity, EntityId, Mechanism, Sprite, World};
+use crate::interact::{Interact, InteractHost};
+use crate::map::TileMap;
+
+// Planks for raised bridges
+const BRIDGE_COLOR: [f32; 4] = [0.6, 0.4, 0.2, 1.0];
+
+impl Interact for Mechanism {
+    fn interact(&self, id: EntityId, host: &mut dyn InteractHost) -> bool {
+        match self {
+            Mechanism::Lever { .. } => host.pull_lever(id),
+            Mechanism::Gate { .. } => host.tell("It won't open by hand.", 2.0),
+            Mechanism::Plate { .. } | Mechanism::Bridge { .. } => return false,
+        }
+        true
+    }
+}
+
+/// Whether a lever is pulled, to be flipped.
+pub fn lever_mut(world: &mut World, id: EntityId) -> Option<&mut bool> {
+    match world.get_mut(id)?.mechanism.as_mut()? {
+        Mechanism::Lever { on, .. } => Some(on),
+        _ => None,
+    }
+}
+
+pub fn pull_lever(world: &mut World, map: &mut TileMap, id: EntityId) {
+    if let Some(on) = lever_mut(world, id) {
+        *on = !*on;
+    }
+    update(world, map);
+}
+
+/// Works the puzzles on the map: presses the plates something solid stands
+/// on, then opens the gates and raises the bridges of complete circuits and
+/// shuts the others. Gates and bridges stay as they are while something is
+/// in the way.
+pub fn update(world: &mut World, map: &mut TileMap) {
+    let weighed: HashSet<(i32, i32)> = world
+        .iter()
+        .filter(|(_, entity)| entity.collider.is_some())
+        .map(|(_, entity)| (entity.position.x, entity.position.y))
+        .collect();
+    let ids: Vec<EntityId> = world.iter().filter(|(_, entity)| entity.mechanism.is_some()).map(|(id, _)| id).collect();
+
+    let mut complete: HashMap<String, bool> = HashMap::new();
+    for &id in &ids {
+        let Some(entity) = world.get_mut(id) else {
+            continue;
+        };
+        let position = (entity.position.x, entity.position.y);
+        let Some(mechanism) = entity.mechanism.as_mut() else {
+            continue;
+        };
+        let down = match mechanism {
+            Mechanism::Plate { pressed, .. } => {
+                *pressed = weighed.contains(&position);
+                *pressed
+            }
+            Mechanism::Lever { on, .. } => *on,
+            Mechanism::Gate { .. } | Mechanism::Bridge { .. } => true,
+        };
+        *complete.entry(mechanism.link().to_string()).or_insert(true) &= down;
+    }
+
+    for id in ids {
+        let Some(entity) = world.get(id) else {
+            continue;
+        };
+        let (x, y) = (entity.position.x, entity.position.y);
+        let active = entity.mechanism.as_ref().is_some_and(|mechanism| complete[mechanism.link()]);
+        let door_open = entity.door.as_ref().map(|door| door.open);
+        if world.is_blocked(x, y, Some(id)) {
+            continue;
+        }
+        let Some(entity) = world.get_mut(id) else {
+            continue;
+        };
+        match &mut entity.mechanism {
+            Some(Mechanism::Gate { .. }) if door_open != Some(active) => {
+                entity.set_door(Door { open: active, lock: None });
+            }
+            Some(Mechanism::Bridge { raised, .. }) if *raised != active => {
+                *raised = active;
+                entity.sprite = active.then_some(Sprite::Marker { color: BRIDGE_COLOR });
+                if active {
+                    map.set_solid(x, y, false);
+                } else {
+                    map.update_collision(x, y);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Puts the puzzles on the map back how they started: crates where they
+/// were and levers up. Nothing moves unless every crate can go home.
+/// Returns what to tell the player.
+pub fn reset(world: &mut World, map: &mut TileMap) -> &'static str {
+    let crates: Vec<(EntityId, (i32, i32))> =
+        world.iter().filter_map(|(id, entity)| Some((id, entity.pushable?.home))).collect();
+    let levers: Vec<EntityId> = world
+        .iter()
+        .filter(|(_, entity)| matches!(entity.mechanism, Some(Mechanism::Lever { .. })))
+        .map(|(id, _)| id)
+        .collect();
+    if crates.is_empty() && levers.is_empty() {
+        return "There is no puzzle here.";
+    }
+
+    let blocked = crates.iter().any(|&(_, (x, y))| {
+        world.find_at(x, y, |entity| entity.collider.is_some() && entity.pushable.is_none()).is_some()
+    });
+    if blocked {
+        return "Stand clear to reset the puzzle.";
+    }
+
+    for (id, (x, y)) in crates {
+        if let Some(entity) = world.get_mut(id) {
+            entity.position.x = x;
+            entity.position.y = y;
+        }
+    }
+    for id in levers {
+        if let Some(on) = lever_mut(world, id) {
+            *on = false;
+        }
+    }
+    update(world, map);
+    "The puzzle is reset."
+}
+
+/// Sprite name of a plate, lever or raised bridge. Gates look like the door
+/// they are, and lowered bridges aren't there to see.
+pub fn sprite_kind(mechanism: &Mechanism) -> Option<&'static str> {
+    match mechanism {
+        Mechanism::Plate { pressed: true, .. } => Some("plate_pressed"),
+        Mechanism::Plate { .. } => Some("plate"),
+        Mechanism::Lever { on: true, .. } => Some("lever_on"),
+        Mechanism::Lever { .. } => Some("lever"),
+        Mechanism::Bridge { raised: true, .. } => Some("bridge"),
+        Mechanism::Bridge { .. } | Mechanism::Gate { .. } => None,
+    }
+}
+
+/// Whether an entity lies flat on the ground, to be drawn under anything
+/// standing on it.
+pub fn lies_flat(entity: &Entity) -> bool {
+    matches!(entity.mechanism, Some(Mechanism::Plate { .. } | Mechanism::Bridge { .. }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entity::Prop;
+    use crate::map::{Layer, WATER};
+
+    /// A plate and a lever on one link, working a gate and a bridge over water.
+    fn puzzle() -> (World, TileMap, [EntityId; 4]) {
+        let mut map = TileMap::new(0, 0, 6, 1);
+        map.set_tile(Layer::Ground, 5, 0, WATER);
+        map.derive_collision();
+
+        let mut world = World::new();
+        let link = || "a".to_string();
+        let plate = world.spawn(Entity::new(1, 0).with_mechanism(Mechanism::Plate { link: link(), pressed: false }));
+        let lever = Mechanism::Lever { link: link(), on: false };
+        let lever = world.spawn(Entity::new(2, 0).with_collider().with_mechanism(lever));
+        let gate = world.spawn(
+            Entity::new(4, 0)
+                .with_door(Door { open: false, lock: None })
+                .with_mechanism(Mechanism::Gate { link: link() }),
+        );
+        let bridge = world.spawn(Entity::new(5, 0).with_mechanism(Mechanism::Bridge { link: link(), raised: false }));
+        (world, map, [plate, lever, gate, bridge])
+    }
+
+    fn gate_open(world: &World, gate: EntityId) -> bool {
+        world.get(gate).and_then(|entity| entity.door.as_ref()).is_some_and(|door| door.open)
+    }
+
+    #[test]
+    fn circuits_work_once_every_plate_and_lever_is_down() {
+        let (mut world, mut map, [_, lever, gate, _]) = puzzle();
+        update(&mut world, &mut map);
+        assert!(!gate_open(&world, gate) && map.is_solid(5, 0));
+
+        pull_lever(&mut world, &mut map, lever);
+        assert!(!gate_open(&world, gate), "the plate is still up");
+
+        let crate_entity = Entity::new(1, 0).with_collider().with_prop(Prop { kind: "crate".to_string() });
+        let crate_id = world.spawn(crate_entity.with_pushable());
+        update(&mut world, &mut map);
+        assert!(gate_open(&world, gate));
+        assert!(!map.is_solid(5, 0), "the bridge spans the water");
+
+        // Taking the crate off shuts everything again
+        world.despawn(crate_id);
+        update(&mut world, &mut map);
+        assert!(!gate_open(&world, gate) && map.is_solid(5, 0));
+    }
+
+    #[test]
+    fn gates_stay_open_while_something_stands_in_them() {
+        let (mut world, mut map, [_, lever, gate, _]) = puzzle();
+        world.spawn(Entity::new(1, 0).with_collider());
+        pull_lever(&mut world, &mut map, lever);
+        assert!(gate_open(&world, gate));
+
+        world.spawn(Entity::new(4, 0).with_collider());
+        pull_lever(&mut world, &mut map, lever);
+        assert!(gate_open(&world, gate));
+    }
+
+    #[test]
+    fn resetting_puts_crates_home_and_levers_up() {
+        let (mut world, mut map, [_, lever, _, _]) = puzzle();
+        let crate_id = world.spawn(Entity::new(1, 0).with_collider().with_pushable());
+        pull_lever(&mut world, &mut map, lever);
+        world.get_mut(crate_id).unwrap().position.x = 3;
+
+        // Somebody standing where the crate goes back keeps it from moving
+        let stander = world.spawn(Entity::new(1, 0).with_collider());
+        assert_eq!(reset(&mut world, &mut map), "Stand clear to reset the puzzle.");
+        assert_eq!(world.get(crate_id).unwrap().position.x, 3);
+
+        world.despawn(stander);
+        assert_eq!(reset(&mut world, &mut map), "The puzzle is reset.");
+        assert_eq!(world.get(crate_id).unwrap().position.x, 1);
+        assert_eq!(lever_mut(&mut world, lever), Some(&mut false));
+
+        assert_eq!(reset(&mut World::new(), &mut map), "There is no puzzle here.");
+    }
+}
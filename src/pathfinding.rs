@@ -0,0 +1,197 @@
+//! Path searches over the tile grid. Callers describe the grid through a
+//! closure, which lets them fold in walls, tile costs and whatever entities
+//! happen to be in the way at the time of the search.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+const NEIGHBOURS: [(i32, i32); 4] = [(0, 1), (0, -1), (-1, 0), (1, 0)];
+
+/// Upper bound on tiles explored by one search, in case a cost function
+/// forgets to keep the search inside the map.
+const MAX_EXPLORED: usize = 10_000;
+
+/// Cheapest path from `start` to `goal` using A*.
+///
+/// `cost` returns what it takes to step onto a tile, or `None` if the tile
+/// can't be entered. Costs should be at least 1 for the search to stay exact.
+/// The path leaves out `start` and ends on `goal`; it is empty if both are the same.
+pub fn find_path(
+    start: (i32, i32),
+    goal: (i32, i32),
+    cost: impl Fn(i32, i32) -> Option<u32>,
+) -> Option<Vec<(i32, i32)>> {
+    let heuristic = |(x, y): (i32, i32)| (x - goal.0).unsigned_abs() + (y - goal.1).unsigned_abs();
+
+    let mut open = BinaryHeap::new();
+    let mut best: HashMap<(i32, i32), u32> = HashMap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    open.push(Reverse((heuristic(start), 0, start)));
+    best.insert(start, 0);
+
+    while let Some(Reverse((_, spent, tile))) = open.pop() {
+        if tile == goal {
+            let mut path = vec![tile];
+            while let Some(&previous) = came_from.get(path.last().expect("path is never empty")) {
+                path.push(previous);
+            }
+            path.pop(); // The start tile
+            path.reverse();
+            return Some(path);
+        }
+
+        // Skip stale queue entries for tiles reached more cheaply since
+        if best.get(&tile).is_some_and(|&known| known < spent) {
+            continue;
+        }
+        if best.len() > MAX_EXPLORED {
+            return None;
+        }
+
+        for (dx, dy) in NEIGHBOURS {
+            let next = (tile.0 + dx, tile.1 + dy);
+            let Some(step) = cost(next.0, next.1) else {
+                continue;
+            };
+
+            let total = spent + step;
+            if best.get(&next).is_none_or(|&known| total < known) {
+                best.insert(next, total);
+                came_from.insert(next, tile);
+                open.push(Reverse((total + heuristic(next), total, next)));
+            }
+        }
+    }
+
+    None
+}
+
+/// Number of steps from the nearest of `sources` to every tile reachable
+/// through `passable` tiles, stopping at `max_distance` if given.
+pub fn distance_field(
+    sources: &[(i32, i32)],
+    passable: impl Fn(i32, i32) -> bool,
+    max_distance: Option<u32>,
+) -> HashMap<(i32, i32), u32> {
+    let mut distances: HashMap<(i32, i32), u32> = sources.iter().map(|&tile| (tile, 0)).collect();
+    let mut queue: VecDeque<(i32, i32)> = sources.iter().copied().collect();
+
+    while let Some(tile) = queue.pop_front() {
+        let distance = distances[&tile];
+        if max_distance.is_some_and(|max| distance >= max) || distances.len() > MAX_EXPLORED {
+            continue;
+        }
+
+        for (dx, dy) in NEIGHBOURS {
+            let next = (tile.0 + dx, tile.1 + dy);
+            if !distances.contains_key(&next) && passable(next.0, next.1) {
+                distances.insert(next, distance + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    distances
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tile costs from rows of text: `.` costs 1, `~` costs 5 and `#` can't
+    /// be entered. Row `y` is line `y`.
+    fn grid<'a>(rows: &'a [&'a str]) -> impl Fn(i32, i32) -> Option<u32> + 'a {
+        move |x, y| {
+            let row = rows.get(usize::try_from(y).ok()?)?;
+            match row.as_bytes().get(usize::try_from(x).ok()?)? {
+                b'.' => Some(1),
+                b'~' => Some(5),
+                _ => None,
+            }
+        }
+    }
+
+    fn total_cost(path: &[(i32, i32)], cost: impl Fn(i32, i32) -> Option<u32>) -> u32 {
+        path.iter().map(|&(x, y)| cost(x, y).unwrap()).sum()
+    }
+
+    #[test]
+    fn goes_around_a_wall() {
+        let rows = [
+            ".....", //
+            ".###.",
+            ".#...",
+            ".#.#.",
+        ];
+        let path = find_path((0, 3), (2, 3), grid(&rows)).unwrap();
+        assert_eq!(path.last(), Some(&(2, 3)));
+        assert_eq!(path.len(), 12);
+        assert!(path.iter().all(|&(x, y)| grid(&rows)(x, y).is_some()));
+        // Every step is to a neighbouring tile
+        let mut previous = (0, 3);
+        for &tile in &path {
+            assert_eq!((tile.0 - previous.0).abs() + (tile.1 - previous.1).abs(), 1);
+            previous = tile;
+        }
+    }
+
+    #[test]
+    fn takes_the_cheaper_route() {
+        let rows = [
+            "~~~~~", //
+            ".....",
+            ".###.",
+        ];
+        // Straight along the top row costs 20, dropping down to the plain row costs 10
+        let path = find_path((0, 0), (4, 0), grid(&rows)).unwrap();
+        assert_eq!(total_cost(&path, grid(&rows)), 10);
+        assert!(path.contains(&(2, 1)));
+    }
+
+    #[test]
+    fn start_and_goal_the_same() {
+        let rows = ["..."];
+        assert_eq!(find_path((1, 0), (1, 0), grid(&rows)), Some(Vec::new()));
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_path() {
+        let rows = [
+            "..#..", //
+            "..#..",
+            "..#..",
+        ];
+        assert_eq!(find_path((0, 0), (4, 2), grid(&rows)), None);
+        assert_eq!(find_path((0, 0), (2, 1), grid(&rows)), None);
+    }
+
+    #[test]
+    fn distance_field_counts_steps_up_to_the_limit() {
+        let rows = [
+            "......", //
+            ".####.",
+            "......",
+        ];
+        let passable = |x, y| grid(&rows)(x, y).is_some();
+
+        let all = distance_field(&[(0, 0)], passable, None);
+        assert_eq!(all.len(), 14);
+        assert_eq!(all[&(0, 2)], 2);
+        assert_eq!(all[&(5, 2)], 7);
+        assert!(!all.contains_key(&(1, 1)));
+
+        let near = distance_field(&[(0, 0)], passable, Some(3));
+        assert!(near.values().all(|&distance| distance <= 3));
+        assert_eq!(near.len(), 7);
+        assert_eq!(near[&(3, 0)], 3);
+        assert!(!near.contains_key(&(4, 0)));
+    }
+
+    #[test]
+    fn distance_field_starts_from_the_nearest_source() {
+        let rows = ["......."];
+        let field = distance_field(&[(0, 0), (6, 0)], |x, y| grid(&rows)(x, y).is_some(), None);
+        assert_eq!(field[&(3, 0)], 3);
+        assert_eq!(field[&(5, 0)], 1);
+    }
+}
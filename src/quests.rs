@@ -0,0 +1,397 @@
+//! Quests, loaded from `assets/data/quests.json`, and the player's progress
+//! through them.
+//!
+//! A quest is a list of stages worked through in order. A stage is done once
+//! all of its objectives are met, and finishing the last stage hands out the
+//! quest's rewards. Scripts start quests with `start_quest`; after that the
+//! game reports what the player does as [`QuestEvent`]s.
+
+use serde::{Deserialize, Serialize};
+use crate::combat::EnemyDb;
+use crate::items::{Inventory, ItemDb, ItemStack};
+use crate::script::Flags;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Objective {
+    /// Interact with the NPC of this name
+    Talk { npc: String },
+    /// Arrive on a map file, or at one tile of it
+    Reach {
+        map: String,
+        #[serde(default)]
+        at: Option<(i32, i32)>,
+    },
+    /// Carry this many of an item
+    Collect { item: String, count: u32 },
+    /// Win battles against this many of an enemy while the stage is current
+    Defeat { enemy: String, count: u32 },
+    /// Have a story flag set, for steps that scripts decide on
+    Flag { flag: String },
+}
+
+impl Objective {
+    /// How much an event adds to the progress on this objective.
+    fn progress(&self, event: &QuestEvent) -> u32 {
+        match (self, event) {
+            (Objective::Talk { npc }, QuestEvent::Talked(name)) if npc == name => 1,
+            (Objective::Reach { map, at }, QuestEvent::Reached { map: reached, position })
+                if map == reached && at.is_none_or(|at| at == *position) =>
+            {
+                1
+            }
+            (Objective::Defeat { enemy, .. }, QuestEvent::Defeated(id)) if enemy == id => 1,
+            _ => 0,
+        }
+    }
+
+    /// Whether the objective is met, given the progress recorded on it.
+    pub fn is_met(&self, progress: u32, inventory: &Inventory, flags: &Flags) -> bool {
+        match self {
+            Objective::Talk { .. } | Objective::Reach { .. } => progress > 0,
+            Objective::Collect { item, count } => inventory.count(item) >= *count,
+            Objective::Defeat { count, .. } => progress >= *count,
+            Objective::Flag { flag } => flags.get(flag),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Stage {
+    /// What the player is told to do, shown in the quest log
+    pub description: String,
+    pub objectives: Vec<Objective>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Rewards {
+    #[serde(default)]
+    pub xp: u32,
+    #[serde(default)]
+    pub items: Vec<ItemStack>,
+    /// Story flags set, so scripts can tell the quest is done
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct QuestDef {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub stages: Vec<Stage>,
+    #[serde(default)]
+    pub rewards: Rewards,
+}
+
+/// Every quest the game knows about.
+#[derive(Debug)]
+pub struct QuestDb {
+    quests: Vec<QuestDef>,
+}
+
+impl QuestDb {
+    pub fn builtin(items: &ItemDb, enemies: &EnemyDb) -> Self {
+        QuestDb::from_json(include_str!("../assets/data/quests.json"), items, enemies)
+            .unwrap_or_else(|e| panic!("Invalid built-in quests: {}", e))
+    }
+
+    /// Parses quests, checking that every item and enemy they mention exists.
+    pub fn from_json(source: &str, items: &ItemDb, enemies: &EnemyDb) -> Result<Self, String> {
+        let quests: Vec<QuestDef> = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, quest) in quests.iter().enumerate() {
+            if quest.stages.is_empty() {
+                return Err(format!("quest '{}' has no stages", quest.id));
+            }
+            for objective in quest.stages.iter().flat_map(|stage| &stage.objectives) {
+                match objective {
+                    Objective::Collect { item, count } | Objective::Defeat { enemy: item, count } if *count == 0 => {
+                        return Err(format!("quest '{}' asks for 0 of '{}'", quest.id, item));
+                    }
+                    Objective::Collect { item, .. } if items.get(item).is_none() => {
+                        return Err(format!("quest '{}' uses unknown item '{}'", quest.id, item));
+                    }
+                    Objective::Defeat { enemy, .. } if enemies.get(enemy).is_none() => {
+                        return Err(format!("quest '{}' uses unknown enemy '{}'", quest.id, enemy));
+                    }
+                    _ => {}
+                }
+            }
+            if let Some(reward) = quest.rewards.items.iter().find(|stack| items.get(&stack.item).is_none()) {
+                return Err(format!("quest '{}' rewards unknown item '{}'", quest.id, reward.item));
+            }
+            if quests[..index].iter().any(|other| other.id == quest.id) {
+                return Err(format!("quest '{}' is defined twice", quest.id));
+            }
+        }
+
+        Ok(QuestDb { quests })
+    }
+
+    pub fn get(&self, id: &str) -> Option<&QuestDef> {
+        self.quests.iter().find(|quest| quest.id == id)
+    }
+}
+
+/// Something the player did that may move a quest along.
+#[derive(Clone, Copy, Debug)]
+pub enum QuestEvent<'a> {
+    /// Interacted with the NPC of this name
+    Talked(&'a str),
+    /// Arrived on a tile of a map file
+    Reached { map: &'a str, position: (i32, i32) },
+    /// Won a battle against one enemy of this id
+    Defeated(&'a str),
+    /// The inventory or story flags changed. Collect and flag objectives are
+    /// checked on every event, so this only makes sure they are checked now.
+    Changed,
+}
+
+/// What recording an event did to a quest.
+#[derive(Clone, Debug, PartialEq)]
+pub enum QuestUpdate {
+    /// The quest moved on to this stage
+    Advanced { quest: String, stage: usize },
+    /// The last stage is done, and the rewards are due
+    Completed { quest: String },
+}
+
+/// A quest under way.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QuestProgress {
+    pub quest: String,
+    pub stage: usize,
+    /// Progress on each objective of the current stage, such as enemies defeated
+    pub progress: Vec<u32>,
+}
+
+/// The quests the player has started and finished, in the order they were
+/// started.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct QuestLog {
+    active: Vec<QuestProgress>,
+    completed: Vec<String>,
+}
+
+impl QuestLog {
+    pub fn active(&self) -> &[QuestProgress] {
+        &self.active
+    }
+
+    pub fn completed(&self) -> &[String] {
+        &self.completed
+    }
+
+    pub fn has(&self, quest: &str) -> bool {
+        self.completed.iter().any(|id| id == quest) || self.active.iter().any(|progress| progress.quest == quest)
+    }
+
+    /// Starts a quest at its first stage. Returns false if the log already
+    /// has it, running or done.
+    pub fn start(&mut self, quest: &QuestDef) -> bool {
+        if self.has(&quest.id) {
+            return false;
+        }
+        self.active.push(QuestProgress {
+            quest: quest.id.clone(),
+            stage: 0,
+            progress: vec![0; quest.stages[0].objectives.len()],
+        });
+        true
+    }
+
+    /// Records an event against every quest under way, moving each on for
+    /// as many stages as are now done.
+    pub fn record(&mut self, db: &QuestDb, event: QuestEvent, inventory: &Inventory, flags: &Flags) -> Vec<QuestUpdate> {
+        let mut updates = Vec::new();
+        let mut finished = Vec::new();
+
+        for progress in &mut self.active {
+            let Some(def) = db.get(&progress.quest) else {
+                continue;
+            };
+            for (objective, count) in def.stages[progress.stage].objectives.iter().zip(&mut progress.progress) {
+                *count += objective.progress(&event);
+            }
+
+            loop {
+                let stage = &def.stages[progress.stage];
+                let done = stage
+                    .objectives
+                    .iter()
+                    .zip(&progress.progress)
+                    .all(|(objective, &count)| objective.is_met(count, inventory, flags));
+                if !done {
+                    break;
+                }
+                if progress.stage + 1 == def.stages.len() {
+                    finished.push(progress.quest.clone());
+                    updates.push(QuestUpdate::Completed { quest: progress.quest.clone() });
+                    break;
+                }
+                progress.stage += 1;
+                progress.progress = vec![0; def.stages[progress.stage].objectives.len()];
+                updates.push(QuestUpdate::Advanced { quest: progress.quest.clone(), stage: progress.stage });
+            }
+        }
+
+        self.active.retain(|progress| !finished.contains(&progress.quest));
+        self.completed.extend(finished);
+        updates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ITEMS: &str = r#"[
+        { "id": "herb", "name": "Herb", "description": "", "stack_size": 10, "category": "consumable" }
+    ]"#;
+    const ENEMIES: &str = r#"[
+        { "id": "slime", "name": "Slime", "level": 1, "xp": 3,
+          "stats": { "max_hp": 5, "max_mp": 0, "attack": 1, "defense": 0, "speed": 1 } }
+    ]"#;
+    const QUESTS: &str = r#"[
+        {
+            "id": "errand",
+            "name": "An Errand",
+            "description": "",
+            "stages": [
+                { "description": "Go to the grove", "objectives": [{ "type": "reach", "map": "grove" }] },
+                {
+                    "description": "Gather and hunt",
+                    "objectives": [
+                        { "type": "collect", "item": "herb", "count": 2 },
+                        { "type": "defeat", "enemy": "slime", "count": 2 }
+                    ]
+                },
+                { "description": "Report back", "objectives": [{ "type": "talk", "npc": "WREN" }] }
+            ],
+            "rewards": { "xp": 10, "flags": ["errand_done"] }
+        },
+        {
+            "id": "secret",
+            "name": "A Secret",
+            "description": "",
+            "stages": [{ "description": "Learn it", "objectives": [{ "type": "flag", "flag": "knows" }] }]
+        }
+    ]"#;
+
+    fn dbs() -> (ItemDb, QuestDb) {
+        let items = ItemDb::from_json(ITEMS).unwrap();
+        let enemies = EnemyDb::from_json(ENEMIES).unwrap();
+        let quests = QuestDb::from_json(QUESTS, &items, &enemies).unwrap();
+        (items, quests)
+    }
+
+    fn reach(map: &str, at: Option<(i32, i32)>) -> Objective {
+        Objective::Reach { map: map.to_string(), at }
+    }
+
+    #[test]
+    fn objectives_count_the_events_they_care_about() {
+        let talk = Objective::Talk { npc: "WREN".to_string() };
+        assert_eq!(talk.progress(&QuestEvent::Talked("WREN")), 1);
+        assert_eq!(talk.progress(&QuestEvent::Talked("ODO")), 0);
+        assert_eq!(talk.progress(&QuestEvent::Changed), 0);
+
+        let anywhere = QuestEvent::Reached { map: "grove", position: (3, 4) };
+        assert_eq!(reach("grove", None).progress(&anywhere), 1);
+        assert_eq!(reach("grove", Some((3, 4))).progress(&anywhere), 1);
+        assert_eq!(reach("grove", Some((0, 0))).progress(&anywhere), 0);
+        assert_eq!(reach("cave", None).progress(&anywhere), 0);
+
+        let defeat = Objective::Defeat { enemy: "slime".to_string(), count: 2 };
+        assert_eq!(defeat.progress(&QuestEvent::Defeated("slime")), 1);
+        assert_eq!(defeat.progress(&QuestEvent::Defeated("rat")), 0);
+    }
+
+    #[test]
+    fn objectives_are_met_by_progress_items_or_flags() {
+        let (items, _) = dbs();
+        let mut inventory = Inventory::default();
+        let mut flags = Flags::default();
+
+        let defeat = Objective::Defeat { enemy: "slime".to_string(), count: 2 };
+        assert!(!defeat.is_met(1, &inventory, &flags));
+        assert!(defeat.is_met(2, &inventory, &flags));
+
+        let collect = Objective::Collect { item: "herb".to_string(), count: 2 };
+        inventory.add(&items, "herb", 1);
+        assert!(!collect.is_met(0, &inventory, &flags));
+        inventory.add(&items, "herb", 1);
+        assert!(collect.is_met(0, &inventory, &flags));
+
+        let flag = Objective::Flag { flag: "knows".to_string() };
+        assert!(!flag.is_met(0, &inventory, &flags));
+        flags.set("knows", true);
+        assert!(flag.is_met(0, &inventory, &flags));
+    }
+
+    #[test]
+    fn stages_advance_in_order_and_complete_once() {
+        let (items, db) = dbs();
+        let mut inventory = Inventory::default();
+        let flags = Flags::default();
+        let mut log = QuestLog::default();
+        assert!(log.start(db.get("errand").unwrap()));
+        assert!(!log.start(db.get("errand").unwrap()));
+
+        // Talking to Wren early doesn't count towards the last stage
+        assert_eq!(log.record(&db, QuestEvent::Talked("WREN"), &inventory, &flags), Vec::new());
+
+        let updates = log.record(&db, QuestEvent::Reached { map: "grove", position: (0, 0) }, &inventory, &flags);
+        assert_eq!(updates, vec![QuestUpdate::Advanced { quest: "errand".to_string(), stage: 1 }]);
+
+        inventory.add(&items, "herb", 2);
+        assert_eq!(log.record(&db, QuestEvent::Defeated("slime"), &inventory, &flags), Vec::new());
+        assert_eq!(log.active()[0].progress, vec![0, 1]);
+        let updates = log.record(&db, QuestEvent::Defeated("slime"), &inventory, &flags);
+        assert_eq!(updates, vec![QuestUpdate::Advanced { quest: "errand".to_string(), stage: 2 }]);
+
+        let updates = log.record(&db, QuestEvent::Talked("WREN"), &inventory, &flags);
+        assert_eq!(updates, vec![QuestUpdate::Completed { quest: "errand".to_string() }]);
+        assert!(log.active().is_empty());
+        assert_eq!(log.completed(), ["errand".to_string()]);
+
+        // Rewards are only due the one time
+        assert_eq!(log.record(&db, QuestEvent::Talked("WREN"), &inventory, &flags), Vec::new());
+        assert!(!log.start(db.get("errand").unwrap()));
+        assert!(log.has("errand"));
+    }
+
+    #[test]
+    fn stages_already_done_are_passed_straight_through() {
+        let (_, db) = dbs();
+        let mut flags = Flags::default();
+        flags.set("knows", true);
+        let mut log = QuestLog::default();
+        log.start(db.get("secret").unwrap());
+        let updates = log.record(&db, QuestEvent::Changed, &Inventory::default(), &flags);
+        assert_eq!(updates, vec![QuestUpdate::Completed { quest: "secret".to_string() }]);
+    }
+
+    #[test]
+    fn bad_quests_are_rejected() {
+        let items = ItemDb::from_json(ITEMS).unwrap();
+        let enemies = EnemyDb::from_json(ENEMIES).unwrap();
+        let quest = |stages: &str| format!(r#"[{{ "id": "q", "name": "Q", "description": "", "stages": {} }}]"#, stages);
+        let stage = |objective: &str| quest(&format!(r#"[{{ "description": "", "objectives": [{}] }}]"#, objective));
+        let valid = |source: String| QuestDb::from_json(&source, &items, &enemies).is_ok();
+
+        assert!(!valid(quest("[]")));
+        assert!(!valid(stage(r#"{ "type": "collect", "item": "gold", "count": 1 }"#)));
+        assert!(!valid(stage(r#"{ "type": "defeat", "enemy": "dragon", "count": 1 }"#)));
+        assert!(!valid(stage(r#"{ "type": "collect", "item": "herb", "count": 0 }"#)));
+        assert!(valid(stage(r#"{ "type": "talk", "npc": "WREN" }"#)));
+    }
+
+    #[test]
+    fn builtin_quests_load() {
+        let items = ItemDb::builtin();
+        let quests = QuestDb::builtin(&items, &EnemyDb::builtin());
+        assert!(quests.get("find_grove").is_some());
+    }
+}
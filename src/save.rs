@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::clock::Clock;
+use crate::encounters::{EncounterRoller, EncounterZone};
+use crate::entity::{Direction, Door, EntityId, World};
+use crate::fov::FogOfWar;
+use crate::items::Inventory;
+use crate::map::TileMap;
+use crate::quests::QuestLog;
+use crate::script::{Flags, MapScripts};
+use crate::stats::Character;
+
+const SAVE_FILE_NAME: &str = "save.json";
+
+/// Everything needed to restore a game, written as JSON next to the executable.
+#[derive(Serialize, Deserialize)]
+pub struct SaveData {
+    /// Map file the player is on, `None` on a generated map
+    pub map_name: Option<String>,
+    pub map: TileMap,
+    pub spawn: (i32, i32),
+    pub map_scripts: MapScripts,
+    pub encounter_zones: Vec<EncounterZone>,
+    pub encounters: EncounterRoller,
+    /// Explored tiles of the saved map
+    pub fog: FogOfWar,
+    pub world: World,
+    pub player: EntityId,
+    /// The map outside, while the player is in a house
+    pub outside: Option<StashedMap>,
+    pub memories: MapMemories,
+    pub flags: Flags,
+    pub quests: QuestLog,
+    pub clock: Clock,
+    pub character: Character,
+    pub inventory: Inventory,
+}
+
+/// What the player changed on a map file: maps are loaded afresh from their
+/// files, so this is what keeps an opened door open when they come back.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MapMemory {
+    pub doors: Vec<((i32, i32), Door)>,
+    /// Tiles of `once` triggers that have already fired
+    pub fired_triggers: Vec<(i32, i32)>,
+    /// Where each crate was pushed to, by where it started
+    pub crates: Vec<((i32, i32), (i32, i32))>,
+    /// Tiles of the levers left pulled
+    pub levers: Vec<(i32, i32)>,
+    /// What the player has explored of the map
+    #[serde(default)]
+    pub fog: FogOfWar,
+}
+
+/// Memories of each map file the player has been to, by map name.
+pub type MapMemories = HashMap<String, MapMemory>;
+
+/// A map the player left to go into a house, kept as it was for when they
+/// come back out.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StashedMap {
+    pub map_name: Option<String>,
+    pub map: TileMap,
+    pub spawn: (i32, i32),
+    pub map_scripts: MapScripts,
+    pub encounter_zones: Vec<EncounterZone>,
+    pub fog: FogOfWar,
+    /// Everything on the map, the player included
+    pub world: World,
+    /// Where the player comes back out, and the way they face
+    pub exit: (i32, i32),
+    pub facing: Direction,
+}
+
+fn save_path() -> Result<PathBuf, String> {
+    let exe_path = env::current_exe().map_err(|e| e.to_string())?;
+    let exe_dir = exe_path.parent().ok_or("Failed to get executable directory.")?;
+    Ok(exe_dir.join(SAVE_FILE_NAME))
+}
+
+pub fn write(data: &SaveData) -> Result<(), String> {
+    let json = serde_json::to_string(data).map_err(|e| e.to_string())?;
+    fs::write(save_path()?, json).map_err(|e| e.to_string())
+}
+
+pub fn read() -> Result<SaveData, String> {
+    let json = fs::read_to_string(save_path()?).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Serializes maps keyed by grid coordinates as a list of pairs, since JSON
+/// object keys have to be strings.
+pub mod coord_map {
+    use std::collections::HashMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<V, S>(map: &HashMap<(i32, i32), V>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        V: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, V, D>(deserializer: D) -> Result<HashMap<(i32, i32), V>, D::Error>
+    where
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let pairs = Vec::<((i32, i32), V)>::deserialize(deserializer)?;
+        Ok(pairs.into_iter().collect())
+    }
+}
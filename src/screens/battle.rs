@@ -0,0 +1,272 @@
+use graphics::*;
+use graphics::types::Color;
+use piston::input::*;
+use opengl_graphics::{GlGraphics, GlyphCache};
+use crate::combat::{Action, Outcome};
+use crate::items::{ItemCategory, ItemStack};
+use crate::screens::{draw_bar, draw_text, Screen, ScreenState};
+use crate::state::{GameState, SharedState};
+
+const BACKGROUND_COLOR: Color = [0.1, 0.05, 0.05, 1.0];
+const PANEL_COLOR: Color = [0.0, 0.0, 0.0, 0.8];
+const SELECTED_COLOR: Color = [0.3, 0.3, 0.3, 1.0];
+const TEXT_COLOR: Color = [1.0, 1.0, 1.0, 1.0];
+const DIM_TEXT_COLOR: Color = [0.6, 0.6, 0.6, 1.0];
+const ENEMY_COLOR: Color = [0.7, 0.3, 0.8, 1.0];
+const TARGET_COLOR: Color = [1.0, 1.0, 0.0, 1.0];
+const HP_COLOR: Color = [0.8, 0.2, 0.2, 1.0];
+const MP_COLOR: Color = [0.2, 0.4, 0.9, 1.0];
+const MARGIN: f64 = 30.0;
+const ROW_HEIGHT: f64 = 24.0;
+const LOG_LINES: usize = 4;
+const MAIN_OPTIONS: [&str; 4] = ["ATTACK", "SKILL", "ITEM", "FLEE"];
+
+#[derive(Clone, PartialEq)]
+enum Menu {
+    Main,
+    Skills,
+    Items,
+    /// Picking an enemy for an attack, or for the given skill
+    Target(Option<String>),
+    Finished,
+}
+
+pub struct BattleScreen {
+    state: SharedState,
+    menu: Menu,
+    selected: usize,
+    message: Option<String>,
+}
+
+impl BattleScreen {
+    pub fn new(state: SharedState) -> Self {
+        BattleScreen {
+            state,
+            menu: Menu::Main,
+            selected: 0,
+            message: None,
+        }
+    }
+
+    /// Labels and values of the options in the current menu.
+    fn options(&self, state: &GameState) -> Vec<(String, String)> {
+        let Some(battle) = &state.battle else {
+            return Vec::new();
+        };
+
+        match &self.menu {
+            Menu::Main => MAIN_OPTIONS.iter().map(|o| (o.to_string(), o.to_string())).collect(),
+            Menu::Skills => state
+                .skills
+                .known_at(battle.player.level)
+                .map(|skill| (format!("{} ({} MP)", skill.name, skill.mp_cost), skill.id.clone()))
+                .collect(),
+            Menu::Items => {
+                let mut options: Vec<(String, String)> = Vec::new();
+                for stack in state.inventory.stacks() {
+                    let usable = state
+                        .items
+                        .get(&stack.item)
+                        .is_some_and(|def| def.category == ItemCategory::Consumable && def.effect.is_some());
+                    if usable && !options.iter().any(|(_, id)| *id == stack.item) {
+                        let label = format!("{} x {}", state.items.name(&stack.item), state.inventory.count(&stack.item));
+                        options.push((label, stack.item.clone()));
+                    }
+                }
+                options
+            }
+            Menu::Target(_) => battle
+                .living_enemies()
+                .map(|(index, enemy)| (enemy.name.clone(), index.to_string()))
+                .collect(),
+            Menu::Finished => vec![("CONTINUE".to_string(), String::new())],
+        }
+    }
+
+    fn open_menu(&mut self, menu: Menu) {
+        self.menu = menu;
+        self.selected = 0;
+    }
+
+    fn move_selection(&mut self, delta: i32) {
+        let len = self.options(&self.state.borrow()).len();
+        if len > 0 {
+            self.selected = (self.selected as i32 + delta).rem_euclid(len as i32) as usize;
+        }
+    }
+
+    fn confirm(&mut self) -> Option<ScreenState> {
+        let options = self.options(&self.state.borrow());
+        let Some((_, value)) = options.get(self.selected).cloned() else {
+            // Nothing to pick, e.g. an empty item list
+            self.open_menu(Menu::Main);
+            return None;
+        };
+
+        match self.menu.clone() {
+            Menu::Main => match value.as_str() {
+                "ATTACK" => self.open_menu(Menu::Target(None)),
+                "SKILL" => self.open_menu(Menu::Skills),
+                "ITEM" => self.open_menu(Menu::Items),
+                _ => self.perform(Action::Flee),
+            },
+            Menu::Skills => self.open_menu(Menu::Target(Some(value))),
+            Menu::Items => self.perform(Action::Item { item: value }),
+            Menu::Target(skill) => {
+                let target = value.parse().unwrap_or(0);
+                match skill {
+                    Some(skill) => self.perform(Action::Skill { skill, target }),
+                    None => self.perform(Action::Attack { target }),
+                }
+            }
+            Menu::Finished => return Some(self.finish()),
+        }
+        None
+    }
+
+    fn perform(&mut self, action: Action) {
+        let finished = {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+            let Some(battle) = state.battle.as_mut() else {
+                return;
+            };
+
+            self.message = battle
+                .player_action(action, &state.items, &state.skills, &mut state.inventory)
+                .err();
+            battle.outcome.is_some()
+        };
+
+        self.open_menu(if finished { Menu::Finished } else { Menu::Main });
+    }
+
+    /// Hands the results of the battle back to the player and returns to the map.
+    fn finish(&mut self) -> ScreenState {
+        {
+            let mut state = self.state.borrow_mut();
+            let state = &mut *state;
+
+            if let Some(battle) = state.battle.take() {
+                state.player = battle.player;
+
+                match &battle.outcome {
+                    Some(Outcome::Victory { xp, drops, .. }) => {
+                        state.player.gain_xp(*xp, &state.growth);
+                        // Whatever doesn't fit in the bag is dropped next to the player, like
+                        // items dropped from the inventory screen
+                        for drop in drops {
+                            let left_over = state.inventory.add(&state.items, &drop.item, drop.count);
+                            if left_over > 0 {
+                                state.dropped.push(ItemStack { item: drop.item.clone(), count: left_over });
+                            }
+                        }
+                    }
+                    Some(Outcome::Defeat) => {
+                        // Wake up patched up rather than ending the game
+                        state.player.hp = state.player.stats.max_hp;
+                        state.player.mp = state.player.stats.max_mp;
+                    }
+                    Some(Outcome::Fled) | None => {}
+                }
+
+                state.battle_outcome = battle.outcome;
+            }
+        }
+
+        self.open_menu(Menu::Main);
+        self.message = None;
+        ScreenState::Game
+    }
+}
+
+impl Screen for BattleScreen {
+    fn draw(&mut self, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache, window_size: [f64; 2]) {
+        clear(BACKGROUND_COLOR, g);
+
+        let state = self.state.borrow();
+        let Some(battle) = &state.battle else {
+            return;
+        };
+        let options = self.options(&state);
+
+        // Enemies spread across the top half
+        let targeted = match self.menu {
+            Menu::Target(_) => options.get(self.selected).and_then(|(_, index)| index.parse::<usize>().ok()),
+            _ => None,
+        };
+        let slot_width = window_size[0] / battle.enemies.len().max(1) as f64;
+        for (index, enemy) in battle.enemies.iter().enumerate() {
+            if !enemy.is_alive() {
+                continue;
+            }
+            let center_x = slot_width * (index as f64 + 0.5);
+            let color = if targeted == Some(index) { TARGET_COLOR } else { ENEMY_COLOR };
+            ellipse(color, [center_x - 30.0, 90.0, 60.0, 60.0], c.transform, g);
+            draw_text(&enemy.name, 12, TEXT_COLOR, [center_x - 30.0, 175.0], c, g, glyphs);
+            draw_bar(HP_COLOR, enemy.hp, enemy.stats.max_hp, [center_x - 40.0, 185.0], 80.0, c, g);
+        }
+
+        // Battle log
+        let log_top = 230.0;
+        let first = battle.log.len().saturating_sub(LOG_LINES);
+        for (index, line) in battle.log[first..].iter().enumerate() {
+            draw_text(line, 12, TEXT_COLOR, [MARGIN, log_top + index as f64 * 20.0], c, g, glyphs);
+        }
+
+        // Player status and menu at the bottom
+        let panel_top = window_size[1] - 170.0;
+        rectangle(PANEL_COLOR, [0.0, panel_top, window_size[0], 170.0], c.transform, g);
+
+        let player = &battle.player;
+        let status_x = window_size[0] / 2.0 + MARGIN;
+        draw_text(&format!("{}  LV {}", player.name, player.level), 16, TEXT_COLOR, [status_x, panel_top + 30.0], c, g, glyphs);
+        draw_text(&format!("HP {}/{}", player.hp, player.stats.max_hp), 12, TEXT_COLOR, [status_x, panel_top + 60.0], c, g, glyphs);
+        draw_bar(HP_COLOR, player.hp, player.stats.max_hp, [status_x + 120.0, panel_top + 50.0], 150.0, c, g);
+        draw_text(&format!("MP {}/{}", player.mp, player.stats.max_mp), 12, TEXT_COLOR, [status_x, panel_top + 84.0], c, g, glyphs);
+        draw_bar(MP_COLOR, player.mp, player.stats.max_mp, [status_x + 120.0, panel_top + 74.0], 150.0, c, g);
+
+        if options.is_empty() {
+            draw_text("Nothing to choose.", 12, DIM_TEXT_COLOR, [MARGIN, panel_top + 30.0], c, g, glyphs);
+        }
+        for (index, (label, _)) in options.iter().enumerate() {
+            let y = panel_top + 12.0 + index as f64 * ROW_HEIGHT;
+            if index == self.selected {
+                rectangle(SELECTED_COLOR, [MARGIN - 10.0, y, window_size[0] / 2.0 - MARGIN, ROW_HEIGHT], c.transform, g);
+            }
+            draw_text(label, 16, TEXT_COLOR, [MARGIN, y + ROW_HEIGHT * 0.7], c, g, glyphs);
+        }
+
+        if let Some(message) = &self.message {
+            draw_text(message, 12, TARGET_COLOR, [status_x, panel_top + 115.0], c, g, glyphs);
+        }
+        draw_text("W/S: SELECT   E: CONFIRM   Q: BACK", 12, DIM_TEXT_COLOR, [status_x, panel_top + 150.0], c, g, glyphs);
+    }
+
+    fn update(&mut self) -> Option<ScreenState> {
+        // Battles can be decided before the player gets a turn
+        let finished = self.state.borrow().battle.as_ref().is_some_and(|b| b.outcome.is_some());
+        if finished && self.menu != Menu::Finished {
+            self.open_menu(Menu::Finished);
+        }
+        None
+    }
+
+    fn handle_input(&mut self, input: &Input) -> Option<ScreenState> {
+        if let Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            ..
+        }) = input
+        {
+            match key {
+                Key::W => self.move_selection(-1),
+                Key::S => self.move_selection(1),
+                Key::E => return self.confirm(),
+                Key::Q if self.menu != Menu::Finished => self.open_menu(Menu::Main),
+                _ => {}
+            }
+        }
+        None
+    }
+}
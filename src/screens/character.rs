@@ -0,0 +1,84 @@
+use graphics::*;
+use graphics::types::Color;
+use piston::input::*;
+use opengl_graphics::{GlGraphics, GlyphCache};
+use crate::screens::{draw_bar, draw_text, Screen, ScreenState};
+use crate::state::SharedState;
+
+const BACKGROUND_COLOR: Color = [0.05, 0.05, 0.1, 1.0];
+const TEXT_COLOR: Color = [1.0, 1.0, 1.0, 1.0];
+const DIM_TEXT_COLOR: Color = [0.6, 0.6, 0.6, 1.0];
+const HP_COLOR: Color = [0.8, 0.2, 0.2, 1.0];
+const MP_COLOR: Color = [0.2, 0.4, 0.9, 1.0];
+const XP_COLOR: Color = [0.9, 0.8, 0.2, 1.0];
+const MARGIN: f64 = 40.0;
+const BAR_WIDTH: f64 = 200.0;
+const ROW_HEIGHT: f64 = 28.0;
+
+pub struct CharacterScreen {
+    state: SharedState,
+}
+
+impl CharacterScreen {
+    pub fn new(state: SharedState) -> Self {
+        CharacterScreen { state }
+    }
+}
+
+impl Screen for CharacterScreen {
+    fn draw(&mut self, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache, window_size: [f64; 2]) {
+        clear(BACKGROUND_COLOR, g);
+
+        let state = self.state.borrow();
+        let player = &state.player;
+        let stats = player.stats;
+
+        draw_text("CHARACTER", 24, TEXT_COLOR, [MARGIN, 50.0], c, g, glyphs);
+        draw_text(&format!("{}   LV {}", player.name, player.level), 16, TEXT_COLOR, [MARGIN, 100.0], c, g, glyphs);
+
+        // Resource bars
+        let bars = [
+            ("HP", player.hp, stats.max_hp, HP_COLOR),
+            ("MP", player.mp, stats.max_mp, MP_COLOR),
+        ];
+        for (index, (label, current, max, color)) in bars.into_iter().enumerate() {
+            let y = 140.0 + index as f64 * ROW_HEIGHT;
+            draw_text(&format!("{} {}/{}", label, current, max), 12, TEXT_COLOR, [MARGIN, y + 10.0], c, g, glyphs);
+            draw_bar(color, current, max, [MARGIN + 140.0, y], BAR_WIDTH, c, g);
+        }
+
+        let xp_needed = state.growth.xp_to_next(player.level) as i32;
+        let xp_y = 140.0 + 2.0 * ROW_HEIGHT;
+        if player.level < state.growth.max_level {
+            draw_text(&format!("XP {}/{}", player.xp, xp_needed), 12, TEXT_COLOR, [MARGIN, xp_y + 10.0], c, g, glyphs);
+            draw_bar(XP_COLOR, player.xp as i32, xp_needed, [MARGIN + 140.0, xp_y], BAR_WIDTH, c, g);
+        } else {
+            draw_text("XP MAX", 12, TEXT_COLOR, [MARGIN, xp_y + 10.0], c, g, glyphs);
+        }
+
+        // Attributes
+        let attributes = [
+            ("ATTACK", stats.attack),
+            ("DEFENSE", stats.defense),
+            ("SPEED", stats.speed),
+        ];
+        for (index, (label, value)) in attributes.into_iter().enumerate() {
+            let y = 260.0 + index as f64 * ROW_HEIGHT;
+            draw_text(label, 16, TEXT_COLOR, [MARGIN, y], c, g, glyphs);
+            draw_text(&value.to_string(), 16, TEXT_COLOR, [MARGIN + 160.0, y], c, g, glyphs);
+        }
+
+        draw_text("C: CLOSE", 12, DIM_TEXT_COLOR, [MARGIN, window_size[1] - 30.0], c, g, glyphs);
+    }
+
+    fn handle_input(&mut self, input: &Input) -> Option<ScreenState> {
+        match input {
+            Input::Button(ButtonArgs {
+                state: ButtonState::Press,
+                button: Button::Keyboard(Key::C),
+                ..
+            }) => Some(ScreenState::Game),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Developer map editor. It runs inside the game screen on the map being
+//! played, so what gets painted is what the player walks on. Saving writes a
+//! Tiled map (see `crate::tiled`) using the built-in tile atlas.
+
+use std::fs;
+use std::path::Path;
+use piston::input::Key;
+use crate::ai::{Behaviour, Pace};
+use crate::entity::{Entity, EntityId, Mechanism, Portal, World};
+use crate::map::{self, Layer, TileMap, EMPTY, FLOOR, ROAD};
+use crate::mapgen::{GeneratorKind, MapGen};
+use crate::sprites::{SpriteData, SpriteSheet};
+use crate::tiled::{self, MapObject, TiledMap, TilesetDef};
+use super::game::portal_entity;
+
+/// Extensions tried, in order, when loading a map file by name
+pub const MAP_EXTENSIONS: [&str; 2] = ["tmj", "tmx"];
+// Tile atlas the editor saves maps with
+const EDITOR_ATLAS: &str = "tiles";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tool {
+    /// Paints the brush onto the edited layer
+    Paint,
+    /// Places and picks portals, and removes anything
+    Objects,
+    /// Moves the spawn point
+    Spawn,
+}
+
+/// What painting changes: one of the tile layers, or where walking is blocked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EditLayer {
+    Tiles(Layer),
+    Collision,
+}
+
+impl EditLayer {
+    const ALL: [EditLayer; 4] = [
+        EditLayer::Tiles(Layer::Ground),
+        EditLayer::Tiles(Layer::Decoration),
+        EditLayer::Tiles(Layer::Overhead),
+        EditLayer::Collision,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            EditLayer::Tiles(Layer::Ground) => "ground",
+            EditLayer::Tiles(Layer::Decoration) => "decoration",
+            EditLayer::Tiles(Layer::Overhead) => "overhead",
+            EditLayer::Collision => "collision",
+        }
+    }
+}
+
+pub struct Editor {
+    pub tool: Tool,
+    pub layer: EditLayer,
+    pub brush: u8,
+    /// Portal being edited
+    pub selected: Option<EntityId>,
+    /// Set while a mouse button is held down to paint, true when it erases
+    pub stroke: Option<bool>,
+    /// Map files a portal can lead to, besides a freshly generated map
+    destinations: Vec<String>,
+}
+
+impl Editor {
+    pub fn new(destinations: Vec<String>) -> Self {
+        Editor {
+            tool: Tool::Paint,
+            layer: EditLayer::Tiles(Layer::Ground),
+            brush: FLOOR,
+            selected: None,
+            stroke: None,
+            destinations,
+        }
+    }
+
+    pub fn set_destinations(&mut self, destinations: Vec<String>) {
+        self.destinations = destinations;
+    }
+
+    /// Roofs and canopies would hide what is painted below them, so they
+    /// only show while the overhead layer is being edited.
+    pub fn shows_overhead(&self) -> bool {
+        self.layer == EditLayer::Tiles(Layer::Overhead)
+    }
+
+    pub fn shows_collision(&self) -> bool {
+        self.tool == Tool::Paint && self.layer == EditLayer::Collision
+    }
+
+    /// Paints the brush onto a tile, or clears it when `erase` is set.
+    /// Collision follows the tiles painted on the ground and decoration.
+    pub fn paint(&self, map: &mut TileMap, x: i32, y: i32, erase: bool) {
+        match self.layer {
+            EditLayer::Tiles(layer) => {
+                let tile = match (erase, layer) {
+                    (false, _) => self.brush,
+                    (true, Layer::Ground) => FLOOR,
+                    (true, _) => EMPTY,
+                };
+                map.set_tile(layer, x, y, tile);
+                if layer != Layer::Overhead {
+                    map.update_collision(x, y);
+                }
+            }
+            EditLayer::Collision => map.set_solid(x, y, !erase),
+        }
+    }
+
+    /// Applies the tool to the tile at (`x`, `y`). Erasing clears tiles and
+    /// removes entities. The player can't be picked or removed.
+    pub fn click(
+        &mut self,
+        world: &mut World,
+        map: &mut TileMap,
+        spawn: &mut (i32, i32),
+        player: EntityId,
+        (x, y): (i32, i32),
+        erase: bool,
+    ) {
+        let found = world.iter().find(|&(id, entity)| id != player && entity.is_at(x, y)).map(|(id, _)| id);
+
+        match self.tool {
+            Tool::Paint => {
+                self.stroke = Some(erase);
+                self.paint(map, x, y, erase);
+            }
+            Tool::Objects => match found {
+                Some(id) if erase => {
+                    world.despawn(id);
+                    if self.selected == Some(id) {
+                        self.selected = None;
+                    }
+                }
+                Some(id) => self.selected = Some(id),
+                None if !erase && !map.is_solid(x, y) => {
+                    let mut portal = portal_entity(x, y, (0, 0));
+                    portal.portal = Some(Portal {
+                        destination_map: None,
+                        destination_position: None,
+                        generator: None,
+                    });
+                    self.selected = Some(world.spawn(portal));
+                }
+                None => {}
+            },
+            Tool::Spawn => {
+                if !erase && !map.is_solid(x, y) {
+                    *spawn = (x, y);
+                }
+            }
+        }
+    }
+
+    /// Keeps painting while a mouse button is held down and the cursor moves.
+    pub fn continue_stroke(&self, map: &mut TileMap, x: i32, y: i32) {
+        if let (Tool::Paint, Some(erase)) = (self.tool, self.stroke) {
+            self.paint(map, x, y, erase);
+        }
+    }
+
+    /// Handles the editor's own keys, returning false for any it doesn't use.
+    pub fn handle_key(&mut self, key: Key, world: &mut World) -> bool {
+        match key {
+            Key::D1 => self.tool = Tool::Paint,
+            Key::D2 => self.tool = Tool::Objects,
+            Key::D3 => self.tool = Tool::Spawn,
+            Key::Tab => {
+                let index = EditLayer::ALL.iter().position(|&layer| layer == self.layer).unwrap_or(0);
+                self.layer = EditLayer::ALL[(index + 1) % EditLayer::ALL.len()];
+            }
+            Key::LeftBracket => self.brush = if self.brush > FLOOR { self.brush - 1 } else { ROAD },
+            Key::RightBracket => self.brush = if self.brush < ROAD { self.brush + 1 } else { FLOOR },
+            Key::Comma => self.cycle_destination_map(world, -1),
+            Key::Period => self.cycle_destination_map(world, 1),
+            Key::G => self.cycle_generator(world),
+            Key::R => {
+                if let Some(generator) = self.selected_portal(world).and_then(|portal| portal.generator.as_mut()) {
+                    generator.seed = Some(rand::random());
+                }
+            }
+            Key::W => self.nudge_destination(world, 0, 1),
+            Key::S => self.nudge_destination(world, 0, -1),
+            Key::A => self.nudge_destination(world, -1, 0),
+            Key::D => self.nudge_destination(world, 1, 0),
+            Key::Backspace => {
+                if let Some(portal) = self.selected_portal(world) {
+                    portal.destination_position = None;
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn selected_portal<'a>(&self, world: &'a mut World) -> Option<&'a mut Portal> {
+        self.selected.and_then(|id| world.get_mut(id)).and_then(|entity| entity.portal.as_mut())
+    }
+
+    /// Steps through the map files, with a generated map before the first one.
+    fn cycle_destination_map(&self, world: &mut World, step: i32) {
+        let destinations = &self.destinations;
+        let Some(portal) = self.selected_portal(world) else {
+            return;
+        };
+
+        let count = destinations.len() as i32 + 1;
+        let current = portal
+            .destination_map
+            .as_ref()
+            .and_then(|name| destinations.iter().position(|destination| destination == name))
+            .map_or(0, |index| index as i32 + 1);
+        let next = (current + step).rem_euclid(count);
+        portal.destination_map = (next > 0).then(|| destinations[next as usize - 1].clone());
+        if portal.destination_map.is_some() {
+            portal.generator = None;
+        }
+    }
+
+    /// Steps through the generators for a portal to a generated map, keeping
+    /// its seed. A portal leads to a map file or a generated map, never both.
+    fn cycle_generator(&self, world: &mut World) {
+        let Some(portal) = self.selected_portal(world) else {
+            return;
+        };
+
+        let kinds = GeneratorKind::ALL;
+        let next = match portal.generator {
+            None => Some(0),
+            Some(generator) => kinds.iter().position(|&kind| kind == generator.kind).map(|index| index + 1),
+        };
+        let seed = portal.generator.and_then(|generator| generator.seed);
+        portal.generator = next.filter(|&index| index < kinds.len()).map(|index| MapGen { kind: kinds[index], seed });
+        if portal.generator.is_some() {
+            portal.destination_map = None;
+        }
+    }
+
+    fn nudge_destination(&self, world: &mut World, dx: i32, dy: i32) {
+        if let Some(portal) = self.selected_portal(world) {
+            let (x, y) = portal.destination_position.unwrap_or((0, 0));
+            portal.destination_position = Some((x + dx, y + dy));
+        }
+    }
+
+    /// Help and state of the current tool, one line each.
+    pub fn status(&self, world: &World) -> Vec<String> {
+        let mut lines = vec!["EDITOR  1 paint  2 objects  3 spawn  F6 save  F2 leave".to_string()];
+        lines.push(match self.tool {
+            Tool::Paint => format!(
+                "PAINT  layer {} (Tab)  brush {} ([ ])",
+                self.layer.name(),
+                map::tile_name(self.brush).unwrap_or("?")
+            ),
+            Tool::Objects => "OBJECTS  click places or picks a portal, right click removes".to_string(),
+            Tool::Spawn => "SPAWN  click to move the spawn point".to_string(),
+        });
+
+        let portal = self.selected.and_then(|id| world.get(id)).and_then(|entity| entity.portal.as_ref());
+        if let (Tool::Objects, Some(portal)) = (self.tool, portal) {
+            let map = match (&portal.destination_map, portal.generator) {
+                (Some(name), _) => name.clone(),
+                (None, Some(MapGen { kind, seed: Some(seed) })) => format!("{} #{}", kind.name(), seed),
+                (None, Some(MapGen { kind, seed: None })) => format!("{}, any seed", kind.name()),
+                (None, None) => "new area".to_string(),
+            };
+            let position = portal
+                .destination_position
+                .map_or("spawn".to_string(), |(x, y)| format!("{}, {}", x, y));
+            lines.push(format!("PORTAL  to {} (, . or G, R seed)  at {} (WASD, Backspace)", map, position));
+        }
+        lines
+    }
+}
+
+/// Names of the map files in the maps folder, sorted.
+pub fn map_names(maps_dir: &Path) -> Vec<String> {
+    let entries = match fs::read_dir(maps_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Could not list the maps in {:?}: {}", maps_dir, e);
+            return Vec::new();
+        }
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| MAP_EXTENSIONS.contains(&extension))
+        })
+        .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The first `map_N` not taken in the maps folder, for a generated map
+/// saved for the first time.
+pub fn unused_map_name(maps_dir: &Path) -> String {
+    let names = map_names(maps_dir);
+    let mut number = 1;
+    while names.contains(&format!("map_{}", number)) {
+        number += 1;
+    }
+    format!("map_{}", number)
+}
+
+/// Writes a map to the file called `name` in the maps folder, with the
+/// built-in tile atlas as its tileset.
+pub fn save_map(maps_dir: &Path, name: &str, map: &TiledMap, sprites: &SpriteSheet) -> Result<(), String> {
+    let path = maps_dir.join(format!("{}.{}", name, MAP_EXTENSIONS[0]));
+    tiled::save(&path, map, &tileset(sprites)?)
+}
+
+/// The built-in tile atlas as a Tiled tileset, with its image found from
+/// the maps folder.
+fn tileset(sprites: &SpriteSheet) -> Result<TilesetDef, String> {
+    let data = SpriteData::builtin();
+    let atlas = data
+        .atlases
+        .iter()
+        .find(|atlas| atlas.id == EDITOR_ATLAS)
+        .ok_or_else(|| format!("no '{}' atlas in the built-in sprites", EDITOR_ATLAS))?;
+    let (columns, rows) = sprites
+        .atlas_grid(EDITOR_ATLAS)
+        .ok_or_else(|| format!("the image of the '{}' atlas didn't load", EDITOR_ATLAS))?;
+    let frames = data
+        .sprites
+        .iter()
+        .filter(|sprite| sprite.atlas == EDITOR_ATLAS)
+        .filter_map(|sprite| map::tile_from_name(&sprite.id).map(|tile| (tile, sprite.frame)))
+        .collect();
+
+    Ok(TilesetDef {
+        name: EDITOR_ATLAS.to_string(),
+        image: format!("../{}", atlas.image),
+        tile_width: atlas.frame_width,
+        tile_height: atlas.frame_height,
+        columns,
+        rows,
+        frames,
+    })
+}
+
+/// What a map file records of everything in `world` but the player.
+pub fn map_objects(world: &World, player: EntityId) -> Vec<((i32, i32), MapObject)> {
+    world
+        .iter()
+        .filter(|&(id, _)| id != player)
+        .filter_map(|(_, entity)| entity_object(entity).map(|object| ((entity.position.x, entity.position.y), object)))
+        .collect()
+}
+
+/// The map object an entity is saved as, the inverse of the game screen's
+/// `object_entity`. Things the map file can't describe are left out.
+fn entity_object(entity: &Entity) -> Option<MapObject> {
+    let on_interact = entity.hooks.as_ref().and_then(|hooks| hooks.on_interact.clone());
+
+    if let Some(mechanism) = &entity.mechanism {
+        let link = mechanism.link().to_string();
+        Some(match mechanism {
+            Mechanism::Plate { .. } => MapObject::Plate { link },
+            Mechanism::Lever { .. } => MapObject::Lever { link },
+            Mechanism::Gate { .. } => MapObject::Gate { link },
+            Mechanism::Bridge { .. } => MapObject::Bridge { link },
+        })
+    } else if let (Some(_), Some(prop)) = (&entity.pushable, &entity.prop) {
+        Some(MapObject::Crate { kind: prop.kind.clone() })
+    } else if let Some(portal) = &entity.portal {
+        Some(MapObject::Portal {
+            destination_map: portal.destination_map.clone(),
+            destination_position: portal.destination_position,
+            generator: portal.generator,
+            on_interact,
+        })
+    } else if let Some(npc) = &entity.npc {
+        Some(MapObject::Npc {
+            name: npc.name.clone(),
+            dialogue: npc.dialogue.clone(),
+            on_interact,
+            schedule: match entity.ai.as_ref().map(|ai| &ai.behaviour) {
+                Some(Behaviour::Schedule { stops }) => stops.clone(),
+                _ => Vec::new(),
+            },
+        })
+    } else if let Some(pickup) = &entity.pickup {
+        Some(MapObject::Pickup {
+            item: pickup.item.clone(),
+            count: pickup.count,
+        })
+    } else if let Some(door) = &entity.door {
+        Some(MapObject::Door {
+            open: door.open,
+            lock: door.lock.clone(),
+        })
+    } else if let Some(prop) = &entity.prop {
+        Some(MapObject::Prop {
+            kind: prop.kind.clone(),
+            solid: entity.collider.is_some(),
+        })
+    } else if let (Some(foe), Some(ai)) = (&entity.foe, &entity.ai) {
+        match (&ai.behaviour, &ai.pace) {
+            (Behaviour::Patrol { .. }, _) | (_, Pace::Turn) => None,
+            (behaviour, Pace::Timer { interval }) => Some(MapObject::Foe {
+                enemies: foe.enemies.clone(),
+                behaviour: behaviour.clone(),
+                interval: *interval,
+            }),
+        }
+    } else {
+        None
+    }
+}
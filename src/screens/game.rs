@@ -1,354 +1,1961 @@
     use graphics::*;
     use opengl_graphics::{GlGraphics, GlyphCache};
     use piston::input::*;
-    use crate::screens::{Screen, ScreenState};
+    use crate::screens::{draw_text, Screen, ScreenState};
+    use super::editor::{self, Editor, MAP_EXTENSIONS};
+    use super::houses::{self, HOUSE_KEY};
     use super::popup::Popup;
+    use crate::ai::{self, Ai, Behaviour, Pace};
+    use crate::animation::{AnimationDb, Animator, Pose};
+    use crate::combat::{Battle, Outcome};
+    use crate::cutscene::{Actor, CutsceneHost, CutsceneLibrary, CutscenePlayer};
+    use crate::doors;
+    use crate::encounters::{EncounterRoller, EncounterZone};
+    use crate::entity::{
+        Direction, Door, Doorway, Entity, EntityId, Foe, Mechanism, Npc, Pickup, Portal, Prop, Sprite, World,
+    };
+    use crate::fov::{self, FogOfWar, Visibility};
+    use crate::interact::{self, InteractHost};
+    use crate::items::ItemStack;
+    use crate::map::{self, Layer, TileMap, CANOPY, FLOOR, FLOWERS, ROAD, ROOF, TALL_GRASS, TREE, WALL, WATER};
+    use crate::mapgen::{GeneratorKind, House, Layout, MapGen};
+    use crate::mechanisms;
+    use crate::pathfinding;
+    use crate::quests::{QuestEvent, QuestUpdate};
+    use crate::save::{self, MapMemories, MapMemory, SaveData, StashedMap};
+    use crate::state::SharedState;
+    use crate::sprites::{SpriteData, SpriteSheet};
+    use crate::tiled::{self, MapObject, TiledMap};
+    use crate::script::{Hooks, MapScripts, ScriptHost, ScriptLibrary, Value, MAX_CHAINED_SCRIPTS};
+    use crate::triggers::{Slide, Trigger, TriggerAction, MAX_CHAINED_TRIGGERS};
+    use rand::rngs::StdRng;
     use rand::Rng;
+    use std::collections::{HashMap, HashSet, VecDeque};
+    use std::path::PathBuf;
+    use std::rc::Rc;
+    use std::time::Instant;
 
-    const GRID_MIN: i32 = -20;
-    const GRID_MAX: i32 = 20;
     const POINT_SIZE: f64 = 5.0;
     const GRID_LINE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];    // White
     const PLAYER_COLOR: [f32; 4] = [1.0, 0.0, 0.0, 1.0];       // Red
     const OBSTACLE_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];     // White for obstacles
+    const GRASS_COLOR: [f32; 4] = [0.2, 0.6, 0.2, 1.0];        // Dark green for tall grass
+    const WATER_COLOR: [f32; 4] = [0.2, 0.4, 0.9, 1.0];        // Blue for water
+    const FLOWER_COLOR: [f32; 4] = [0.9, 0.5, 0.7, 1.0];       // Pink for flowers
+    const TREE_COLOR: [f32; 4] = [0.5, 0.3, 0.1, 1.0];         // Brown for tree trunks
+    const ROOF_COLOR: [f32; 4] = [0.6, 0.2, 0.15, 1.0];        // Red-brown for roofs
+    const CANOPY_COLOR: [f32; 4] = [0.1, 0.4, 0.15, 0.9];      // Dark green for tree crowns
+    const ROAD_COLOR: [f32; 4] = [0.55, 0.45, 0.3, 1.0];       // Packed earth for roads
+    const TORCH_COLOR: [f32; 4] = [1.0, 0.5, 0.1, 1.0];        // Orange for torches
     const INTERACTABLE_COLOR: [f32; 4] = [1.0, 1.0, 0.0, 1.0]; // Yellow for interactables
-    const TRIANGLE_SIZE: f64 = POINT_SIZE * 1.8;
-    const TRIANGLE_INSET: f64 = POINT_SIZE * 0.2;
+    const NPC_COLOR: [f32; 4] = [0.3, 0.6, 1.0, 1.0];          // Blue for NPCs
+    const PICKUP_COLOR: [f32; 4] = [0.2, 0.9, 0.3, 1.0];       // Green for items on the ground
+    const FOE_COLOR: [f32; 4] = [0.7, 0.3, 0.8, 1.0];          // Purple for roaming enemies
+    const DOOR_COLOR: [f32; 4] = [0.45, 0.3, 0.15, 1.0];       // Dark wood for doors
+    const CRATE_COLOR: [f32; 4] = [0.75, 0.55, 0.3, 1.0];      // Light wood for crates
+    const PLATE_COLOR: [f32; 4] = [0.5, 0.5, 0.55, 1.0];       // Grey for pressure plates
+    const LEVER_COLOR: [f32; 4] = [0.9, 0.75, 0.2, 1.0];       // Brass for levers
+    const HOVER_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 0.6];        // Outline of the tile under the cursor
+    const SELECTED_COLOR: [f32; 4] = [1.0, 0.9, 0.2, 1.0];     // Outline of the entity picked in the editor
+    const SOLID_OVERLAY_COLOR: [f32; 4] = [1.0, 0.1, 0.1, 0.35]; // Blocked tiles while editing collision
+    const SPAWN_COLOR: [f32; 4] = [0.2, 1.0, 1.0, 1.0];        // Spawn point in the editor
+    const REMEMBERED_FOG_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.6]; // Dims tiles out of sight
+    const UNKNOWN_FOG_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];    // Hides tiles never seen
+    const MAP_PANEL_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.8];      // Behind the minimap and full map
+    const MAP_FLOOR_COLOR: [f32; 4] = [0.25, 0.25, 0.25, 1.0];   // Explored floor on the minimap
+    // Brightness of remembered tiles on the minimap, relative to visible ones
+    const MAP_REMEMBERED_SHADE: f32 = 0.5;
+    const MINIMAP_SIZE: f64 = 150.0;
+    const MINIMAP_MARGIN: f64 = 10.0;
+    // Arrow sizes relative to the point size
+    const TRIANGLE_SIZE: f64 = 1.8;
+    const TRIANGLE_INSET: f64 = 0.2;
     const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
     const TEXT_POS_X: f64 = 20.0;
     const TEXT_POS_Y: f64 = 30.0;
-    const MAP_WIDTH: usize = (GRID_MAX - GRID_MIN + 1) as usize;
-    const MAP_HEIGHT: usize = (GRID_MAX - GRID_MIN + 1) as usize;
-
-    #[derive(Clone, Copy, PartialEq)]
-    enum Direction {
-        Up,
-        Down,
-        Left,
-        Right,
+    const DEFAULT_GRID_SCALE: f64 = 30.0;
+    const MIN_GRID_SCALE: f64 = 12.0;
+    const MAX_GRID_SCALE: f64 = 80.0;
+    const ZOOM_FACTOR: f64 = 1.15;
+    // Pixels the camera moves per key press in free-look mode
+    const PAN_STEP: f64 = 60.0;
+
+    const GRASS_ENCOUNTERS: &str = "grassland";
+    // Tall grass is slow going, so paths prefer to go around it
+    const TALL_GRASS_COST: u32 = 3;
+    // How far the player can see, in tiles
+    const SIGHT_RADIUS: i32 = 8;
+    // Length of the interact pose when there is no animation saying otherwise
+    const INTERACT_POSE_LENGTH: f64 = 0.3;
+
+    // Longest time step handed to timed AI, so a long pause doesn't look like a long wait
+    const MAX_TICK: f64 = 0.25;
+    // Moves a roaming enemy sits out after the player escaped from it
+    const FOE_REST_AFTER_FLEE: u32 = 6;
+    // Seconds between steps when walking to a clicked tile
+    const WALK_INTERVAL: f64 = 0.12;
+    // Seconds between steps of an NPC walking to the next stop of its schedule
+    const SCHEDULE_WALK_INTERVAL: f64 = 0.5;
+    // Seconds between tiles when carried along by a conveyor or ice
+    const SLIDE_INTERVAL: f64 = 0.08;
+    // Map file one of the portals on a generated map leads to
+    const GROVE_MAP: &str = "grove";
+    // Height above the bottom of the window of the hint for skipping a cutscene
+    const SKIP_HINT_OFFSET: f64 = 100.0;
+
+    pub struct GameScreen {
+        state: SharedState,
+        sprites: Rc<SpriteSheet>,
+        // Tileset art of a map loaded from a file, drawn in place of the built-in tiles
+        map_sprites: Option<SpriteSheet>,
+        maps_dir: PathBuf,
+        map_name: Option<String>,
+        // Where the player starts on the map when arriving without a destination
+        spawn: (i32, i32),
+        // The map outside while the player is in a house
+        outside: Option<StashedMap>,
+        memories: MapMemories,
+        editor: Option<Editor>,
+        animations: AnimationDb,
+        animators: HashMap<EntityId, Animator>,
+        // Seconds since the screen was created, for animations that just run
+        clock: f64,
+        world: World,
+        player: EntityId,
+        map: TileMap,
+        grid_scale: f64,
+        popups: Vec<Popup>,
+        camera_position: (f64, f64),
+        /// How far the camera sits from centred on the player while following
+        /// them, so zooming can keep the point under the cursor in place
+        follow_offset: (f64, f64),
+        scripts: Rc<ScriptLibrary>,
+        map_scripts: MapScripts,
+        encounter_zones: Vec<EncounterZone>,
+        encounters: EncounterRoller,
+        fog: FogOfWar,
+        pending_scripts: VecDeque<String>,
+        cutscenes: CutsceneLibrary,
+        // The cutscene playing, which has control instead of the player
+        cutscene: Option<CutscenePlayer>,
+        next_screen: Option<ScreenState>,
+        last_tick: Instant,
+        engaged_foe: Option<EntityId>,
+        cursor: Option<[f64; 2]>,
+        window_size: [f64; 2],
+        free_look: bool,
+        show_full_map: bool,
+        /// Whether the HUD shows the time instead of the way the player faces
+        show_clock: bool,
+        walk_path: VecDeque<(i32, i32)>,
+        walk_elapsed: f64,
+        // Way the player is being carried by a conveyor or ice, out of their control
+        sliding: Option<Direction>,
+        slide_elapsed: f64,
     }
 
-    impl Direction {
-        fn to_string(&self) -> &'static str {
-            match self {
-                Direction::Up => "FACING: UP",
-                Direction::Down => "FACING: DOWN",
-                Direction::Left => "FACING: LEFT",
-                Direction::Right => "FACING: RIGHT",
+    impl GameScreen {
+        pub fn new(state: SharedState, sprites: Rc<SpriteSheet>, maps_dir: PathBuf) -> Self {
+            let (layout, mut rng) = MapGen::default().generate();
+            let (spawn_x, spawn_y) = layout.spawn;
+            let mut world = World::new();
+            let player = world.spawn(
+                Entity::new(spawn_x, spawn_y)
+                    .with_facing(Direction::Right)
+                    .with_sprite(Sprite::Arrow { color: PLAYER_COLOR })
+                    .with_collider(),
+            );
+            populate_world(&mut world, &layout, &mut rng);
+
+            GameScreen {
+                state,
+                sprites,
+                map_sprites: None,
+                maps_dir,
+                map_name: None,
+                spawn: layout.spawn,
+                outside: None,
+                memories: MapMemories::new(),
+                editor: None,
+                animations: AnimationDb::builtin(),
+                animators: HashMap::new(),
+                clock: 0.0,
+                world,
+                player,
+                map_scripts: generate_map_scripts(&layout.houses),
+                encounter_zones: generate_encounter_zones(&layout.map),
+                map: layout.map,
+                grid_scale: DEFAULT_GRID_SCALE,
+                popups: Vec::new(),
+                camera_position: (0.0, 0.0),
+                follow_offset: (0.0, 0.0),
+                scripts: Rc::new(ScriptLibrary::builtin()),
+                encounters: EncounterRoller::new(rand::thread_rng().gen()),
+                fog: FogOfWar::default(),
+                pending_scripts: VecDeque::new(),
+                cutscenes: CutsceneLibrary::builtin(),
+                cutscene: None,
+                next_screen: None,
+                last_tick: Instant::now(),
+                engaged_foe: None,
+                cursor: None,
+                window_size: [0.0, 0.0],
+                free_look: false,
+                show_full_map: false,
+                show_clock: false,
+                walk_path: VecDeque::new(),
+                walk_elapsed: 0.0,
+                sliding: None,
+                slide_elapsed: 0.0,
+            }
+        }
+
+        fn player(&self) -> &Entity {
+            self.world.get(self.player).expect("Player entity missing from world.")
+        }
+
+        fn player_mut(&mut self) -> &mut Entity {
+            self.world.get_mut(self.player).expect("Player entity missing from world.")
+        }
+
+        fn is_within_bounds(&self, x: i32, y: i32) -> bool {
+            self.map.contains(x, y)
+        }
+
+        fn is_obstacle(&self, x: i32, y: i32) -> bool {
+            self.is_obstacle_for(x, y, self.player)
+        }
+
+        /// Whether `mover` is kept off the tile by a wall or another solid entity.
+        fn is_obstacle_for(&self, x: i32, y: i32, mover: EntityId) -> bool {
+            // The collision layer covers walls, water, trees and everything off the map
+            if self.map.is_solid(x, y) {
+                return true;
+            }
+
+            // Solid entities other than the mover block movement too
+            self.world.is_blocked(x, y, Some(mover))
+        }
+
+        /// Recomputes what the player can see from where they stand.
+        fn update_fov(&mut self) {
+            let position = self.player().position;
+            let closed_doors: HashSet<(i32, i32)> = self
+                .world
+                .iter()
+                .filter(|(_, entity)| entity.door.as_ref().is_some_and(|door| !door.open))
+                .map(|(_, entity)| (entity.position.x, entity.position.y))
+                .collect();
+            let visible = fov::visible_tiles((position.x, position.y), SIGHT_RADIUS, |x, y| {
+                self.map.blocks_sight(x, y) || closed_doors.contains(&(x, y))
+            });
+            self.fog.update(visible);
+        }
+
+        /// Path cost for `mover` to step onto a tile, `None` if it can't.
+        fn tile_cost(&self, x: i32, y: i32, mover: EntityId) -> Option<u32> {
+            if !self.is_within_bounds(x, y) || self.is_obstacle_for(x, y, mover) {
+                return None;
+            }
+
+            match self.map.ground(x, y) {
+                TALL_GRASS => Some(TALL_GRASS_COST),
+                _ => Some(1),
+            }
+        }
+
+        fn try_move_player(&mut self, dx: i32, dy: i32) {
+            // There is nothing to push off from while sliding
+            if self.sliding.is_some() {
+                return;
+            }
+
+            // Update facing direction based on movement attempt
+            let player = self.player_mut();
+            player.facing = Some(
+                Direction::from_delta(dx, dy).unwrap_or(player.facing.unwrap_or(Direction::Right)),
+            );
+
+            let new_x = player.position.x + dx;
+            let new_y = player.position.y + dy;
+
+            // Check if new position is within map bounds
+            if !self.is_within_bounds(new_x, new_y) {
+                self.show_boundary_message();
+                return;
+            }
+
+            // Walking into a roaming enemy starts a fight with it
+            if let Some(foe) = self.world.find_at(new_x, new_y, |entity| entity.foe.is_some()) {
+                self.engage_foe(foe);
+                return;
+            }
+
+            // Walking into a crate shoves it along, if there is room behind it
+            if let Some(id) = self.world.find_at(new_x, new_y, |entity| entity.pushable.is_some()) {
+                self.push(id, dx, dy);
+            }
+
+            // Check if the new position is occupied by an obstacle
+            if self.is_obstacle(new_x, new_y) {
+                self.show_boundary_message();
+                return;
+            }
+
+            if let Some(direction) = Direction::from_delta(dx, dy) {
+                self.step_player(direction);
+            }
+        }
+
+        /// Moves a pushable entity one tile, unless something solid is in the way.
+        /// It can't be pushed through doorways either, where it would be stuck.
+        fn push(&mut self, id: EntityId, dx: i32, dy: i32) {
+            let Some(entity) = self.world.get(id) else {
+                return;
+            };
+            let (x, y) = (entity.position.x + dx, entity.position.y + dy);
+            let doorway = self.world.find_at(x, y, |entity| entity.doorway.is_some());
+            if self.is_obstacle_for(x, y, id) || doorway.is_some() {
+                return;
+            }
+            if let Some(entity) = self.world.get_mut(id) {
+                entity.position.x = x;
+                entity.position.y = y;
+            }
+        }
+
+        /// Moves the player onto the next tile, which is known to be free, and
+        /// sets off whatever is there.
+        fn step_player(&mut self, direction: Direction) {
+            let (dx, dy) = direction.delta();
+            let player = self.player_mut();
+            let (old_x, old_y) = (player.position.x, player.position.y);
+            let (new_x, new_y) = (old_x + dx, old_y + dy);
+            player.position.x = new_x;
+            player.position.y = new_y;
+
+            self.queue_step_scripts(old_x, old_y, new_x, new_y);
+            if self.fire_trigger(new_x, new_y, direction, 0) {
+                // The player woke up on a new map, where the rest of this step
+                // means nothing. Only its arrival script is left to run.
+                self.run_pending_scripts();
+                return;
+            }
+
+            // Unless a trigger took the player elsewhere, a doorway leads on
+            let position = self.player().position;
+            let doorway = self.world.find_at(new_x, new_y, |entity| entity.doorway.is_some());
+            if let (Some(doorway), true) = (doorway, (position.x, position.y) == (new_x, new_y)) {
+                self.use_doorway(doorway);
+            }
+            self.run_pending_scripts();
+            self.report_position();
+
+            // Scripts may already have started a fight or moved the player
+            if self.next_screen.is_none() {
+                self.check_encounter();
+            }
+
+            // Everything that moves in turns follows the player's step
+            self.move_npcs(None);
+        }
+
+        /// Handles a left click on a tile: interacts with an adjacent interactable,
+        /// otherwise walks the player there along the cheapest path.
+        fn click_tile(&mut self, x: i32, y: i32) {
+            self.walk_path.clear();
+            let player = self.player().position;
+            let (dx, dy) = (x - player.x, y - player.y);
+
+            if let Some(direction) = Direction::from_delta(dx, dy) {
+                if self.world.find_at(x, y, |entity| entity.interactable.is_some()).is_some() {
+                    self.player_mut().facing = Some(direction);
+                    self.try_interact();
+                    return;
+                }
+            }
+
+            if (dx, dy) == (0, 0) {
+                return;
+            }
+            match pathfinding::find_path((player.x, player.y), (x, y), |x, y| self.tile_cost(x, y, self.player)) {
+                Some(path) => {
+                    self.walk_path = path.into();
+                    // Take the first step right away
+                    self.walk_elapsed = WALK_INTERVAL;
+                }
+                None => self.popups.push(Popup::new_text_box("You can't get there.".to_string(), 2.0)),
+            }
+        }
+
+        /// Takes the next step along the clicked path once enough time has passed.
+        fn follow_walk_path(&mut self, dt: f64) {
+            if self.walk_path.is_empty() {
+                return;
+            }
+            self.walk_elapsed += dt;
+            if self.walk_elapsed < WALK_INTERVAL {
+                return;
+            }
+            self.walk_elapsed = 0.0;
+
+            let Some((x, y)) = self.walk_path.pop_front() else {
+                return;
+            };
+            let player = self.player().position;
+            self.try_move_player(x - player.x, y - player.y);
+
+            // Stop if the step was blocked, or a script or battle took over
+            if !self.player().is_at(x, y) || self.next_screen.is_some() {
+                self.walk_path.clear();
+            }
+        }
+
+        /// Lets entities with AI take their move: turn-based ones when `dt` is
+        /// `None`, timed ones when their timer runs out.
+        fn move_npcs(&mut self, dt: Option<f64>) {
+            let player = self.player().position;
+            let minute = self.state.borrow().clock.minute_of_day();
+            let movers: Vec<EntityId> = self
+                .world
+                .iter()
+                .filter(|(_, entity)| entity.ai.is_some())
+                .map(|(id, _)| id)
+                .collect();
+            let mut rng = rand::thread_rng();
+
+            for id in movers {
+                if self.next_screen.is_some() {
+                    return;
+                }
+
+                // Take the AI out so the world can be checked while it decides
+                let Some(mut ai) = self.world.get_mut(id).and_then(|entity| entity.ai.take()) else {
+                    continue;
+                };
+                let entity = self.world.get(id).expect("Moving entity missing from world.");
+                let position = entity.position;
+                let is_foe = entity.foe.is_some();
+
+                let moves = match dt {
+                    Some(dt) => ai.tick(dt),
+                    None => ai.pace == Pace::Turn,
+                };
+                let mut caught = false;
+                let mut step = None;
+                if moves {
+                    caught = is_foe && ai.has_caught(position, player);
+                    if !caught {
+                        step = ai.next_step(position, player, minute, &mut rng, |x, y| self.tile_cost(x, y, id));
+                    }
+                }
+
+                let entity = self.world.get_mut(id).expect("Moving entity missing from world.");
+                entity.ai = Some(ai);
+                if let Some(direction) = step {
+                    let (dx, dy) = direction.delta();
+                    entity.position.x += dx;
+                    entity.position.y += dy;
+                    entity.facing = Some(direction);
+                }
+
+                if caught {
+                    self.engage_foe(id);
+                }
+            }
+        }
+
+        /// Starts the battle against a roaming enemy, remembering it for the outcome.
+        fn engage_foe(&mut self, id: EntityId) {
+            let Some(foe) = self.world.get(id).and_then(|entity| entity.foe.clone()) else {
+                return;
+            };
+
+            self.start_battle(&foe.enemies);
+            if self.next_screen == Some(ScreenState::Battle) {
+                self.engaged_foe = Some(id);
+            }
+        }
+
+        /// Rolls for a random battle on the player's tile. Tile tables take
+        /// precedence over zone tables.
+        fn check_encounter(&mut self) {
+            let position = self.player().position;
+            let table_id = if self.map.ground(position.x, position.y) == TALL_GRASS {
+                Some(GRASS_ENCOUNTERS)
+            } else {
+                self.encounter_zones
+                    .iter()
+                    .find(|zone| zone.contains(position.x, position.y))
+                    .map(|zone| zone.table.as_str())
+            };
+            let Some(table_id) = table_id else {
+                return;
+            };
+
+            let enemies = {
+                let state = self.state.borrow();
+                match state.encounter_tables.get(table_id) {
+                    Some(table) => self.encounters.step(table),
+                    None => {
+                        eprintln!("Unknown encounter table '{}'", table_id);
+                        None
+                    }
+                }
+            };
+
+            if let Some(enemies) = enemies {
+                self.start_battle(&enemies);
+            }
+        }
+
+        /// Queues the on-step hooks of the entities under the player, and the
+        /// on-enter hooks of any region the step moved into.
+        fn queue_step_scripts(&mut self, old_x: i32, old_y: i32, new_x: i32, new_y: i32) {
+            for (id, entity) in self.world.iter() {
+                if id != self.player && entity.is_at(new_x, new_y) {
+                    if let Some(name) = entity.hooks.as_ref().and_then(|h| h.on_step.as_ref()) {
+                        self.pending_scripts.push_back(name.clone());
+                    }
+                }
+            }
+
+            for region in &self.map_scripts.regions {
+                if region.contains(new_x, new_y) && !region.contains(old_x, old_y) {
+                    self.pending_scripts.push_back(region.on_enter.clone());
+                }
+            }
+        }
+
+        /// Sets off the trigger on the tile the player just got onto, if its
+        /// conditions allow. `heading` is the way the player was going, which
+        /// ice keeps them going. Triggers that put the player somewhere else
+        /// set off the trigger there in turn, `chained` counting how many did.
+        /// Returns true if a trigger knocked the player out, leaving them on a new map.
+        fn fire_trigger(&mut self, x: i32, y: i32, heading: Direction, chained: usize) -> bool {
+            let state = self.state.borrow();
+            let trigger = self.map_scripts.tiles.get_mut(&(x, y));
+            let Some(trigger) = trigger.filter(|trigger| trigger.is_armed(&state.flags)) else {
+                return false;
+            };
+            if chained == MAX_CHAINED_TRIGGERS {
+                eprintln!("Too many chained triggers, stopping at ({}, {})", x, y);
+                return false;
+            }
+            trigger.fired = true;
+            let action = trigger.action.clone();
+            drop(state);
+
+            match action {
+                TriggerAction::Script(name) => self.pending_scripts.push_back(name),
+                TriggerAction::Warp { map, position } => {
+                    let map_before = self.map_name.clone();
+                    match (map, position) {
+                        (Some(name), position) => self.enter_map(&name, position),
+                        (None, Some((to_x, to_y))) if !self.is_obstacle(to_x, to_y) => self.move_player(to_x, to_y),
+                        (None, _) => {
+                            eprintln!("Trigger at ({}, {}) warps nowhere the player can stand", x, y);
+                            return false;
+                        }
+                    }
+                    // A map that failed to load leaves the player where they were
+                    let position = self.player().position;
+                    if (position.x, position.y) != (x, y) || self.map_name != map_before {
+                        return self.fire_trigger(position.x, position.y, heading, chained + 1);
+                    }
+                }
+                TriggerAction::Damage(amount) => return !self.hurt_player(amount),
+                TriggerAction::Slide(slide) => {
+                    self.sliding = Some(match slide {
+                        Slide::Conveyor(direction) => direction,
+                        Slide::Ice => heading,
+                    });
+                    self.slide_elapsed = 0.0;
+                    self.walk_path.clear();
+                }
+            }
+            false
+        }
+
+        /// Carries a sliding player on a tile at a time, until they reach
+        /// ground that doesn't slide or run into something.
+        fn slide_player(&mut self, dt: f64) {
+            let Some(direction) = self.sliding else {
+                return;
+            };
+            self.slide_elapsed += dt;
+            if self.slide_elapsed < SLIDE_INTERVAL {
+                return;
+            }
+
+            // Stepping onto more sliding ground keeps the slide going
+            self.sliding = None;
+            let position = self.player().position;
+            let (dx, dy) = direction.delta();
+            let (x, y) = (position.x + dx, position.y + dy);
+            if self.is_within_bounds(x, y) && !self.is_obstacle(x, y) {
+                self.step_player(direction);
+            }
+        }
+
+        /// Hurts the player outside of battle. Running out of HP ends the same
+        /// way as losing a battle. Returns whether the player is still standing.
+        fn hurt_player(&mut self, amount: i32) -> bool {
+            let mut state = self.state.borrow_mut();
+            state.player.take_damage(amount);
+            let alive = state.player.is_alive();
+            if !alive {
+                state.player.hp = state.player.stats.max_hp;
+            }
+            drop(state);
+
+            if alive {
+                self.show_popup(&format!("Ouch! You take {} damage.", amount), 1.0);
+            } else {
+                // Scripts queued on the old map have nothing left to act on
+                self.pending_scripts.clear();
+                self.generate_new_map(None, None);
+                self.popups.push(Popup::new_text_box(
+                    "You collapse, and wake up somewhere unfamiliar.".to_string(),
+                    3.0,
+                ));
+            }
+            alive
+        }
+
+        /// Runs queued scripts in order, including any they queue themselves.
+        fn run_pending_scripts(&mut self) {
+            let library = Rc::clone(&self.scripts);
+            let mut executed = 0;
+
+            while let Some(name) = self.pending_scripts.pop_front() {
+                if executed == MAX_CHAINED_SCRIPTS {
+                    eprintln!("Too many chained scripts, dropping '{}' and the rest", name);
+                    self.pending_scripts.clear();
+                    break;
+                }
+                library.run(&name, self);
+                executed += 1;
+            }
+        }
+
+        fn try_interact(&mut self) {
+            self.play_interact_pose();
+
+            // Calculate the point in front of the player based on facing direction
+            let player = self.player();
+            let (dx, dy) = player.facing.unwrap_or(Direction::Right).delta();
+            let target_x = player.position.x + dx;
+            let target_y = player.position.y + dy;
+
+            // Check if there's an interactable entity at the target position
+            let target = self
+                .world
+                .find_at(target_x, target_y, |entity| entity.interactable.is_some())
+                .and_then(|id| self.world.get(id).map(|entity| (id, entity.clone())));
+
+            let Some((target_id, target)) = target else {
+                // Show a message if there's nothing to interact with
+                self.popups.push(Popup::new_text_box(
+                    "Nothing to interact with".to_string(),
+                    2.0,
+                ));
+                return;
+            };
+
+            interact::interact(target_id, &target, self);
+            self.run_pending_scripts();
+        }
+
+        fn play_interact_pose(&mut self) {
+            let player = self.player();
+            let facing = player.facing.unwrap_or(Direction::Right);
+            let position = player.position;
+            let length = self
+                .animations
+                .get(&format!("player_interact_{}", facing.name()))
+                .map_or(INTERACT_POSE_LENGTH, |animation| animation.length());
+
+            self.animators
+                .entry(self.player)
+                .or_insert_with(|| Animator::new(position))
+                .interact(length);
+        }
+
+        /// Moves the animation clocks on and follows entities to where they are now.
+        fn update_animations(&mut self, dt: f64) {
+            self.clock += dt;
+            self.animators.retain(|id, _| self.world.get(*id).is_some());
+            for (id, entity) in self.world.iter() {
+                self.animators
+                    .entry(id)
+                    .or_insert_with(|| Animator::new(entity.position))
+                    .update(entity.position, dt);
+            }
+        }
+
+        /// Sprite showing for an entity of `kind` right now: the current frame
+        /// of the most specific animation defined for what it is doing, such as
+        /// `player_walk_up`, `npc_walk` or just `torch`, or else its still
+        /// sprite, such as `player_up` or `npc`.
+        fn entity_sprite(&self, id: EntityId, entity: &Entity, kind: &str) -> String {
+            let animator = self.animators.get(&id);
+            let pose = animator.map_or(Pose::Idle, |animator| animator.pose());
+            let facing = entity.facing.map(|direction| direction.name());
+
+            let mut candidates = Vec::new();
+            for candidate_pose in [pose, Pose::Idle] {
+                if let Some(facing) = facing {
+                    candidates.push((format!("{}_{}_{}", kind, candidate_pose.name(), facing), candidate_pose));
+                }
+                candidates.push((format!("{}_{}", kind, candidate_pose.name()), candidate_pose));
+            }
+
+            for (name, candidate_pose) in candidates {
+                if let Some(animation) = self.animations.get(&name) {
+                    // Idle animations standing in for a missing pose just run on the clock
+                    let time = match animator {
+                        Some(animator) if candidate_pose == pose => animator.elapsed(),
+                        _ => self.clock,
+                    };
+                    return animation.sprite_at(time).to_string();
+                }
+            }
+            if let Some(animation) = self.animations.get(kind) {
+                return animation.sprite_at(self.clock).to_string();
+            }
+
+            match facing {
+                Some(facing) if self.sprites.has(&format!("{}_{}", kind, facing)) => format!("{}_{}", kind, facing),
+                _ => kind.to_string(),
+            }
+        }
+
+        /// Places items dropped from the inventory in front of the player, or
+        /// under them if that tile is taken.
+        fn place_dropped_items(&mut self) {
+            let dropped: Vec<_> = self.state.borrow_mut().dropped.drain(..).collect();
+            if dropped.is_empty() {
+                return;
+            }
+
+            let player = self.player();
+            let (dx, dy) = player.facing.unwrap_or(Direction::Right).delta();
+            let (front_x, front_y) = (player.position.x + dx, player.position.y + dy);
+            let (x, y) = if !self.is_obstacle(front_x, front_y) && self.world.find_at(front_x, front_y, |_| true).is_none() {
+                (front_x, front_y)
+            } else {
+                (player.position.x, player.position.y)
+            };
+
+            for stack in dropped {
+                self.world.spawn(pickup_entity(x, y, &stack.item, stack.count));
+            }
+        }
+
+        /// Reacts to the end of a battle once the battle screen hands control back.
+        fn handle_battle_outcome(&mut self) {
+            let Some(outcome) = self.state.borrow_mut().battle_outcome.take() else {
+                return;
+            };
+            let foe = self.engaged_foe.take();
+
+            match outcome {
+                Outcome::Victory { drops, defeated, .. } => {
+                    if let Some(id) = foe {
+                        self.world.despawn(id);
+                    }
+
+                    let state = self.state.borrow();
+                    let mut message = "Victory!".to_string();
+                    for drop in drops {
+                        message.push_str(&format!(" Found {} x {}.", drop.count, state.items.name(&drop.item)));
+                    }
+                    drop(state);
+                    self.popups.push(Popup::new_text_box(message, 3.0));
+                    for enemy in &defeated {
+                        self.advance_quests(QuestEvent::Defeated(enemy));
+                    }
+                }
+                Outcome::Defeat => {
+                    self.generate_new_map(None, None);
+                    self.run_pending_scripts();
+                    self.popups.push(Popup::new_text_box(
+                        "You were defeated, and wake up somewhere unfamiliar.".to_string(),
+                        3.0,
+                    ));
+                }
+                Outcome::Fled => {
+                    // Give the player a head start before the enemy gives chase again
+                    if let Some(ai) = foe.and_then(|id| self.world.get_mut(id)).and_then(|entity| entity.ai.as_mut()) {
+                        ai.rest = FOE_REST_AFTER_FLEE;
+                    }
+                    self.popups.push(Popup::new_text_box("You escaped.".to_string(), 2.0));
+                }
+            }
+        }
+
+        /// The entity a cutscene means by an actor.
+        fn actor_id(&self, actor: &Actor) -> Option<EntityId> {
+            match actor {
+                Actor::Player => Some(self.player),
+                Actor::Npc(name) => self
+                    .world
+                    .iter()
+                    .find(|(_, entity)| entity.npc.as_ref().is_some_and(|npc| &npc.name == name))
+                    .map(|(id, _)| id),
+            }
+        }
+
+        /// Ends the cutscene playing as though it had played out, and hands
+        /// control back to the player.
+        fn skip_cutscene(&mut self) {
+            if let Some(mut cutscene) = self.cutscene.take() {
+                self.popups.clear();
+                cutscene.skip(self);
+                self.run_pending_scripts();
+            }
+        }
+
+        /// Tells the quests on foot what the player did, announcing the ones it
+        /// moves along and rewarding the ones it finishes.
+        fn advance_quests(&mut self, event: QuestEvent) {
+            let mut event = event;
+            loop {
+                let updates = {
+                    let mut state = self.state.borrow_mut();
+                    let state = &mut *state;
+                    state.quest_log.record(&state.quests, event, &state.inventory, &state.flags)
+                };
+                if updates.is_empty() {
+                    break;
+                }
+                for update in updates {
+                    match update {
+                        QuestUpdate::Advanced { quest, stage } => {
+                            let state = self.state.borrow();
+                            let message = state
+                                .quests
+                                .get(&quest)
+                                .map(|def| format!("{}: {}", def.name, def.stages[stage].description));
+                            drop(state);
+                            if let Some(message) = message {
+                                self.show_popup(&message, 3.0);
+                            }
+                        }
+                        QuestUpdate::Completed { quest } => self.reward_quest(&quest),
+                    }
+                }
+                // Rewards can meet the objectives of other quests
+                event = QuestEvent::Changed;
+            }
+        }
+
+        /// Hands out a finished quest's rewards. Items that don't fit in the
+        /// bag are dropped at the player's feet.
+        fn reward_quest(&mut self, quest: &str) {
+            let Some(def) = self.state.borrow().quests.get(quest).cloned() else {
+                return;
+            };
+            let mut message = format!("Quest complete: {}!", def.name);
+            {
+                let mut state = self.state.borrow_mut();
+                let state = &mut *state;
+                for stack in &def.rewards.items {
+                    let left_over = state.inventory.add(&state.items, &stack.item, stack.count);
+                    if left_over > 0 {
+                        state.dropped.push(ItemStack { item: stack.item.clone(), count: left_over });
+                    }
+                    message.push_str(&format!(" Received {} x {}.", stack.count, state.items.name(&stack.item)));
+                }
+                for flag in &def.rewards.flags {
+                    state.flags.set(flag, true);
+                }
+            }
+            self.show_popup(&message, 3.0);
+            if def.rewards.xp > 0 {
+                self.give_xp(def.rewards.xp);
+            }
+        }
+
+        /// Lets quests know where the player is, on maps loaded from files.
+        fn report_position(&mut self) {
+            if let Some(map) = self.map_name.clone() {
+                let position = self.player().position;
+                self.advance_quests(QuestEvent::Reached { map: &map, position: (position.x, position.y) });
+            }
+        }
+
+        fn save_game(&mut self) {
+            let data = {
+                let state = self.state.borrow();
+                SaveData {
+                    map_name: self.map_name.clone(),
+                    map: self.map.clone(),
+                    spawn: self.spawn,
+                    map_scripts: self.map_scripts.clone(),
+                    encounter_zones: self.encounter_zones.clone(),
+                    encounters: self.encounters.clone(),
+                    fog: self.fog.clone(),
+                    world: self.world.clone(),
+                    player: self.player,
+                    outside: self.outside.clone(),
+                    memories: self.memories.clone(),
+                    flags: state.flags.clone(),
+                    quests: state.quest_log.clone(),
+                    clock: state.clock.clone(),
+                    character: state.player.clone(),
+                    inventory: state.inventory.clone(),
+                }
+            };
+
+            let message = match save::write(&data) {
+                Ok(()) => "Game saved.".to_string(),
+                Err(e) => format!("Could not save: {}", e),
+            };
+            self.popups.push(Popup::new_text_box(message, 2.0));
+        }
+
+        fn load_game(&mut self) {
+            // A save without its player, here or on the map outside, would
+            // leave nobody to control
+            let data = save::read().and_then(|data| {
+                let mut worlds = std::iter::once(&data.world).chain(data.outside.as_ref().map(|outside| &outside.world));
+                if worlds.any(|world| world.get(data.player).is_none()) {
+                    return Err("the player is missing from the save".to_string());
+                }
+                Ok(data)
+            });
+            let data = match data {
+                Ok(data) => data,
+                Err(e) => {
+                    self.popups.push(Popup::new_text_box(format!("Could not load: {}", e), 2.0));
+                    return;
+                }
+            };
+
+            self.map_name = data.map_name;
+            self.map = data.map;
+            self.spawn = data.spawn;
+            self.map_scripts = data.map_scripts;
+            self.encounter_zones = data.encounter_zones;
+            self.encounters = data.encounters;
+            self.fog = data.fog;
+            self.world = data.world;
+            self.player = data.player;
+            self.outside = data.outside;
+            self.memories = data.memories;
+            self.pending_scripts.clear();
+            self.popups.clear();
+            self.engaged_foe = None;
+            self.animators.clear();
+            self.walk_path.clear();
+            self.sliding = None;
+
+            let mut state = self.state.borrow_mut();
+            state.flags = data.flags;
+            state.quest_log = data.quests;
+            state.clock = data.clock;
+            state.player = data.character;
+            state.inventory = data.inventory;
+            state.dropped.clear();
+            drop(state);
+
+            self.reload_map_sprites();
+            self.popups.push(Popup::new_text_box("Game loaded.".to_string(), 2.0));
+        }
+
+        /// Loads the tileset art of the current map from its file. Only the
+        /// tiles are kept in saves and stashed maps, not the art.
+        fn reload_map_sprites(&mut self) {
+            self.map_sprites = None;
+            if let Some(name) = self.map_name.clone() {
+                match self.map_path(&name).and_then(|path| tiled::load(&path)) {
+                    Ok(loaded) => self.map_sprites = Some(SpriteSheet::load(&loaded.sprites, &self.maps_dir)),
+                    Err(e) => eprintln!("Could not load the art of map '{}': {}", name, e),
+                }
+            }
+        }
+
+        fn update_popups(&mut self) {
+            self.popups.retain_mut(|popup| {
+                popup.update();
+                popup.active
+            });
+        }
+
+        fn show_boundary_message(&mut self) {
+            self.popups.push(Popup::new_text_box(
+                "Boundary in the way".to_string(),
+                2.0, // Display for 2 seconds
+            ));
+        }
+
+        fn update_camera_position(&mut self, window_size: [f64; 2]) {
+            let num_tiles_x = self.map.width() as f64;
+            let num_tiles_y = self.map.height() as f64;
+
+            let world_width = num_tiles_x * self.grid_scale;
+            let world_height = num_tiles_y * self.grid_scale;
+
+            // In free-look the camera stays where the player panned it,
+            // otherwise it follows the player
+            let (desired_camera_x, desired_camera_y) = if self.free_look {
+                self.camera_position
+            } else {
+                let (centred_x, centred_y) = self.camera_centred_on_player(window_size);
+                (centred_x + self.follow_offset.0, centred_y + self.follow_offset.1)
+            };
+
+            // Clamp camera position
+            self.camera_position.0 = clamp_camera(desired_camera_x, world_width, window_size[0]);
+            self.camera_position.1 = clamp_camera(desired_camera_y, world_height, window_size[1]);
+        }
+
+        /// Camera position that puts the player in the middle of the window.
+        fn camera_centred_on_player(&self, window_size: [f64; 2]) -> (f64, f64) {
+            // Player position in world coordinates
+            let player = self.player().position;
+            let player_world_x = (player.x - self.map.left()) as f64 * self.grid_scale;
+            let player_world_y = (self.map.top() - player.y) as f64 * self.grid_scale; // Adjusted for Y inversion
+
+            (player_world_x - window_size[0] / 2.0, player_world_y - window_size[1] / 2.0)
+        }
+
+        /// Multiplies the zoom by `factor`, keeping the world point under
+        /// `pivot` (a screen position) in place. While the camera follows the
+        /// player, that becomes an offset from them, kept small enough that
+        /// the player stays in view.
+        fn zoom_at(&mut self, factor: f64, pivot: [f64; 2]) {
+            let new_scale = (self.grid_scale * factor).clamp(MIN_GRID_SCALE, MAX_GRID_SCALE);
+            let ratio = new_scale / self.grid_scale;
+
+            self.camera_position.0 = (pivot[0] + self.camera_position.0) * ratio - pivot[0];
+            self.camera_position.1 = (pivot[1] + self.camera_position.1) * ratio - pivot[1];
+            self.grid_scale = new_scale;
+
+            if !self.free_look {
+                let (centred_x, centred_y) = self.camera_centred_on_player(self.window_size);
+                let max_x = (self.window_size[0] / 2.0 - new_scale).max(0.0);
+                let max_y = (self.window_size[1] / 2.0 - new_scale).max(0.0);
+                self.follow_offset = (
+                    (self.camera_position.0 - centred_x).clamp(-max_x, max_x),
+                    (self.camera_position.1 - centred_y).clamp(-max_y, max_y),
+                );
+            }
+        }
+
+        /// Zooms around the cursor, or the middle of the window if the cursor is unknown.
+        fn zoom(&mut self, factor: f64) {
+            let pivot = self.cursor.unwrap_or([self.window_size[0] / 2.0, self.window_size[1] / 2.0]);
+            self.zoom_at(factor, pivot);
+        }
+
+        fn toggle_free_look(&mut self) {
+            self.free_look = !self.free_look;
+            self.follow_offset = (0.0, 0.0);
+            let message = if self.free_look {
+                "Free look: arrow keys to pan, F to return."
+            } else {
+                "Camera follows you again."
+            };
+            self.popups.push(Popup::new_text_box(message.to_string(), 2.0));
+        }
+
+        fn pan_camera(&mut self, dx: f64, dy: f64) {
+            if self.free_look {
+                self.camera_position.0 += dx;
+                self.camera_position.1 += dy;
+            }
+        }
+
+        /// Marker sizes grow and shrink with the zoom level.
+        fn point_size(&self) -> f64 {
+            POINT_SIZE * self.grid_scale / DEFAULT_GRID_SCALE
+        }
+
+        /// Tile under a point on the screen, the inverse of `grid_to_screen`.
+        fn screen_to_grid(&self, pos: [f64; 2]) -> Option<(i32, i32)> {
+            // Tiles are centered on their grid point, so round to the nearest one
+            let world_x = pos[0] + self.camera_position.0;
+            let world_y = pos[1] + self.camera_position.1;
+            let x = (world_x / self.grid_scale).round() as i32 + self.map.left();
+            let y = self.map.top() - (world_y / self.grid_scale).round() as i32;
+
+            self.is_within_bounds(x, y).then_some((x, y))
+        }
+
+        fn grid_to_screen(&self, x: i32, y: i32) -> [f64; 2] {
+            self.point_to_screen(x as f64, y as f64)
+        }
+
+        /// Like `grid_to_screen`, for positions between tiles.
+        fn point_to_screen(&self, x: f64, y: f64) -> [f64; 2] {
+            // Convert grid coordinates to world coordinates
+            let world_x = (x - self.map.left() as f64) * self.grid_scale;
+            let world_y = (self.map.top() as f64 - y) * self.grid_scale; // Y-axis inversion
+
+            // Convert world coordinates to screen coordinates
+            let screen_x = world_x - self.camera_position.0;
+            let screen_y = world_y - self.camera_position.1;
+
+            [screen_x, screen_y]
+        }
+
+        fn draw_grid(&self, c: &Context, g: &mut GlGraphics) {
+            for x in self.map.left()..=self.map.right() {
+                let start = self.grid_to_screen(x, self.map.bottom());
+                let end = self.grid_to_screen(x, self.map.top());
+                line(
+                    GRID_LINE_COLOR,
+                    0.5,
+                    [start[0], start[1], end[0], end[1]],
+                    c.transform,
+                    g,
+                );
+            }
+
+            for y in self.map.bottom()..=self.map.top() {
+                let start = self.grid_to_screen(self.map.left(), y);
+                let end = self.grid_to_screen(self.map.right(), y);
+                line(
+                    GRID_LINE_COLOR,
+                    0.5,
+                    [start[0], start[1], end[0], end[1]],
+                    c.transform,
+                    g,
+                );
+            }
+        }
+
+        /// Darkens remembered tiles and blacks out unknown ones.
+        fn draw_fog(&self, c: &Context, g: &mut GlGraphics) {
+            for (x, y) in self.map.positions() {
+                let color = match self.fog.visibility(x, y) {
+                    Visibility::Visible => continue,
+                    Visibility::Remembered => REMEMBERED_FOG_COLOR,
+                    Visibility::Unknown => UNKNOWN_FOG_COLOR,
+                };
+                rectangle(color, self.tile_rect(x, y), c.transform, g);
+            }
+        }
+
+        /// Whether an entity shows up on the map. Things that stay put are
+        /// remembered where they were last seen, anything that moves is not.
+        fn is_entity_shown(&self, entity: &Entity) -> bool {
+            match self.fog.visibility(entity.position.x, entity.position.y) {
+                Visibility::Visible => true,
+                Visibility::Remembered => entity.ai.is_none(),
+                Visibility::Unknown => false,
+            }
+        }
+
+        /// Draws the explored part of the map into `area` (x, y, size), one
+        /// square per tile, with the player and anything interactable on it.
+        fn draw_map_overview(&self, area: [f64; 3], c: &Context, g: &mut GlGraphics) {
+            let [left, top, size] = area;
+            let cell = size / self.map.width().max(self.map.height()) as f64;
+            let cell_rect = |x: i32, y: i32| {
+                let map_x = (x - self.map.left()) as f64;
+                let map_y = (self.map.top() - y) as f64;
+                [left + map_x * cell, top + map_y * cell, cell, cell]
+            };
+
+            rectangle(MAP_PANEL_COLOR, [left, top, size, size], c.transform, g);
+
+            for (x, y) in self.map.positions() {
+                // The topmost tile that says something about the place
+                let mut color = match self.map.tile(Layer::Overhead, x, y) {
+                    ROOF => ROOF_COLOR,
+                    _ => match (self.map.tile(Layer::Decoration, x, y), self.map.ground(x, y)) {
+                        (TREE, _) => TREE_COLOR,
+                        (_, WALL) => OBSTACLE_COLOR,
+                        (_, TALL_GRASS) => GRASS_COLOR,
+                        (_, WATER) => WATER_COLOR,
+                        (_, ROAD) => ROAD_COLOR,
+                        _ => MAP_FLOOR_COLOR,
+                    },
+                };
+                match self.fog.visibility(x, y) {
+                    Visibility::Visible => {}
+                    Visibility::Remembered => {
+                        for channel in &mut color[..3] {
+                            *channel *= MAP_REMEMBERED_SHADE;
+                        }
+                    }
+                    Visibility::Unknown => continue,
+                }
+                rectangle(color, cell_rect(x, y), c.transform, g);
+            }
+
+            for (id, entity) in self.world.iter() {
+                let noteworthy = entity.interactable.is_some() || entity.foe.is_some();
+                if id == self.player || !noteworthy || !self.is_entity_shown(entity) {
+                    continue;
+                }
+                let Some(Sprite::Marker { color } | Sprite::Arrow { color }) = entity.sprite else {
+                    continue;
+                };
+                rectangle(color, cell_rect(entity.position.x, entity.position.y), c.transform, g);
+            }
+
+            let player = self.player().position;
+            rectangle(PLAYER_COLOR, cell_rect(player.x, player.y), c.transform, g);
+        }
+
+        fn draw_hover(&self, c: &Context, g: &mut GlGraphics) {
+            let Some((x, y)) = self.cursor.and_then(|pos| self.screen_to_grid(pos)) else {
+                return;
+            };
+
+            Rectangle::new_border(HOVER_COLOR, 1.0).draw(
+                self.tile_rect(x, y),
+                &c.draw_state,
+                c.transform,
+                g,
+            );
+        }
+
+        /// Screen rectangle covering a whole tile.
+        fn tile_rect(&self, x: i32, y: i32) -> [f64; 4] {
+            let pos = self.grid_to_screen(x, y);
+            self.rect_around(pos)
+        }
+
+        /// Tile-sized screen rectangle centered on `pos`.
+        fn rect_around(&self, pos: [f64; 2]) -> [f64; 4] {
+            let half = self.grid_scale / 2.0;
+            [pos[0] - half, pos[1] - half, self.grid_scale, self.grid_scale]
+        }
+
+        /// Draws an entity with the sprite for its kind (see `entity_sprite`), or
+        /// its primitive shape if there is none, sliding between tiles as it walks.
+        fn draw_entity(&self, id: EntityId, entity: &Entity, kind: Option<&str>, c: &Context, g: &mut GlGraphics) {
+            let (x, y) = self
+                .animators
+                .get(&id)
+                .map_or((entity.position.x as f64, entity.position.y as f64), |animator| animator.draw_position());
+            let pos = self.point_to_screen(x, y);
+
+            if let Some(kind) = kind {
+                let sprite = self.entity_sprite(id, entity, kind);
+                if self.sprites.draw(&sprite, self.rect_around(pos), c, g) {
+                    return;
+                }
+            }
+
+            match entity.sprite {
+                Some(Sprite::Arrow { color }) => {
+                    let (sin, cos) = match entity.facing.unwrap_or(Direction::Right) {
+                        Direction::Right => (0.0, 1.0),   // Point right
+                        Direction::Up => (-1.0, 0.0),     // Point up
+                        Direction::Left => (0.0, -1.0),   // Point left
+                        Direction::Down => (1.0, 0.0),    // Point down
+                    };
+
+                    let triangle_size = self.point_size() * TRIANGLE_SIZE;
+                    let triangle_inset = self.point_size() * TRIANGLE_INSET;
+
+                    let tip_x = pos[0] + cos * triangle_size;
+                    let tip_y = pos[1] + sin * triangle_size;
+
+                    let base_x = pos[0] - cos * triangle_inset;
+                    let base_y = pos[1] - sin * triangle_inset;
+
+                    let half_base = triangle_size * 0.5;
+                    let base1_x = base_x - sin * half_base;
+                    let base1_y = base_y + cos * half_base;
+                    let base2_x = base_x + sin * half_base;
+                    let base2_y = base_y - cos * half_base;
+
+                    let triangle = [[tip_x, tip_y], [base1_x, base1_y], [base2_x, base2_y]];
+
+                    polygon(color, &triangle, c.transform, g);
+                }
+                Some(Sprite::Marker { color }) => self.draw_circle(pos, color, c, g),
+                None => {}
+            }
+        }
+
+        fn draw_marker(&self, x: i32, y: i32, color: [f32; 4], c: &Context, g: &mut GlGraphics) {
+            self.draw_circle(self.grid_to_screen(x, y), color, c, g);
+        }
+
+        fn draw_circle(&self, pos: [f64; 2], color: [f32; 4], c: &Context, g: &mut GlGraphics) {
+            let size = self.point_size();
+            ellipse(
+                color,
+                [
+                    pos[0] - size,
+                    pos[1] - size,
+                    size * 2.0,
+                    size * 2.0,
+                ],
+                c.transform,
+                g,
+            );
+        }
+
+        fn draw_direction_text(&self, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache) {
+            let label = if self.show_clock {
+                Some(self.state.borrow().clock.label())
+            } else {
+                self.player().facing.map(|direction| direction.label().to_string())
+            };
+            if let Some(label) = label {
+                text::Text::new_color(TEXT_COLOR, 16)
+                    .draw(
+                        &label,
+                        glyphs,
+                        &c.draw_state,
+                        c.transform.trans(TEXT_POS_X, TEXT_POS_Y),
+                        g,
+                    )
+                    .unwrap_or_else(|e| eprintln!("Error drawing text: {}", e));
+            }
+        }
+
+        /// Draws every tile of one layer, skipping the overhead tiles in `hidden`.
+        fn draw_layer(&self, layer: Layer, hidden: &HashSet<(i32, i32)>, c: &Context, g: &mut GlGraphics) {
+            for (x, y) in self.map.positions() {
+                let tile = self.map.tile(layer, x, y);
+                let Some(name) = map::tile_name(tile) else {
+                    continue;
+                };
+                if hidden.contains(&(x, y)) {
+                    continue;
+                }
+
+                // Tiles with an animation of the same name animate in step,
+                // unless the map's own tilesets have art for them
+                let rect = self.tile_rect(x, y);
+                if self.map_sprites.as_ref().is_some_and(|sheet| sheet.draw(name, rect, c, g)) {
+                    continue;
+                }
+                let sprite = self.animations.get(name).map_or(name, |animation| animation.sprite_at(self.clock));
+                if self.sprites.draw(sprite, rect, c, g) {
+                    continue;
+                }
+
+                match tile {
+                    WALL => self.draw_marker(x, y, OBSTACLE_COLOR, c, g),
+                    TALL_GRASS => self.draw_grass(x, y, c, g),
+                    WATER => {
+                        let size = self.point_size();
+                        let pos = self.grid_to_screen(x, y);
+                        rectangle(WATER_COLOR, [pos[0] - size, pos[1] - size, size * 2.0, size * 2.0], c.transform, g);
+                    }
+                    ROAD => rectangle(ROAD_COLOR, self.tile_rect(x, y), c.transform, g),
+                    FLOWERS => {
+                        let size = self.point_size() * 0.4;
+                        let pos = self.grid_to_screen(x, y);
+                        for (dx, dy) in [(-1.5, -1.0), (1.5, -0.5), (0.0, 1.5)] {
+                            let center = [pos[0] + dx * size * 2.0, pos[1] + dy * size * 2.0];
+                            ellipse(FLOWER_COLOR, [center[0] - size, center[1] - size, size * 2.0, size * 2.0], c.transform, g);
+                        }
+                    }
+                    TREE => self.draw_marker(x, y, TREE_COLOR, c, g),
+                    ROOF => rectangle(ROOF_COLOR, self.tile_rect(x, y), c.transform, g),
+                    CANOPY => {
+                        let pos = self.grid_to_screen(x, y);
+                        ellipse(CANOPY_COLOR, self.rect_around(pos), c.transform, g);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        /// Roof tiles over the building the player is standing in, which
+        /// are left out so the player can see inside.
+        fn roof_over_player(&self) -> HashSet<(i32, i32)> {
+            let position = self.player().position;
+            if self.map.tile(Layer::Overhead, position.x, position.y) != ROOF {
+                return HashSet::new();
+            }
+
+            pathfinding::distance_field(
+                &[(position.x, position.y)],
+                |x, y| self.map.tile(Layer::Overhead, x, y) == ROOF,
+                None,
+            )
+            .into_keys()
+            .collect()
+        }
+
+        fn draw_grass(&self, x: i32, y: i32, c: &Context, g: &mut GlGraphics) {
+            let pos = self.grid_to_screen(x, y);
+            let size = self.point_size();
+            for offset in [-size, 0.0, size] {
+                line(
+                    GRASS_COLOR,
+                    1.0,
+                    [pos[0] + offset, pos[1] + size, pos[0] + offset * 1.4, pos[1] - size],
+                    c.transform,
+                    g,
+                );
+            }
+        }
+
+        fn generate_new_map(&mut self, destination_position: Option<(i32, i32)>, generator: Option<MapGen>) {
+            let (layout, mut rng) = generator.unwrap_or_default().generate();
+            self.remember_map();
+
+            // Replace every entity except the player with the new map's population
+            let player_id = self.player;
+            self.world.retain(|id, _| id == player_id);
+            populate_world(&mut self.world, &layout, &mut rng);
+
+            self.map_name = None;
+            self.map_sprites = None;
+            self.outside = None;
+            self.spawn = layout.spawn;
+            self.map_scripts = generate_map_scripts(&layout.houses);
+            self.encounter_zones = generate_encounter_zones(&layout.map);
+            self.map = layout.map;
+            self.fog = FogOfWar::default();
+
+            // Defaulting to the spawn point if no destination is specified or
+            // the destination turned out to be walled in on the new map
+            self.arrive(destination_position, self.spawn);
+        }
+
+        /// The file of a map in the maps folder, in whichever format it was saved.
+        fn map_path(&self, name: &str) -> Result<PathBuf, String> {
+            MAP_EXTENSIONS
+                .iter()
+                .map(|extension| self.maps_dir.join(format!("{}.{}", name, extension)))
+                .find(|path| path.exists())
+                .ok_or_else(|| format!("no map named '{}' in {:?}", name, self.maps_dir))
+        }
+
+        /// Loads a map made in Tiled and puts the player on it, at its spawn
+        /// point unless a destination is given. Keeps the current map if the
+        /// file can't be loaded.
+        fn enter_map(&mut self, name: &str, destination_position: Option<(i32, i32)>) {
+            let loaded = match self.map_path(name).and_then(|path| tiled::load(&path)) {
+                Ok(loaded) => loaded,
+                Err(e) => {
+                    eprintln!("Could not load map '{}': {}", name, e);
+                    self.popups.push(Popup::new_text_box(format!("Could not load map '{}'.", name), 2.0));
+                    return;
+                }
+            };
+
+            self.remember_map();
+            self.map = loaded.tiles;
+            self.map_name = Some(name.to_string());
+            self.map_sprites = Some(SpriteSheet::load(&loaded.sprites, &self.maps_dir));
+            self.outside = None;
+            self.spawn = loaded.spawn;
+            self.map_scripts = loaded.scripts;
+            self.encounter_zones = loaded.encounter_zones;
+            self.fog = FogOfWar::default();
+
+            let player_id = self.player;
+            self.world.retain(|id, _| id == player_id);
+            for ((x, y), object) in &loaded.objects {
+                self.world.spawn(object_entity(*x, *y, object));
+            }
+            self.restore_map();
+
+            self.arrive(destination_position, self.spawn);
+            self.settle_schedules();
+        }
+
+        /// Puts NPCs that keep a schedule straight at the stop they should be
+        /// at by now, instead of having them walk there from wherever they were.
+        fn settle_schedules(&mut self) {
+            let minute = self.state.borrow().clock.minute_of_day();
+            let stops: Vec<(EntityId, (i32, i32))> = self
+                .world
+                .iter()
+                .filter_map(|(id, entity)| match &entity.ai.as_ref()?.behaviour {
+                    Behaviour::Schedule { stops } => Some((id, ai::scheduled_stop(stops, minute)?.position)),
+                    _ => None,
+                })
+                .collect();
+            for (id, (x, y)) in stops {
+                if self.is_within_bounds(x, y) && !self.is_obstacle_for(x, y, id) {
+                    let entity = self.world.get_mut(id).expect("Scheduled entity missing from world.");
+                    entity.position.x = x;
+                    entity.position.y = y;
+                }
+            }
+        }
+
+        /// Notes what the player changed on the current map file, before leaving it.
+        fn remember_map(&mut self) {
+            if let Some(name) = &self.map_name {
+                let doors = self
+                    .world
+                    .iter()
+                    .filter_map(|(_, entity)| Some(((entity.position.x, entity.position.y), entity.door.clone()?)))
+                    .collect();
+                let fired_triggers = self
+                    .map_scripts
+                    .tiles
+                    .iter()
+                    .filter(|(_, trigger)| trigger.once && trigger.fired)
+                    .map(|(&position, _)| position)
+                    .collect();
+                let crates = self
+                    .world
+                    .iter()
+                    .filter_map(|(_, entity)| Some((entity.pushable?.home, (entity.position.x, entity.position.y))))
+                    .collect();
+                let levers = self
+                    .world
+                    .iter()
+                    .filter(|(_, entity)| matches!(entity.mechanism, Some(Mechanism::Lever { on: true, .. })))
+                    .map(|(_, entity)| (entity.position.x, entity.position.y))
+                    .collect();
+                let memory = MapMemory { doors, fired_triggers, crates, levers, fog: self.fog.clone() };
+                self.memories.insert(name.clone(), memory);
+            }
+        }
+
+        /// Puts a freshly loaded map file back the way the player left it.
+        fn restore_map(&mut self) {
+            let Some(memory) = self.map_name.as_ref().and_then(|name| self.memories.get(name)) else {
+                return;
+            };
+            let memory = memory.clone();
+            self.fog = memory.fog;
+            for (position, door) in memory.doors {
+                let found = self.world.find_at(position.0, position.1, |entity| entity.door.is_some());
+                if let Some(entity) = found.and_then(|id| self.world.get_mut(id)) {
+                    entity.set_door(door);
+                }
+            }
+            for position in memory.fired_triggers {
+                if let Some(trigger) = self.map_scripts.tiles.get_mut(&position) {
+                    trigger.fired = true;
+                }
+            }
+            for (home, (x, y)) in memory.crates {
+                let found = self
+                    .world
+                    .iter()
+                    .find(|(_, entity)| entity.pushable.is_some_and(|pushable| pushable.home == home))
+                    .map(|(id, _)| id);
+                if let Some(entity) = found.and_then(|id| self.world.get_mut(id)) {
+                    entity.position.x = x;
+                    entity.position.y = y;
+                }
+            }
+            for (x, y) in memory.levers {
+                let found = self.world.find_at(x, y, |entity| entity.mechanism.is_some());
+                if let Some(on) = found.and_then(|id| mechanisms::lever_mut(&mut self.world, id)) {
+                    *on = true;
+                }
+            }
+            // Plates, gates and bridges follow from where the crates and levers are
+            mechanisms::update(&mut self.world, &mut self.map);
+        }
+
+        fn use_doorway(&mut self, id: EntityId) {
+            match self.world.get(id).and_then(|entity| entity.doorway) {
+                Some(Doorway::Inside { seed, size, outside }) => self.enter_house(seed, size, outside),
+                Some(Doorway::Outside) => self.leave_house(),
+                None => {}
             }
         }
-    }
 
-    #[derive(Clone, Copy, PartialEq)]
-    struct Point {
-        x: i32,
-        y: i32,
-        movable: bool,
-        facing: Option<Direction>,
-    }
+        /// Takes the player from the doorway they stand in into the house
+        /// behind it. The house is laid out from `seed`, so it is the same every
+        /// visit, while the map outside is kept as it is for coming back out.
+        fn enter_house(&mut self, seed: u64, size: (i32, i32), outside: (i32, i32)) {
+            let player = self.player();
+            let entrance = (player.position.x, player.position.y);
+            let facing = Direction::from_delta(outside.0 - entrance.0, outside.1 - entrance.1).unwrap_or(Direction::Down);
+            self.outside = Some(StashedMap {
+                map_name: self.map_name.take(),
+                map: self.map.clone(),
+                spawn: self.spawn,
+                map_scripts: self.map_scripts.clone(),
+                encounter_zones: std::mem::take(&mut self.encounter_zones),
+                fog: std::mem::take(&mut self.fog),
+                world: self.world.clone(),
+                exit: outside,
+                facing,
+            });
 
-    #[derive(Clone)]
-    struct InteractablePoint {
-        x: i32,
-        y: i32,
-        destination_map: Option<usize>,
-        destination_position: Option<(i32, i32)>,
-    }
+            let player_id = self.player;
+            self.world.retain(|id, _| id == player_id);
+            let inside = houses::inside(&mut self.world, seed, size);
 
-    pub struct GameScreen {
-        player: Point,
-        map: Vec<Vec<u8>>,
-        grid_scale: f64,
-        popups: Vec<Popup>,
-        interactables: Vec<InteractablePoint>,
-        camera_position: (f64, f64),
-    }
+            self.map_sprites = None;
+            self.spawn = inside.spawn;
+            self.map_scripts = inside.scripts;
+            self.map = inside.map;
+            self.arrive(None, self.spawn);
+            self.player_mut().facing = Some(Direction::Up);
+        }
 
-    impl GameScreen {
-        pub fn new() -> Self {
-            let player = Point {
-                x: 0,
-                y: 0,
-                movable: true,
-                facing: Some(Direction::Right),
+        /// Brings the player back out of a house, onto the tile in front of its
+        /// door and facing away from it.
+        fn leave_house(&mut self) {
+            let Some(outside) = self.outside.take() else {
+                return;
             };
 
-            let map = generate_map();
-            let interactables = generate_interactables(&map);
-
-            GameScreen {
-                player,
-                map,
-                grid_scale: 30.0,
-                popups: Vec::new(),
-                interactables,
-                camera_position: (0.0, 0.0),
-            }
-        }
-
-        fn is_within_bounds(&self, x: i32, y: i32) -> bool {
-            x >= GRID_MIN && x <= GRID_MAX && y >= GRID_MIN && y <= GRID_MAX
+            self.map_name = outside.map_name;
+            self.map = outside.map;
+            self.spawn = outside.spawn;
+            self.map_scripts = outside.map_scripts;
+            self.encounter_zones = outside.encounter_zones;
+            self.fog = outside.fog;
+            self.world = outside.world;
+            self.reload_map_sprites();
+
+            let player = self.player_mut();
+            player.position.x = outside.exit.0;
+            player.position.y = outside.exit.1;
+            player.facing = Some(outside.facing);
+            self.popups.clear();
+            self.walk_path.clear();
+            self.animators.clear();
+            self.sliding = None;
         }
 
-        fn is_obstacle(&self, x: i32, y: i32) -> bool {
-            let map_x = (x - GRID_MIN) as usize;
-            let map_y = (GRID_MAX - y) as usize;
+        /// Puts the player on a freshly entered map at `destination`, or at
+        /// `fallback` if there is none or it is blocked, and runs the map's
+        /// on-enter script.
+        fn arrive(&mut self, destination: Option<(i32, i32)>, fallback: (i32, i32)) {
+            let (x, y) = destination
+                .filter(|&(x, y)| self.is_within_bounds(x, y) && !self.is_obstacle(x, y))
+                .unwrap_or(fallback);
+            let player = self.player_mut();
+            player.position.x = x;
+            player.position.y = y;
+
+            // Reset the player's facing direction, and put them back in the middle of the view
+            player.facing = Some(Direction::Right);
+            self.follow_offset = (0.0, 0.0);
+
+            // Clear existing popups and any walk planned on the old map
+            self.popups.clear();
+            self.walk_path.clear();
+            self.animators.clear();
+            self.sliding = None;
 
-            // Check if indices are within bounds
-            if map_x >= MAP_WIDTH || map_y >= MAP_HEIGHT {
-                return true; // Treat out-of-bounds as obstacle
+            if let Some(name) = self.map_scripts.on_enter.clone() {
+                self.pending_scripts.push_back(name);
             }
-
-            self.map[map_y][map_x] == 1 // Return true if the cell is an obstacle
+            self.report_position();
         }
 
-        fn try_move_player(&mut self, dx: i32, dy: i32) {
-            // Update facing direction based on movement attempt
-            self.player.facing = Some(match (dx, dy) {
-                (0, 1) => Direction::Up,
-                (0, -1) => Direction::Down,
-                (-1, 0) => Direction::Left,
-                (1, 0) => Direction::Right,
-                _ => self.player.facing.unwrap_or(Direction::Right),
-            });
-
-            let new_x = self.player.x + dx;
-            let new_y = self.player.y + dy;
-
-            // Check if new position is within map bounds
-            if !self.is_within_bounds(new_x, new_y) {
-                self.show_boundary_message();
+        /// Opens or closes the map editor. The camera stops following the
+        /// player while it is open, so the whole map can be panned over.
+        fn toggle_editor(&mut self) {
+            if self.editor.take().is_some() {
+                self.free_look = false;
+                self.follow_offset = (0.0, 0.0);
                 return;
             }
+            self.walk_path.clear();
+            self.free_look = true;
+            self.editor = Some(Editor::new(editor::map_names(&self.maps_dir)));
+        }
 
-            // Check if the new position is occupied by an obstacle
-            if self.is_obstacle(new_x, new_y) {
-                self.show_boundary_message();
+        /// Applies the editor's tool to the tile under the cursor. The right
+        /// button erases tiles and removes entities.
+        fn editor_click(&mut self, button: MouseButton) {
+            let Some(tile) = self.cursor.and_then(|pos| self.screen_to_grid(pos)) else {
                 return;
+            };
+            if let Some(editor) = self.editor.as_mut() {
+                let erase = button == MouseButton::Right;
+                editor.click(&mut self.world, &mut self.map, &mut self.spawn, self.player, tile, erase);
             }
+        }
 
-            // Move player to new position
-            self.player.x = new_x;
-            self.player.y = new_y;
+        /// Keeps painting while a mouse button is held down and the cursor moves.
+        fn continue_stroke(&mut self) {
+            let Some((x, y)) = self.cursor.and_then(|pos| self.screen_to_grid(pos)) else {
+                return;
+            };
+            if let Some(editor) = &self.editor {
+                editor.continue_stroke(&mut self.map, x, y);
+            }
         }
 
-        fn try_interact(&mut self) {
-            // Calculate the point in front of the player based on facing direction
-            let (dx, dy) = match self.player.facing.unwrap_or(Direction::Right) {
-                Direction::Up => (0, 1),
-                Direction::Down => (0, -1),
-                Direction::Left => (-1, 0),
-                Direction::Right => (1, 0),
+        /// Writes the map with everything on it to its file in the maps folder.
+        /// A generated map gets a new file, which it is known by from then on.
+        fn save_map(&mut self) {
+            let name = self.map_name.clone().unwrap_or_else(|| editor::unused_map_name(&self.maps_dir));
+            let map = TiledMap {
+                tiles: self.map.clone(),
+                spawn: self.spawn,
+                objects: editor::map_objects(&self.world, self.player),
+                scripts: self.map_scripts.clone(),
+                encounter_zones: self.encounter_zones.clone(),
+                sprites: SpriteData { atlases: Vec::new(), sprites: Vec::new() },
             };
 
-            let target_x = self.player.x + dx;
-            let target_y = self.player.y + dy;
+            let message = match editor::save_map(&self.maps_dir, &name, &map, &self.sprites) {
+                Ok(()) => {
+                    self.map_name = Some(name.clone());
+                    if let Some(editor) = self.editor.as_mut() {
+                        editor.set_destinations(editor::map_names(&self.maps_dir));
+                    }
+                    format!("Map saved as '{}'.", name)
+                }
+                Err(e) => {
+                    eprintln!("Could not save map '{}': {}", name, e);
+                    format!("Could not save map '{}'.", name)
+                }
+            };
+            self.popups.push(Popup::new_text_box(message, 2.0));
+        }
 
-            // Check if there's an interactable point at the target position
-            if let Some(index) = self
-                .interactables
-                .iter()
-                .position(|point| point.x == target_x && point.y == target_y)
-            {
-                // Get the interactable point
-                let interactable = self.interactables[index].clone();
+        /// Blocked tiles while editing collision, the spawn point, the picked
+        /// entity and the editor's help lines.
+        fn draw_editor(&self, editor: &Editor, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache) {
+            if editor.shows_collision() {
+                for (x, y) in self.map.positions().filter(|&(x, y)| self.map.is_solid(x, y)) {
+                    rectangle(SOLID_OVERLAY_COLOR, self.tile_rect(x, y), c.transform, g);
+                }
+            }
 
-                // Transport the player to the new map
-                self.generate_new_map(interactable.destination_position);
+            let (spawn_x, spawn_y) = self.spawn;
+            Rectangle::new_border(SPAWN_COLOR, 2.0).draw(self.tile_rect(spawn_x, spawn_y), &c.draw_state, c.transform, g);
+            if let Some(entity) = editor.selected.and_then(|id| self.world.get(id)) {
+                let rect = self.tile_rect(entity.position.x, entity.position.y);
+                Rectangle::new_border(SELECTED_COLOR, 2.0).draw(rect, &c.draw_state, c.transform, g);
+            }
 
-                // Optionally remove the interactable point from the current map
-                // self.interactables.remove(index);
+            for (row, line) in editor.status(&self.world).iter().enumerate() {
+                draw_text(line, 14, TEXT_COLOR, [TEXT_POS_X, TEXT_POS_Y * (row + 2) as f64], c, g, glyphs);
+            }
+        }
+    }
 
-                // Display a popup indicating the map has changed
-                self.popups.push(Popup::new_text_box(
-                    "You have entered a new area.".to_string(),
-                    2.0,
-                ));
-            } else {
-                // Show a message if there's nothing to interact with
-                self.popups.push(Popup::new_text_box(
-                    "Nothing to interact with".to_string(),
-                    2.0,
-                ));
+    impl ScriptHost for GameScreen {
+        fn show_popup(&mut self, text: &str, duration: f64) {
+            self.popups.push(Popup::new_text_box(text.to_string(), duration));
+        }
+
+        fn move_player(&mut self, x: i32, y: i32) {
+            if !self.is_within_bounds(x, y) || self.is_obstacle(x, y) {
+                eprintln!("Script tried to move the player onto blocked tile ({}, {})", x, y);
+                return;
             }
+            let player = self.player_mut();
+            player.position.x = x;
+            player.position.y = y;
         }
 
-        fn update_popups(&mut self) {
-            self.popups.retain_mut(|popup| {
-                popup.update();
-                popup.active
-            });
+        fn face_player(&mut self, direction: Direction) {
+            self.player_mut().facing = Some(direction);
         }
 
-        fn show_boundary_message(&mut self) {
-            self.popups.push(Popup::new_text_box(
-                "Boundary in the way".to_string(),
-                2.0, // Display for 2 seconds
-            ));
+        fn set_flag(&mut self, flag: &str, value: bool) {
+            self.state.borrow_mut().flags.set(flag, value);
+            self.advance_quests(QuestEvent::Changed);
         }
 
-        fn update_camera_position(&mut self, window_size: [f64; 2]) {
-            let num_tiles_x = (GRID_MAX - GRID_MIN + 1) as f64;
-            let num_tiles_y = (GRID_MAX - GRID_MIN + 1) as f64;
+        fn flag(&self, flag: &str) -> bool {
+            self.state.borrow().flags.get(flag)
+        }
 
-            let world_width = num_tiles_x * self.grid_scale;
-            let world_height = num_tiles_y * self.grid_scale;
+        fn set_var(&mut self, name: &str, value: Value) {
+            self.state.borrow_mut().flags.set_value(name, value);
+            self.advance_quests(QuestEvent::Changed);
+        }
 
-            // Player position in world coordinates
-            let player_world_x = (self.player.x - GRID_MIN) as f64 * self.grid_scale;
-            let player_world_y = (GRID_MAX - self.player.y) as f64 * self.grid_scale; // Adjusted for Y inversion
+        fn var(&self, name: &str) -> Option<Value> {
+            self.state.borrow().flags.value(name).cloned()
+        }
 
-            // Desired camera position to center the player
-            let desired_camera_x = player_world_x - window_size[0] / 2.0;
-            let desired_camera_y = player_world_y - window_size[1] / 2.0;
+        fn spawn_npc(&mut self, x: i32, y: i32, name: &str, dialogue: &str) {
+            self.world.spawn(npc_entity(x, y, name, dialogue));
+        }
 
-            // Calculate camera bounds
-            let min_camera_x = 0.0;
-            let max_camera_x = world_width - window_size[0];
-            let min_camera_y = 0.0;
-            let max_camera_y = world_height - window_size[1];
+        fn spawn_portal(&mut self, x: i32, y: i32, destination: (i32, i32)) {
+            self.world.spawn(portal_entity(x, y, destination));
+        }
 
-            // Clamp camera position
-            self.camera_position.0 = desired_camera_x.clamp(min_camera_x, max_camera_x);
-            self.camera_position.1 = desired_camera_y.clamp(min_camera_y, max_camera_y);
+        fn change_map(&mut self, destination: Option<(i32, i32)>) {
+            self.generate_new_map(destination, None);
         }
 
-        fn grid_to_screen(&self, x: i32, y: i32) -> [f64; 2] {
-            // Convert grid coordinates to world coordinates
-            let world_x = (x - GRID_MIN) as f64 * self.grid_scale;
-            let world_y = (GRID_MAX - y) as f64 * self.grid_scale; // Y-axis inversion
+        fn start_battle(&mut self, enemies: &[String]) {
+            let mut state = self.state.borrow_mut();
+            let mut defs = Vec::new();
+            for id in enemies {
+                match state.enemies.get(id) {
+                    Some(def) => defs.push(def.clone()),
+                    None => eprintln!("Script tried to start a battle with unknown enemy '{}'", id),
+                }
+            }
+            if defs.is_empty() || state.battle.is_some() {
+                return;
+            }
 
-            // Convert world coordinates to screen coordinates
-            let screen_x = world_x - self.camera_position.0;
-            let screen_y = world_y - self.camera_position.1;
+            state.battle = Some(Battle::new(state.player.clone(), defs, self.encounters.battle_seed()));
+            self.encounters.battle_started();
+            self.next_screen = Some(ScreenState::Battle);
+        }
 
-            [screen_x, screen_y]
+        fn reset_puzzle(&mut self) {
+            let message = mechanisms::reset(&mut self.world, &mut self.map);
+            self.show_popup(message, 2.0);
         }
 
-        fn draw_grid(&self, c: &Context, g: &mut GlGraphics) {
-            for x in GRID_MIN..=GRID_MAX {
-                let start = self.grid_to_screen(x, GRID_MIN);
-                let end = self.grid_to_screen(x, GRID_MAX);
-                line(
-                    GRID_LINE_COLOR,
-                    0.5,
-                    [start[0], start[1], end[0], end[1]],
-                    c.transform,
-                    g,
-                );
+        fn start_quest(&mut self, quest: &str) {
+            let started = {
+                let mut state = self.state.borrow_mut();
+                let state = &mut *state;
+                match state.quests.get(quest) {
+                    Some(def) => state.quest_log.start(def).then(|| def.name.clone()),
+                    None => {
+                        eprintln!("Script tried to start unknown quest '{}'", quest);
+                        None
+                    }
+                }
+            };
+            if let Some(name) = started {
+                self.show_popup(&format!("New quest: {}", name), 3.0);
+                // The player may already have done what the first stage asks
+                self.advance_quests(QuestEvent::Changed);
             }
+        }
 
-            for y in GRID_MIN..=GRID_MAX {
-                let start = self.grid_to_screen(GRID_MIN, y);
-                let end = self.grid_to_screen(GRID_MAX, y);
-                line(
-                    GRID_LINE_COLOR,
-                    0.5,
-                    [start[0], start[1], end[0], end[1]],
-                    c.transform,
-                    g,
-                );
+        fn play_cutscene(&mut self, name: &str) {
+            if self.cutscene.is_some() {
+                eprintln!("Cutscene '{}' can't start while another one is playing", name);
+                return;
+            }
+            match self.cutscenes.get(name) {
+                Some(steps) => {
+                    self.cutscene = Some(CutscenePlayer::new(steps.clone()));
+                    self.walk_path.clear();
+                    self.sliding = None;
+                }
+                None => eprintln!("Unknown cutscene '{}'", name),
             }
         }
 
-        fn draw_point(&self, point: &Point, color: [f32; 4], c: &Context, g: &mut GlGraphics) {
-            let pos = self.grid_to_screen(point.x, point.y);
-
-            if point.movable && point.facing.is_some() {
-                // For the player, draw a directional triangle
-                let (sin, cos) = match point.facing.unwrap() {
-                    Direction::Right => (0.0, 1.0),   // Point right
-                    Direction::Up => (-1.0, 0.0),     // Point up
-                    Direction::Left => (0.0, -1.0),   // Point left
-                    Direction::Down => (1.0, 0.0),    // Point down
-                };
-
-                let tip_x = pos[0] + cos * TRIANGLE_SIZE;
-                let tip_y = pos[1] + sin * TRIANGLE_SIZE;
+        fn set_time(&mut self, hour: u32, minute: u32) {
+            self.state.borrow_mut().clock.set_time(hour, minute);
+            self.settle_schedules();
+        }
 
-                let base_x = pos[0] - cos * TRIANGLE_INSET;
-                let base_y = pos[1] - sin * TRIANGLE_INSET;
+        fn set_time_scale(&mut self, scale: f64) {
+            self.state.borrow_mut().clock.scale = scale;
+        }
 
-                let half_base = TRIANGLE_SIZE * 0.5;
-                let base1_x = base_x - sin * half_base;
-                let base1_y = base_y + cos * half_base;
-                let base2_x = base_x + sin * half_base;
-                let base2_y = base_y - cos * half_base;
+        fn give_xp(&mut self, amount: u32) {
+            let message = {
+                let mut state = self.state.borrow_mut();
+                let state = &mut *state;
+                let levels = state.player.gain_xp(amount, &state.growth);
+                if levels > 0 {
+                    format!("Gained {} XP. Reached level {}!", amount, state.player.level)
+                } else {
+                    format!("Gained {} XP.", amount)
+                }
+            };
+            self.popups.push(Popup::new_text_box(message, 2.0));
+        }
+    }
 
-                let triangle = [[tip_x, tip_y], [base1_x, base1_y], [base2_x, base2_y]];
+    impl CutsceneHost for GameScreen {
+        fn actor_position(&self, actor: &Actor) -> Option<(i32, i32)> {
+            let entity = self.world.get(self.actor_id(actor)?)?;
+            Some((entity.position.x, entity.position.y))
+        }
 
-                polygon(color, &triangle, c.transform, g);
-            } else {
-                ellipse(
-                    color,
-                    [
-                        pos[0] - POINT_SIZE,
-                        pos[1] - POINT_SIZE,
-                        POINT_SIZE * 2.0,
-                        POINT_SIZE * 2.0,
-                    ],
-                    c.transform,
-                    g,
-                );
+        fn place_actor(&mut self, actor: &Actor, (x, y): (i32, i32), facing: Direction) -> bool {
+            let Some(id) = self.actor_id(actor) else {
+                return false;
+            };
+            if !self.is_within_bounds(x, y) || self.is_obstacle_for(x, y, id) {
+                return false;
+            }
+            if let Some(entity) = self.world.get_mut(id) {
+                entity.position.x = x;
+                entity.position.y = y;
+                entity.facing = Some(facing);
             }
+            true
         }
 
-        fn draw_direction_text(&self, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache) {
-            if let Some(direction) = self.player.facing {
-                text::Text::new_color(TEXT_COLOR, 16)
-                    .draw(
-                        direction.to_string(),
-                        glyphs,
-                        &c.draw_state,
-                        c.transform.trans(TEXT_POS_X, TEXT_POS_Y),
-                        g,
-                    )
-                    .unwrap_or_else(|e| eprintln!("Error drawing text: {}", e));
+        fn face_actor(&mut self, actor: &Actor, direction: Direction) {
+            if let Some(entity) = self.actor_id(actor).and_then(|id| self.world.get_mut(id)) {
+                entity.facing = Some(direction);
             }
         }
 
-        fn draw_obstacles(&self, c: &Context, g: &mut GlGraphics) {
-            for (map_y, row) in self.map.iter().enumerate() {
-                for (map_x, &cell) in row.iter().enumerate() {
-                    if cell == 1 {
-                        // Convert map indices back to grid coordinates
-                        let x = map_x as i32 + GRID_MIN;
-                        let y = GRID_MAX as i32 - map_y as i32;
+        fn go_to_map(&mut self, name: &str, position: Option<(i32, i32)>) {
+            self.enter_map(name, position);
+        }
+    }
 
-                        let obstacle = Point {
-                            x,
-                            y,
-                            movable: false,
-                            facing: None,
-                        };
-                        self.draw_point(&obstacle, OBSTACLE_COLOR, c, g);
-                    }
-                }
-            }
+    impl InteractHost for GameScreen {
+        fn tell(&mut self, text: &str, duration: f64) {
+            self.show_popup(text, duration);
         }
 
-        fn generate_new_map(&mut self, destination_position: Option<(i32, i32)>) {
-            // Generate a new map
-            self.map = generate_map();
+        /// Transports the player to the new map, which announces itself
+        /// through its on-enter script.
+        fn travel(&mut self, portal: &Portal) {
+            match &portal.destination_map {
+                Some(name) => self.enter_map(name, portal.destination_position),
+                None => self.generate_new_map(portal.destination_position, portal.generator),
+            }
+        }
 
-            // Reinitialize interactable points
-            self.interactables = generate_interactables(&self.map);
+        fn pick_up(&mut self, id: EntityId, pickup: &Pickup) {
+            let (name, left_over) = {
+                let mut state = self.state.borrow_mut();
+                let state = &mut *state;
+                let name = state.items.name(&pickup.item).to_string();
+                (name, state.inventory.add(&state.items, &pickup.item, pickup.count))
+            };
 
-            // Set player position based on the destination coordinates
-            if let Some((x, y)) = destination_position {
-                self.player.x = x;
-                self.player.y = y;
+            let message = if left_over == 0 {
+                self.world.despawn(id);
+                format!("Picked up {} x {}.", pickup.count, name)
             } else {
-                // Default position if no destination is specified
-                self.player.x = 0;
-                self.player.y = 0;
+                if let Some(pickup) = self.world.get_mut(id).and_then(|e| e.pickup.as_mut()) {
+                    pickup.count = left_over;
+                }
+                if left_over == pickup.count {
+                    "Your bag is full.".to_string()
+                } else {
+                    format!("Picked up {} x {}. Your bag is full.", pickup.count - left_over, name)
+                }
+            };
+
+            self.popups.push(Popup::new_text_box(message, 2.0));
+            self.advance_quests(QuestEvent::Changed);
+        }
+
+        fn operate_door(&mut self, id: EntityId) {
+            let result = doors::operate(&mut self.world, id, &mut self.state.borrow_mut());
+            match result {
+                Ok(message) => {
+                    if let Some(message) = message {
+                        self.show_popup(&message, 2.0);
+                    }
+                    self.update_fov();
+                }
+                Err(refusal) => self.show_popup(&refusal, 2.0),
             }
+        }
 
-            // Reset the player's facing direction
-            self.player.facing = Some(Direction::Right);
+        fn pull_lever(&mut self, id: EntityId) {
+            mechanisms::pull_lever(&mut self.world, &mut self.map, id);
+        }
 
-            // Clear existing popups
-            self.popups.clear();
+        fn talked_to(&mut self, name: &str) {
+            self.advance_quests(QuestEvent::Talked(name));
+        }
+
+        fn queue_script(&mut self, name: &str) {
+            self.pending_scripts.push_back(name.to_string());
         }
     }
 
@@ -360,30 +1967,75 @@
             glyphs: &mut GlyphCache,
             window_size: [f64; 2],
         ) {
+            self.window_size = window_size;
             self.update_camera_position(window_size);
 
             clear([0.0, 0.0, 0.0, 1.0], g);
-            self.draw_grid(c, g);
-
-            // Draw obstacles
-            self.draw_obstacles(c, g);
-
-            // Draw interactable points
-            for interactable in &self.interactables {
-                let point = Point {
-                    x: interactable.x,
-                    y: interactable.y,
-                    movable: false,
-                    facing: None,
-                };
-                self.draw_point(&point, INTERACTABLE_COLOR, c, g);
+
+            // Floor sprites replace the grid lines
+            if !self.sprites.has("floor") && !self.map_sprites.as_ref().is_some_and(|sheet| sheet.has("floor")) {
+                self.draw_grid(c, g);
             }
 
-            // Draw player
-            self.draw_point(&self.player, PLAYER_COLOR, c, g);
+            // Ground and decoration under everything that walks
+            let no_tiles = HashSet::new();
+            self.draw_layer(Layer::Ground, &no_tiles, c, g);
+            self.draw_layer(Layer::Decoration, &no_tiles, c, g);
 
-            // Draw direction text
+            // Draw map entities the player has seen, those lying flat first
+            let (flat, upright): (Vec<_>, Vec<_>) = self
+                .world
+                .iter()
+                .filter(|&(id, entity)| id != self.player && (self.editor.is_some() || self.is_entity_shown(entity)))
+                .partition(|(_, entity)| mechanisms::lies_flat(entity));
+            for (id, entity) in flat.into_iter().chain(upright) {
+                self.draw_entity(id, entity, entity_kind(entity), c, g);
+            }
+
+            // Roofs and tree crowns cover the player, except the roof they are under
+            self.draw_entity(self.player, self.player(), Some("player"), c, g);
+            match &self.editor {
+                Some(editor) if !editor.shows_overhead() => {}
+                Some(_) => self.draw_layer(Layer::Overhead, &no_tiles, c, g),
+                None => self.draw_layer(Layer::Overhead, &self.roof_over_player(), c, g),
+            }
+
+            // The light of the time of day, outdoors only, then fog over all of
+            // it and the cursor on top. The editor sees the whole map in daylight.
+            if self.editor.is_none() {
+                if self.outside.is_none() {
+                    let tint = self.state.borrow().clock.tint();
+                    rectangle(tint, [0.0, 0.0, window_size[0], window_size[1]], c.transform, g);
+                }
+                self.draw_fog(c, g);
+            }
+            self.draw_hover(c, g);
+
+            // Draw direction text, or the time
             self.draw_direction_text(c, g, glyphs);
+            if let Some(editor) = &self.editor {
+                self.draw_editor(editor, c, g, glyphs);
+            } else if self.free_look {
+                draw_text("FREE LOOK", 16, TEXT_COLOR, [TEXT_POS_X, TEXT_POS_Y * 2.0], c, g, glyphs);
+            }
+
+            // Full map in the middle of the screen, or the minimap in the corner
+            if self.show_full_map {
+                let size = window_size[0].min(window_size[1]) - MINIMAP_MARGIN * 4.0;
+                let area = [(window_size[0] - size) / 2.0, (window_size[1] - size) / 2.0, size];
+                self.draw_map_overview(area, c, g);
+            } else {
+                let area = [window_size[0] - MINIMAP_SIZE - MINIMAP_MARGIN, MINIMAP_MARGIN, MINIMAP_SIZE];
+                self.draw_map_overview(area, c, g);
+            }
+
+            // A cutscene's fade covers the map, but not what is said over it
+            if let Some(cutscene) = &self.cutscene {
+                let shade = [0.0, 0.0, 0.0, cutscene.fade() as f32];
+                rectangle(shade, [0.0, 0.0, window_size[0], window_size[1]], c.transform, g);
+                let hint_pos = [TEXT_POS_X, window_size[1] - SKIP_HINT_OFFSET];
+                draw_text("SPACE: SKIP", 12, TEXT_COLOR, hint_pos, c, g, glyphs);
+            }
 
             // Draw popups
             for popup in &self.popups {
@@ -392,152 +2044,423 @@
         }
 
         fn update(&mut self) -> Option<ScreenState> {
+            let now = Instant::now();
+            let dt = now.duration_since(self.last_tick).as_secs_f64().min(MAX_TICK);
+            self.last_tick = now;
+
+            self.place_dropped_items();
+            self.handle_battle_outcome();
+            // A cutscene has the world to itself while it plays, and the world
+            // holds still while the map is being edited
+            if let Some(mut cutscene) = self.cutscene.take() {
+                if cutscene.update(dt, self) {
+                    self.cutscene = Some(cutscene);
+                }
+                self.run_pending_scripts();
+            } else if self.editor.is_none() {
+                self.state.borrow_mut().clock.advance(dt);
+                self.slide_player(dt);
+                self.follow_walk_path(dt);
+                self.move_npcs(Some(dt));
+            }
+            mechanisms::update(&mut self.world, &mut self.map);
+            self.update_fov();
+            self.update_animations(dt);
             self.update_popups();
-            None
+            self.next_screen.take()
         }
 
         fn handle_input(&mut self, input: &Input) -> Option<ScreenState> {
             match input {
+                Input::Move(Motion::MouseCursor(pos)) => {
+                    self.cursor = Some(*pos);
+                    self.continue_stroke();
+                }
+                Input::Move(Motion::MouseScroll([_, scroll])) => {
+                    if *scroll > 0.0 {
+                        self.zoom(ZOOM_FACTOR);
+                    } else if *scroll < 0.0 {
+                        self.zoom(1.0 / ZOOM_FACTOR);
+                    }
+                }
+                Input::Button(ButtonArgs {
+                    state: ButtonState::Press,
+                    button: Button::Mouse(button),
+                    ..
+                }) => {
+                    if self.editor.is_some() {
+                        self.editor_click(*button);
+                    } else if *button == MouseButton::Left && self.cutscene.is_none() {
+                        if let Some((x, y)) = self.cursor.and_then(|pos| self.screen_to_grid(pos)) {
+                            self.click_tile(x, y);
+                        }
+                    }
+                }
+                Input::Button(ButtonArgs {
+                    state: ButtonState::Release,
+                    button: Button::Mouse(_),
+                    ..
+                }) => {
+                    if let Some(editor) = self.editor.as_mut() {
+                        editor.stroke = None;
+                    }
+                }
                 Input::Button(ButtonArgs {
                     state: ButtonState::Press,
                     button: Button::Keyboard(key),
                     ..
-                }) => match key {
-                    Key::W => self.try_move_player(0, 1),
-                    Key::S => self.try_move_player(0, -1),
-                    Key::A => self.try_move_player(-1, 0),
-                    Key::D => self.try_move_player(1, 0),
-                    Key::E => self.try_interact(),
-                    Key::Escape => return Some(ScreenState::Pause),
-                    _ => {}
-                },
-                _ => {}
-            }
-            None
-        }
-    }
+                }) => {
+                    // Only skipping or pausing gets through while a cutscene plays
+                    if self.cutscene.is_some() {
+                        match key {
+                            Key::Space => self.skip_cutscene(),
+                            Key::Escape => return Some(ScreenState::Pause),
+                            _ => {}
+                        }
+                        return self.next_screen.take();
+                    }
 
-    fn add_house(
-        map: &mut Vec<Vec<u8>>,
-        top_left: (i32, i32),
-        bottom_right: (i32, i32),
-        entrance_position: (i32, i32),
-    ) {
-        let (x1, y1) = top_left;
-        let (x2, y2) = bottom_right;
-
-        // Determine the start and end coordinates for the house
-        let x_start = x1.min(x2);
-        let x_end = x1.max(x2);
-        let y_start = y1.min(y2);
-        let y_end = y1.max(y2);
-
-        // Iterate over the specified area to place walls
-        for y in y_start..=y_end {
-            for x in x_start..=x_end {
-                // Skip the entrance position
-                if (x, y) == entrance_position {
-                    continue;
-                }
+                    // Any key takes over from a clicked walk
+                    self.walk_path.clear();
 
-                // Only place walls on the edges to create the house outline
-                if y == y_start || y == y_end || x == x_start || x == x_end {
-                    // Convert grid coordinates to map indices
-                    let map_x = (x - GRID_MIN) as usize;
-                    let map_y = (GRID_MAX - y) as usize;
+                    // The editor has keys of its own, and leaves the player be
+                    if let Some(editor) = self.editor.as_mut() {
+                        if editor.handle_key(*key, &mut self.world)
+                            || matches!(key, Key::E | Key::I | Key::C | Key::Q | Key::T | Key::F5 | Key::F9)
+                        {
+                            return self.next_screen.take();
+                        }
+                    }
 
-                    // Check if indices are within bounds
-                    if map_x < MAP_WIDTH && map_y < MAP_HEIGHT {
-                        map[map_y][map_x] = 1; // Place an obstacle
+                    match key {
+                        Key::W => self.try_move_player(0, 1),
+                        Key::S => self.try_move_player(0, -1),
+                        Key::A => self.try_move_player(-1, 0),
+                        Key::D => self.try_move_player(1, 0),
+                        Key::E => self.try_interact(),
+                        Key::I => return Some(ScreenState::Inventory),
+                        Key::C => return Some(ScreenState::Character),
+                        Key::Q => return Some(ScreenState::Quests),
+                        Key::T => self.show_clock = !self.show_clock,
+                        Key::Equals | Key::NumPadPlus => self.zoom(ZOOM_FACTOR),
+                        Key::Minus | Key::NumPadMinus => self.zoom(1.0 / ZOOM_FACTOR),
+                        Key::F => self.toggle_free_look(),
+                        Key::M => self.show_full_map = !self.show_full_map,
+                        Key::Up => self.pan_camera(0.0, -PAN_STEP),
+                        Key::Down => self.pan_camera(0.0, PAN_STEP),
+                        Key::Left => self.pan_camera(-PAN_STEP, 0.0),
+                        Key::Right => self.pan_camera(PAN_STEP, 0.0),
+                        Key::R => self.reset_puzzle(),
+                        Key::F5 => self.save_game(),
+                        Key::F9 => self.load_game(),
+                        Key::F2 => self.toggle_editor(),
+                        Key::F6 if self.editor.is_some() => self.save_map(),
+                        Key::Escape => return Some(ScreenState::Pause),
+                        _ => {}
                     }
                 }
+                _ => {}
             }
+            self.next_screen.take()
         }
     }
 
-    fn generate_map() -> Vec<Vec<u8>> {
-        let mut map = vec![vec![0u8; MAP_WIDTH]; MAP_HEIGHT];
+    /// Sprite name for the kind of thing a map entity is.
+    fn entity_kind(entity: &Entity) -> Option<&str> {
+        if let Some(prop) = &entity.prop {
+            Some(&prop.kind)
+        } else if let Some(kind) = entity.mechanism.as_ref().and_then(mechanisms::sprite_kind) {
+            Some(kind)
+        } else if let Some(door) = &entity.door {
+            Some(doors::sprite_kind(door))
+        } else if entity.foe.is_some() {
+            Some("foe")
+        } else if entity.npc.is_some() {
+            Some("npc")
+        } else if entity.portal.is_some() {
+            Some("portal")
+        } else if entity.pickup.is_some() {
+            Some("pickup")
+        } else {
+            None
+        }
+    }
 
-        // Set boundaries (1 on the edges)
-        for x in 0..MAP_WIDTH {
-            map[0][x] = 1;                  // Top boundary
-            map[MAP_HEIGHT - 1][x] = 1;     // Bottom boundary
+    /// Keeps the camera inside the world along one axis. A world smaller than
+    /// the window is centered instead.
+    fn clamp_camera(desired: f64, world_size: f64, window_size: f64) -> f64 {
+        if world_size <= window_size {
+            (world_size - window_size) / 2.0
+        } else {
+            desired.clamp(0.0, world_size - window_size)
         }
-        for y in 0..MAP_HEIGHT {
-            map[y][0] = 1;                  // Left boundary
-            map[y][MAP_WIDTH - 1] = 1;      // Right boundary
+    }
+
+    fn generate_encounter_zones(map: &TileMap) -> Vec<EncounterZone> {
+        // The far south-west corner is wild land
+        let (left, bottom) = (map.left(), map.bottom());
+        vec![EncounterZone {
+            min: (left + 1, bottom + 1),
+            max: (left + 10, bottom + 10),
+            table: "wilds".to_string(),
+        }]
+    }
+
+    fn generate_map_scripts(houses: &[House]) -> MapScripts {
+        let mut scripts = MapScripts {
+            on_enter: Some("area_enter".to_string()),
+            ..MapScripts::default()
+        };
+
+        for house in houses {
+            scripts.tiles.insert(house.entrance, Trigger::script("house_door"));
         }
 
-        // Add houses with corrected tuples
-        add_house(
-            &mut map,
-            (-10, 10),    // top_left corner as a tuple
-            (-5, 5),      // bottom_right corner as a tuple
-            (-7, 5),      // entrance position
-        );
+        scripts
+    }
+
+    pub(super) fn portal_entity(x: i32, y: i32, destination: (i32, i32)) -> Entity {
+        Entity::new(x, y)
+            .with_sprite(Sprite::Marker { color: INTERACTABLE_COLOR })
+            .with_interactable()
+            .with_portal(Portal {
+                destination_map: None, // A freshly generated map
+                destination_position: Some(destination),
+                generator: None,
+            })
+    }
+
+    pub(super) fn npc_entity(x: i32, y: i32, name: &str, dialogue: &str) -> Entity {
+        Entity::new(x, y)
+            .with_facing(Direction::Down)
+            .with_sprite(Sprite::Marker { color: NPC_COLOR })
+            .with_collider()
+            .with_interactable()
+            .with_npc(Npc {
+                name: name.to_string(),
+                dialogue: dialogue.to_string(),
+            })
+    }
 
-        add_house(
-            &mut map,
-            (5, -5),
-            (10, -10),
-            (7, -10),
-        );
+    fn foe_entity(x: i32, y: i32, enemies: &[&str], behaviour: Behaviour, interval: f64) -> Entity {
+        Entity::new(x, y)
+            .with_facing(Direction::Down)
+            .with_sprite(Sprite::Marker { color: FOE_COLOR })
+            .with_collider()
+            .with_ai(Ai::new(behaviour, Pace::Timer { interval }))
+            .with_foe(Foe {
+                enemies: enemies.iter().map(|id| id.to_string()).collect(),
+            })
+    }
 
-        // Randomly add obstacles
-        let mut rng = rand::thread_rng();
-        let obstacle_count = rng.gen_range(50..150); // Adjust as desired
+    pub(super) fn torch_entity(x: i32, y: i32) -> Entity {
+        Entity::new(x, y)
+            .with_sprite(Sprite::Marker { color: TORCH_COLOR })
+            .with_collider()
+            .with_prop(Prop { kind: "torch".to_string() })
+    }
 
-        for _ in 0..obstacle_count {
-            let x = rng.gen_range(GRID_MIN + 1..GRID_MAX);
-            let y = rng.gen_range(GRID_MIN + 1..GRID_MAX);
+    fn pickup_entity(x: i32, y: i32, item: &str, count: u32) -> Entity {
+        Entity::new(x, y)
+            .with_sprite(Sprite::Marker { color: PICKUP_COLOR })
+            .with_interactable()
+            .with_pickup(Pickup {
+                item: item.to_string(),
+                count,
+            })
+    }
 
-            // Convert grid coordinates to map indices
-            let map_x = (x - GRID_MIN) as usize;
-            let map_y = (GRID_MAX - y) as usize;
+    pub(super) fn door_entity(x: i32, y: i32, door: Door) -> Entity {
+        Entity::new(x, y)
+            .with_sprite(Sprite::Marker { color: DOOR_COLOR })
+            .with_interactable()
+            .with_door(door)
+    }
 
-            // Ensure we don't overwrite boundaries
-            if map_x < MAP_WIDTH && map_y < MAP_HEIGHT && map[map_y][map_x] == 0 {
-                map[map_y][map_x] = 1; // Place an obstacle
+    /// The entity for an object placed on a map file.
+    fn object_entity(x: i32, y: i32, object: &MapObject) -> Entity {
+        let with_script = |entity: Entity, script: &Option<String>| match script {
+            Some(script) => entity.with_hooks(Hooks {
+                on_interact: Some(script.clone()),
+                on_step: None,
+            }),
+            None => entity,
+        };
+
+        match object {
+            MapObject::Portal { destination_map, destination_position, generator, on_interact } => {
+                let mut portal = portal_entity(x, y, (0, 0));
+                portal.portal = Some(Portal {
+                    destination_map: destination_map.clone(),
+                    destination_position: *destination_position,
+                    generator: *generator,
+                });
+                with_script(portal, on_interact)
+            }
+            MapObject::Npc { name, dialogue, on_interact, schedule } => {
+                let npc = npc_entity(x, y, name, dialogue);
+                let npc = if schedule.is_empty() {
+                    npc
+                } else {
+                    let behaviour = Behaviour::Schedule { stops: schedule.clone() };
+                    npc.with_ai(Ai::new(behaviour, Pace::Timer { interval: SCHEDULE_WALK_INTERVAL }))
+                };
+                with_script(npc, on_interact)
+            }
+            MapObject::Pickup { item, count } => pickup_entity(x, y, item, *count),
+            MapObject::Prop { kind, solid } => {
+                let prop = Entity::new(x, y)
+                    .with_sprite(Sprite::Marker { color: TORCH_COLOR })
+                    .with_prop(Prop { kind: kind.clone() });
+                if *solid { prop.with_collider() } else { prop }
+            }
+            MapObject::Foe { enemies, behaviour, interval } => {
+                let enemies: Vec<&str> = enemies.iter().map(String::as_str).collect();
+                foe_entity(x, y, &enemies, behaviour.clone(), *interval)
+            }
+            MapObject::Door { open, lock } => door_entity(x, y, Door { open: *open, lock: lock.clone() }),
+            MapObject::Crate { kind } => Entity::new(x, y)
+                .with_sprite(Sprite::Marker { color: CRATE_COLOR })
+                .with_collider()
+                .with_prop(Prop { kind: kind.clone() })
+                .with_pushable(),
+            MapObject::Plate { link } => Entity::new(x, y)
+                .with_sprite(Sprite::Marker { color: PLATE_COLOR })
+                .with_mechanism(Mechanism::Plate { link: link.clone(), pressed: false }),
+            MapObject::Lever { link } => Entity::new(x, y)
+                .with_sprite(Sprite::Marker { color: LEVER_COLOR })
+                .with_collider()
+                .with_interactable()
+                .with_mechanism(Mechanism::Lever { link: link.clone(), on: false }),
+            MapObject::Gate { link } => door_entity(x, y, Door { open: false, lock: None })
+                .with_mechanism(Mechanism::Gate { link: link.clone() }),
+            // Drawn once raised
+            MapObject::Bridge { link } => {
+                Entity::new(x, y).with_mechanism(Mechanism::Bridge { link: link.clone(), raised: false })
             }
         }
+    }
 
-        map
+    /// Whether there is room to put something on a generated map. The floor
+    /// of a house is out of reach, since its door leads into the house's own map.
+    pub(super) fn is_free_tile(layout: &Layout, world: &World, x: i32, y: i32) -> bool {
+        let map = &layout.map;
+        map.ground(x, y) == FLOOR
+            && !map.is_solid(x, y)
+            && !layout.houses.iter().any(|house| house.covers(x, y))
+            && world.find_at(x, y, |_| true).is_none()
     }
 
-    fn generate_interactables(map: &Vec<Vec<u8>>) -> Vec<InteractablePoint> {
-        let mut interactables: Vec<InteractablePoint> = Vec::new();
-        let mut rng = rand::thread_rng();
-        let mut attempts = 0;
+    /// Puts people, loot and portals on a generated map, placed with the
+    /// random number generator that laid it out.
+    fn populate_world(world: &mut World, layout: &Layout, rng: &mut StdRng) {
+        let map = &layout.map;
+        let (spawn_x, spawn_y) = layout.spawn;
 
-        while interactables.len() < 5 && attempts < 1000 {
-            let x = rng.gen_range(GRID_MIN + 1..GRID_MAX);
-            let y = rng.gen_range(GRID_MIN + 1..GRID_MAX);
-
-            // Convert grid coordinates to map indices
-            let map_x = (x - GRID_MIN) as usize;
-            let map_y = (GRID_MAX - y) as usize;
-
-            // Check if the tile is empty
-            if map_x < MAP_WIDTH && map_y < MAP_HEIGHT && map[map_y][map_x] == 0 {
-                // Ensure no duplicate interactables
-                if !interactables.iter().any(|p| p.x == x && p.y == y) {
-                    // For demonstration, we'll set the destination position to a random point
-                    let dest_x = rng.gen_range(GRID_MIN + 1..GRID_MAX);
-                    let dest_y = rng.gen_range(GRID_MIN + 1..GRID_MAX);
-
-                    let interactable = InteractablePoint {
-                        x,
-                        y,
-                        destination_map: None, // For now, we'll leave it as None
-                        destination_position: Some((dest_x, dest_y)),
-                    };
+        let locked_house = houses::add_doors(world, layout, rng);
 
-                    interactables.push(interactable);
+        // Portals to a new area
+        let mut portals = 0;
+        let mut attempts = 0;
+        while portals < 5 && attempts < 1000 {
+            let x = rng.gen_range(map.left() + 1..map.right());
+            let y = rng.gen_range(map.bottom() + 1..map.top());
+
+            if is_free_tile(layout, world, x, y) {
+                // For demonstration, we'll set the destination position to a random point
+                let dest_x = rng.gen_range(map.left() + 1..map.right());
+                let dest_y = rng.gen_range(map.bottom() + 1..map.top());
+
+                let mut portal = portal_entity(x, y, (dest_x, dest_y));
+                if portals == 0 {
+                    // The first one leads to the grove, which was made in Tiled
+                    portal.portal = Some(Portal {
+                        destination_map: Some(GROVE_MAP.to_string()),
+                        destination_position: None,
+                        generator: None,
+                    });
+                } else if let Some(portal) = portal.portal.as_mut() {
+                    // The others lead to an area of any kind, the same one every time
+                    portal.generator = Some(MapGen {
+                        kind: GeneratorKind::ALL[rng.gen_range(0..GeneratorKind::ALL.len())],
+                        seed: Some(rng.gen()),
+                    });
                 }
+                world.spawn(portal);
+                portals += 1;
             }
 
             attempts += 1;
         }
 
-        interactables
+        // Some loot lying around
+        let mut loot = vec![("apple", 2), ("herb", 1), ("stone", 3)];
+        if locked_house {
+            loot.push((HOUSE_KEY, 1));
+        }
+        for (item, count) in loot {
+            for _ in 0..1000 {
+                let x = rng.gen_range(map.left() + 1..map.right());
+                let y = rng.gen_range(map.bottom() + 1..map.top());
+
+                if is_free_tile(layout, world, x, y) {
+                    world.spawn(pickup_entity(x, y, item, count));
+                    break;
+                }
+            }
+        }
+
+        // A couple of villagers to talk to, optionally driven by a script.
+        // ODO ambles about as the player walks, MARA keeps walking her rounds.
+        let villagers = [
+            ("ODO", "Strange lights glow in these fields.", Some("odo_talk")),
+            ("MARA", "Press E on a yellow light to travel.", None),
+        ];
+        for (name, dialogue, script) in villagers {
+            for _ in 0..1000 {
+                let x = rng.gen_range(map.left() + 1..map.right());
+                let y = rng.gen_range(map.bottom() + 1..map.top());
+
+                if is_free_tile(layout, world, x, y) && (x, y) != layout.spawn {
+                    let mut npc = npc_entity(x, y, name, dialogue);
+                    if let Some(script) = script {
+                        npc = npc.with_hooks(Hooks {
+                            on_interact: Some(script.to_string()),
+                            on_step: None,
+                        });
+                    }
+                    let behaviour = if script.is_some() {
+                        Behaviour::Wander
+                    } else {
+                        let corners = [(x, y), (x + 4, y), (x + 4, y - 4), (x, y - 4)];
+                        let route = corners
+                            .into_iter()
+                            .filter(|&(cx, cy)| (cx, cy) == (x, y) || is_free_tile(layout, world, cx, cy))
+                            .collect();
+                        Behaviour::Patrol { route, next: 0 }
+                    };
+                    let pace = if script.is_some() { Pace::Turn } else { Pace::Timer { interval: 0.8 } };
+                    world.spawn(npc.with_ai(Ai::new(behaviour, pace)));
+                    break;
+                }
+            }
+        }
+
+        // Roaming enemies, kept away from where the player arrives
+        let foes = [
+            (&["rat"][..], Behaviour::Chase { radius: 5 }, 0.7),
+            (&["rat", "rat"][..], Behaviour::Chase { radius: 4 }, 0.8),
+            (&["slime"][..], Behaviour::Flee { radius: 4 }, 0.5),
+        ];
+        for (enemies, behaviour, interval) in foes {
+            for _ in 0..1000 {
+                let x = rng.gen_range(map.left() + 1..map.right());
+                let y = rng.gen_range(map.bottom() + 1..map.top());
+
+                if is_free_tile(layout, world, x, y) && (x - spawn_x).abs() + (y - spawn_y).abs() > 8 {
+                    world.spawn(foe_entity(x, y, enemies, behaviour, interval));
+                    break;
+                }
+            }
+        }
     }
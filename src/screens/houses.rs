@@ -0,0 +1,103 @@
+//! Houses on generated maps. Every house gets a door with a doorway behind
+//! it, now and then a locked one whose key lies somewhere about. The inside
+//! is laid out from the doorway's seed, so it is the same on every visit.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use crate::ai::{Ai, Behaviour, Pace};
+use crate::entity::{Door, Doorway, Entity, Lock, Prop, Sprite, World};
+use crate::map::TileMap;
+use crate::mapgen::{self, Interior, Layout};
+use crate::script::MapScripts;
+use super::game::{door_entity, is_free_tile, npc_entity, torch_entity};
+
+const FURNITURE_COLOR: [f32; 4] = [0.6, 0.45, 0.25, 1.0]; // Wood brown for furniture
+// Chance that somebody is home in a house; empty ones have rats
+const RESIDENT_CHANCE: f64 = 0.6;
+// Chance that a generated map has a locked house, with the key lying about
+const LOCKED_HOUSE_CHANCE: f64 = 0.5;
+pub const HOUSE_KEY: &str = "old_key";
+
+/// The inside of a house, ready to be walked into.
+pub struct Inside {
+    pub map: TileMap,
+    pub spawn: (i32, i32),
+    pub scripts: MapScripts,
+}
+
+/// Puts a closed door into every house on a generated map, with torches on
+/// either side. Returns whether one of them is locked, in which case its
+/// key needs leaving about.
+pub fn add_doors(world: &mut World, layout: &Layout, rng: &mut StdRng) -> bool {
+    let houses = &layout.houses;
+    let locked_house = (!houses.is_empty() && rng.gen_bool(LOCKED_HOUSE_CHANCE)).then(|| rng.gen_range(0..houses.len()));
+    for (index, house) in houses.iter().enumerate() {
+        let (x, y) = house.outside();
+        let size = (house.max.0 - house.min.0 + 1, house.max.1 - house.min.1 + 1);
+        let (entrance_x, entrance_y) = house.entrance;
+        let lock = (locked_house == Some(index)).then(|| Lock {
+            key: Some(HOUSE_KEY.to_string()),
+            flag: None,
+        });
+        let door = door_entity(entrance_x, entrance_y, Door { open: false, lock }).with_doorway(Doorway::Inside {
+            seed: rng.gen(),
+            size,
+            outside: (x, y),
+        });
+        world.spawn(door);
+
+        let (dx, dy) = (y - house.entrance.1, x - house.entrance.0); // Along the wall
+        for side in [-1, 1] {
+            let (torch_x, torch_y) = (x + dx * side, y + dy * side);
+            if is_free_tile(layout, world, torch_x, torch_y) {
+                world.spawn(torch_entity(torch_x, torch_y));
+            }
+        }
+    }
+    locked_house.is_some()
+}
+
+/// Lays out the inside of a house whose outer walls measure `size`, and
+/// fills `world` with what is in it.
+pub fn inside(world: &mut World, seed: u64, size: (i32, i32)) -> Inside {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let interior = mapgen::interior(size, &mut rng);
+    let occupied = furnish(world, &interior, &mut rng);
+    Inside {
+        map: interior.layout.map,
+        spawn: interior.layout.spawn,
+        scripts: MapScripts {
+            on_enter: (!occupied).then(|| "house_inside".to_string()),
+            ..MapScripts::default()
+        },
+    }
+}
+
+/// Furnishes a house and puts the door back out in its exit. Returns
+/// whether somebody lives there, who then stands about somewhere inside.
+fn furnish(world: &mut World, interior: &Interior, rng: &mut StdRng) -> bool {
+    for &((x, y), kind) in &interior.furniture {
+        world.spawn(
+            Entity::new(x, y)
+                .with_sprite(Sprite::Marker { color: FURNITURE_COLOR })
+                .with_collider()
+                .with_prop(Prop { kind: kind.to_string() }),
+        );
+    }
+    let (exit_x, exit_y) = interior.exit;
+    world.spawn(Entity::new(exit_x, exit_y).with_doorway(Doorway::Outside));
+
+    let residents = [
+        ("PIP", "Mind the mud on your boots!"),
+        ("ELNA", "Travellers always find their way in here."),
+        ("BRAM", "Shut the door behind you, it's draughty."),
+    ];
+    let spots: Vec<(i32, i32)> = interior.floor.iter().copied().filter(|&spot| spot != interior.layout.spawn).collect();
+    if spots.is_empty() || !rng.gen_bool(RESIDENT_CHANCE) {
+        return false;
+    }
+    let (name, dialogue) = residents[rng.gen_range(0..residents.len())];
+    let (x, y) = spots[rng.gen_range(0..spots.len())];
+    world.spawn(npc_entity(x, y, name, dialogue).with_ai(Ai::new(Behaviour::Wander, Pace::Turn)));
+    true
+}
@@ -0,0 +1,158 @@
+use graphics::*;
+use graphics::types::Color;
+use piston::input::*;
+use opengl_graphics::{GlGraphics, GlyphCache};
+use crate::items::ItemCategory;
+use crate::screens::{draw_text, Screen, ScreenState};
+use crate::state::SharedState;
+
+const BACKGROUND_COLOR: Color = [0.05, 0.05, 0.1, 1.0];
+const SELECTED_COLOR: Color = [0.3, 0.3, 0.3, 1.0];
+const TEXT_COLOR: Color = [1.0, 1.0, 1.0, 1.0];
+const DIM_TEXT_COLOR: Color = [0.6, 0.6, 0.6, 1.0];
+const MARGIN: f64 = 40.0;
+const ROW_HEIGHT: f64 = 26.0;
+const LIST_TOP: f64 = 90.0;
+
+pub struct InventoryScreen {
+    state: SharedState,
+    selected: usize,
+    message: Option<String>,
+}
+
+impl InventoryScreen {
+    pub fn new(state: SharedState) -> Self {
+        InventoryScreen {
+            state,
+            selected: 0,
+            message: None,
+        }
+    }
+
+    fn move_selection(&mut self, delta: i32) {
+        let len = self.state.borrow().inventory.stacks().len();
+        if len == 0 {
+            self.selected = 0;
+            return;
+        }
+        self.selected = (self.selected as i32 + delta).rem_euclid(len as i32) as usize;
+    }
+
+    fn clamp_selection(&mut self) {
+        let len = self.state.borrow().inventory.stacks().len();
+        self.selected = self.selected.min(len.saturating_sub(1));
+    }
+
+    fn use_selected(&mut self) {
+        let mut state = self.state.borrow_mut();
+        let Some(stack) = state.inventory.stacks().get(self.selected).cloned() else {
+            return;
+        };
+        let Some(def) = state.items.get(&stack.item).cloned() else {
+            return;
+        };
+
+        self.message = Some(match (def.category, def.effect) {
+            (ItemCategory::Consumable, Some(effect)) => {
+                let healed = state.player.heal(effect.heal_hp);
+                let restored = state.player.restore_mp(effect.restore_mp);
+                if healed == 0 && restored == 0 {
+                    format!("The {} would have no effect.", def.name)
+                } else {
+                    state.inventory.take_from_slot(self.selected, 1);
+                    format!("You used the {}. +{} HP, +{} MP", def.name, healed, restored)
+                }
+            }
+            (ItemCategory::Consumable, None) => {
+                state.inventory.take_from_slot(self.selected, 1);
+                format!("You used the {}.", def.name)
+            }
+            _ => format!("The {} can't be used here.", def.name),
+        });
+        drop(state);
+        self.clamp_selection();
+    }
+
+    fn drop_selected(&mut self) {
+        let mut state = self.state.borrow_mut();
+        let Some(stack) = state.inventory.take_from_slot(self.selected, u32::MAX) else {
+            return;
+        };
+        self.message = Some(format!("Dropped {} x {}.", stack.count, state.items.name(&stack.item)));
+        state.dropped.push(stack);
+        drop(state);
+        self.clamp_selection();
+    }
+}
+
+impl Screen for InventoryScreen {
+    fn draw(&mut self, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache, window_size: [f64; 2]) {
+        clear(BACKGROUND_COLOR, g);
+
+        draw_text("INVENTORY", 24, TEXT_COLOR, [MARGIN, 50.0], c, g, glyphs);
+
+        let state = self.state.borrow();
+        let stacks = state.inventory.stacks();
+
+        if stacks.is_empty() {
+            draw_text("Your bag is empty.", 16, DIM_TEXT_COLOR, [MARGIN, LIST_TOP + ROW_HEIGHT * 0.7], c, g, glyphs);
+        }
+
+        for (index, stack) in stacks.iter().enumerate() {
+            let y = LIST_TOP + index as f64 * ROW_HEIGHT;
+            if index == self.selected {
+                rectangle(
+                    SELECTED_COLOR,
+                    [MARGIN - 10.0, y, window_size[0] / 2.0, ROW_HEIGHT],
+                    c.transform,
+                    g,
+                );
+            }
+            let line = format!("{} x {}", state.items.name(&stack.item), stack.count);
+            draw_text(&line, 16, TEXT_COLOR, [MARGIN, y + ROW_HEIGHT * 0.7], c, g, glyphs);
+        }
+
+        // Details of the selected item
+        let details_x = window_size[0] / 2.0 + MARGIN;
+        if let Some(def) = stacks.get(self.selected).and_then(|stack| state.items.get(&stack.item)) {
+            draw_text(&def.name, 16, TEXT_COLOR, [details_x, LIST_TOP + 18.0], c, g, glyphs);
+            draw_text(def.category.label(), 12, DIM_TEXT_COLOR, [details_x, LIST_TOP + 40.0], c, g, glyphs);
+            draw_text(&def.description, 12, TEXT_COLOR, [details_x, LIST_TOP + 66.0], c, g, glyphs);
+        }
+
+        if let Some(message) = &self.message {
+            draw_text(message, 16, TEXT_COLOR, [MARGIN, window_size[1] - 70.0], c, g, glyphs);
+        }
+        draw_text(
+            "W/S: SELECT   E: USE   X: DROP   I: CLOSE",
+            12,
+            DIM_TEXT_COLOR,
+            [MARGIN, window_size[1] - 30.0],
+            c,
+            g,
+            glyphs,
+        );
+    }
+
+    fn handle_input(&mut self, input: &Input) -> Option<ScreenState> {
+        if let Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            ..
+        }) = input
+        {
+            match key {
+                Key::W => self.move_selection(-1),
+                Key::S => self.move_selection(1),
+                Key::E => self.use_selected(),
+                Key::X => self.drop_selected(),
+                Key::I => {
+                    self.message = None;
+                    return Some(ScreenState::Game);
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+}
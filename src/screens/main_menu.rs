@@ -133,7 +133,8 @@ impl Screen for MainMenu {
     fn handle_input(&mut self, input: &Input) -> Option<ScreenState> {
         match input {
             Input::Move(Motion::MouseCursor(pos)) => {
-                self.update_hover_states(*pos, [800.0, 600.0]);
+                self.mouse_pos = *pos;
+                self.update_hover_states(self.mouse_pos, [800.0, 600.0]);
                 None
             }
             Input::Button(ButtonArgs {state: ButtonState::Press, button: Button::Mouse(MouseButton::Left), ..}) => {
@@ -1,18 +1,30 @@
 use std::collections::HashMap;
 use piston::input::*;
 use opengl_graphics::{GlGraphics, GlyphCache};
-use graphics::Context;
+use graphics::types::Color;
+use graphics::{Context, Transformed};
 
 pub mod main_menu;
 pub mod game;
 pub mod popup;
+pub mod inventory;
+pub mod character;
+pub mod quests;
+pub mod battle;
+pub mod editor;
+pub mod houses;
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum ScreenState {
     MainMenu,
     Game,
+    #[allow(dead_code)]
     Settings,
     Pause,
+    Inventory,
+    Character,
+    Quests,
+    Battle,
     // Add more screens as needed
 }
 
@@ -22,6 +34,23 @@ pub trait Screen {
     fn update(&mut self) -> Option<ScreenState> { None }
 }
 
+/// Draws a line of text with its baseline at `pos`.
+pub fn draw_text(text: &str, size: u32, color: Color, pos: [f64; 2], c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache) {
+    graphics::text::Text::new_color(color, size)
+        .draw(text, glyphs, &c.draw_state, c.transform.trans(pos[0], pos[1]), g)
+        .unwrap_or_else(|e| eprintln!("Error drawing text: {}", e));
+}
+
+/// Draws a horizontal bar filled to `current / max`.
+pub fn draw_bar(color: Color, current: i32, max: i32, pos: [f64; 2], width: f64, c: &Context, g: &mut GlGraphics) {
+    const BAR_HEIGHT: f64 = 12.0;
+    const BAR_BACKGROUND_COLOR: Color = [0.2, 0.2, 0.2, 1.0];
+
+    let fraction = if max > 0 { (current as f64 / max as f64).clamp(0.0, 1.0) } else { 0.0 };
+    graphics::rectangle(BAR_BACKGROUND_COLOR, [pos[0], pos[1], width, BAR_HEIGHT], c.transform, g);
+    graphics::rectangle(color, [pos[0], pos[1], width * fraction, BAR_HEIGHT], c.transform, g);
+}
+
 pub struct ScreenManager {
     screens: HashMap<ScreenState, Box<dyn Screen>>,
     current_screen: ScreenState,
@@ -52,6 +81,7 @@ impl ScreenManager {
         }
     }
 
+    #[allow(dead_code)]
     pub fn return_to_previous(&mut self) {
         if let Some(previous) = self.previous_screen {
             self.current_screen = previous;
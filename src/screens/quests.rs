@@ -0,0 +1,139 @@
+use graphics::*;
+use graphics::types::Color;
+use piston::input::*;
+use opengl_graphics::{GlGraphics, GlyphCache};
+use crate::quests::Objective;
+use crate::screens::{draw_text, Screen, ScreenState};
+use crate::state::{GameState, SharedState};
+
+const BACKGROUND_COLOR: Color = [0.05, 0.05, 0.1, 1.0];
+const SELECTED_COLOR: Color = [0.3, 0.3, 0.3, 1.0];
+const TEXT_COLOR: Color = [1.0, 1.0, 1.0, 1.0];
+const DIM_TEXT_COLOR: Color = [0.6, 0.6, 0.6, 1.0];
+const DONE_COLOR: Color = [0.4, 0.8, 0.4, 1.0];
+const MARGIN: f64 = 40.0;
+const ROW_HEIGHT: f64 = 26.0;
+const LIST_TOP: f64 = 90.0;
+
+/// Lists the quests under way, then the finished ones, with the current
+/// stage of the selected quest and how far along its objectives are.
+pub struct QuestLogScreen {
+    state: SharedState,
+    selected: usize,
+}
+
+impl QuestLogScreen {
+    pub fn new(state: SharedState) -> Self {
+        QuestLogScreen { state, selected: 0 }
+    }
+
+    fn entry_count(&self) -> usize {
+        let state = self.state.borrow();
+        state.quest_log.active().len() + state.quest_log.completed().len()
+    }
+
+    fn move_selection(&mut self, delta: i32) {
+        let len = self.entry_count();
+        if len == 0 {
+            self.selected = 0;
+            return;
+        }
+        self.selected = (self.selected as i32 + delta).rem_euclid(len as i32) as usize;
+    }
+}
+
+/// One line about an objective, with progress where it can be counted.
+fn objective_line(objective: &Objective, progress: u32, state: &GameState) -> String {
+    match objective {
+        Objective::Talk { npc } => format!("Talk to {}", npc),
+        Objective::Reach { map, at: None } => format!("Reach {}", map),
+        Objective::Reach { map, at: Some((x, y)) } => format!("Reach {} at {}, {}", map, x, y),
+        Objective::Collect { item, count } => format!(
+            "Collect {} {}/{}",
+            state.items.name(item),
+            state.inventory.count(item).min(*count),
+            count
+        ),
+        Objective::Defeat { enemy, count } => {
+            let name = state.enemies.get(enemy).map_or(enemy.as_str(), |def| def.name.as_str());
+            format!("Defeat {} {}/{}", name, progress.min(*count), count)
+        }
+        Objective::Flag { .. } => "Something still needs doing".to_string(),
+    }
+}
+
+impl Screen for QuestLogScreen {
+    fn draw(&mut self, c: &Context, g: &mut GlGraphics, glyphs: &mut GlyphCache, window_size: [f64; 2]) {
+        clear(BACKGROUND_COLOR, g);
+
+        draw_text("QUEST LOG", 24, TEXT_COLOR, [MARGIN, 50.0], c, g, glyphs);
+
+        let state = self.state.borrow();
+        let log = &state.quest_log;
+        self.selected = self.selected.min(self.entry_count().saturating_sub(1));
+
+        if self.entry_count() == 0 {
+            draw_text("No quests yet.", 16, DIM_TEXT_COLOR, [MARGIN, LIST_TOP + ROW_HEIGHT * 0.7], c, g, glyphs);
+        }
+
+        let active = log.active().iter().map(|progress| (&progress.quest, false));
+        let completed = log.completed().iter().map(|quest| (quest, true));
+        for (index, (quest, done)) in active.chain(completed).enumerate() {
+            let y = LIST_TOP + index as f64 * ROW_HEIGHT;
+            if index == self.selected {
+                rectangle(SELECTED_COLOR, [MARGIN - 10.0, y, window_size[0] / 2.0, ROW_HEIGHT], c.transform, g);
+            }
+            let name = state.quests.get(quest).map_or(quest.as_str(), |def| def.name.as_str());
+            let color = if done { DIM_TEXT_COLOR } else { TEXT_COLOR };
+            draw_text(name, 16, color, [MARGIN, y + ROW_HEIGHT * 0.7], c, g, glyphs);
+        }
+
+        // Details of the selected quest
+        let details_x = window_size[0] / 2.0 + MARGIN;
+        let progress = log.active().get(self.selected);
+        let quest = match progress {
+            Some(progress) => Some(&progress.quest),
+            None => self.selected.checked_sub(log.active().len()).and_then(|index| log.completed().get(index)),
+        };
+        if let Some(def) = quest.and_then(|quest| state.quests.get(quest)) {
+            draw_text(&def.name, 16, TEXT_COLOR, [details_x, LIST_TOP + 18.0], c, g, glyphs);
+            draw_text(&def.description, 12, DIM_TEXT_COLOR, [details_x, LIST_TOP + 44.0], c, g, glyphs);
+            match progress {
+                Some(progress) => {
+                    let stage = &def.stages[progress.stage];
+                    draw_text(&stage.description, 12, TEXT_COLOR, [details_x, LIST_TOP + 76.0], c, g, glyphs);
+                    for (row, (objective, &count)) in stage.objectives.iter().zip(&progress.progress).enumerate() {
+                        let color = if objective.is_met(count, &state.inventory, &state.flags) {
+                            DONE_COLOR
+                        } else {
+                            TEXT_COLOR
+                        };
+                        let line = format!("- {}", objective_line(objective, count, &state));
+                        let y = LIST_TOP + 100.0 + row as f64 * 20.0;
+                        draw_text(&line, 12, color, [details_x, y], c, g, glyphs);
+                    }
+                }
+                None => draw_text("COMPLETE", 12, DONE_COLOR, [details_x, LIST_TOP + 76.0], c, g, glyphs),
+            }
+        }
+
+        draw_text("W/S: SELECT   Q: CLOSE", 12, DIM_TEXT_COLOR, [MARGIN, window_size[1] - 30.0], c, g, glyphs);
+    }
+
+    fn handle_input(&mut self, input: &Input) -> Option<ScreenState> {
+        if let Input::Button(ButtonArgs {
+            state: ButtonState::Press,
+            button: Button::Keyboard(key),
+            ..
+        }) = input
+        {
+            match key {
+                Key::W => self.move_selection(-1),
+                Key::S => self.move_selection(1),
+                Key::Q => return Some(ScreenState::Game),
+                _ => {}
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,710 @@
+//! A tiny command language for map events.
+//!
+//! Scripts live in `assets/data/scripts.txt`. Each script starts with an `@name`
+//! header followed by one command per line:
+//!
+//! ```text
+//! @odo_talk
+//! unless met_odo popup "ODO: Strange lights glow in these fields."
+//! if met_odo popup "ODO: Back again?"
+//! set met_odo
+//! add visits 1
+//! if visits >= 3 popup "ODO: You again!"
+//! ```
+//!
+//! Scripts can only touch the game through [`ScriptHost`], so a broken script can
+//! never reach further than popups, the player, flags and spawning entities.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::entity::Direction;
+use crate::triggers::Trigger;
+
+/// How many scripts may be triggered by other scripts (e.g. `new_map` queuing an
+/// on-enter hook) before the chain is cut off.
+pub const MAX_CHAINED_SCRIPTS: usize = 16;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Popup { text: String, duration: f64 },
+    MovePlayer { x: i32, y: i32 },
+    FacePlayer(Direction),
+    Set { name: String, value: Value },
+    Clear(String),
+    Add { name: String, amount: i32 },
+    If { condition: Condition, expected: bool, then: Box<Command> },
+    SpawnNpc { x: i32, y: i32, name: String, dialogue: String },
+    SpawnPortal { x: i32, y: i32, destination: (i32, i32) },
+    NewMap { destination: Option<(i32, i32)> },
+    GiveXp(u32),
+    Battle(Vec<String>),
+    ResetPuzzle,
+    StartQuest(String),
+    Cutscene(String),
+    SetTime { hour: u32, minute: u32 },
+    TimeScale(f64),
+}
+
+/// What an `if` or `unless` checks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// A variable is set to something other than false, zero or empty text
+    Flag(String),
+    Compare { name: String, op: CompareOp, value: Value },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl CompareOp {
+    fn from_token(token: &str) -> Option<CompareOp> {
+        match token {
+            "==" => Some(CompareOp::Equal),
+            "!=" => Some(CompareOp::NotEqual),
+            "<" => Some(CompareOp::Less),
+            "<=" => Some(CompareOp::LessOrEqual),
+            ">" => Some(CompareOp::Greater),
+            ">=" => Some(CompareOp::GreaterOrEqual),
+            _ => None,
+        }
+    }
+}
+
+pub type Script = Vec<Command>;
+
+/// The sandboxed API scripts run against.
+pub trait ScriptHost {
+    fn show_popup(&mut self, text: &str, duration: f64);
+    fn move_player(&mut self, x: i32, y: i32);
+    fn face_player(&mut self, direction: Direction);
+    fn set_flag(&mut self, flag: &str, value: bool);
+    fn flag(&self, flag: &str) -> bool;
+    fn set_var(&mut self, name: &str, value: Value);
+    fn var(&self, name: &str) -> Option<Value>;
+    fn spawn_npc(&mut self, x: i32, y: i32, name: &str, dialogue: &str);
+    fn spawn_portal(&mut self, x: i32, y: i32, destination: (i32, i32));
+    fn change_map(&mut self, destination: Option<(i32, i32)>);
+    fn give_xp(&mut self, amount: u32);
+    fn start_battle(&mut self, enemies: &[String]);
+    /// Puts crates and levers on the map back where they started
+    fn reset_puzzle(&mut self);
+    /// Adds a quest to the log, unless it is already there
+    fn start_quest(&mut self, quest: &str);
+    /// Takes control from the player to play a cutscene from cutscenes.txt
+    fn play_cutscene(&mut self, name: &str);
+    /// Winds the clock forward to the next time it reads `hour:minute`
+    fn set_time(&mut self, hour: u32, minute: u32);
+    /// Sets how many game minutes pass every real second
+    fn set_time_scale(&mut self, scale: f64);
+}
+
+/// Script hooks attached to an entity.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Hooks {
+    pub on_interact: Option<String>,
+    pub on_step: Option<String>,
+}
+
+/// A rectangle of tiles that runs a script when the player walks into it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+    pub on_enter: String,
+}
+
+impl Region {
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+}
+
+/// Script hooks attached to the map itself rather than to an entity.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MapScripts {
+    pub on_enter: Option<String>,
+    /// Step-on triggers, by tile
+    #[serde(with = "crate::save::coord_map")]
+    pub tiles: HashMap<(i32, i32), Trigger>,
+    pub regions: Vec<Region>,
+}
+
+#[derive(Debug, Default)]
+pub struct ScriptLibrary {
+    scripts: HashMap<String, Script>,
+}
+
+impl ScriptLibrary {
+    pub fn builtin() -> Self {
+        ScriptLibrary::parse(include_str!("../assets/data/scripts.txt"))
+            .unwrap_or_else(|e| panic!("Invalid built-in scripts: {}", e))
+    }
+
+    pub fn parse(source: &str) -> Result<Self, String> {
+        Ok(ScriptLibrary {
+            scripts: parse_sections(source, parse_command)?,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Script> {
+        self.scripts.get(name)
+    }
+
+    /// Runs the named script, reporting unknown names instead of failing.
+    pub fn run(&self, name: &str, host: &mut dyn ScriptHost) {
+        match self.get(name) {
+            Some(script) => {
+                for command in script {
+                    execute(command, host);
+                }
+            }
+            None => eprintln!("Unknown script '{}'", name),
+        }
+    }
+}
+
+fn execute(command: &Command, host: &mut dyn ScriptHost) {
+    match command {
+        Command::Popup { text, duration } => host.show_popup(text, *duration),
+        Command::MovePlayer { x, y } => host.move_player(*x, *y),
+        Command::FacePlayer(direction) => host.face_player(*direction),
+        Command::Set { name, value } => host.set_var(name, value.clone()),
+        Command::Clear(name) => host.set_flag(name, false),
+        Command::Add { name, amount } => {
+            let current = host.var(name).map_or(0, |value| value.as_int());
+            host.set_var(name, Value::Int(current.saturating_add(*amount)));
+        }
+        Command::If { condition, expected, then } => {
+            if holds(condition, host) == *expected {
+                execute(then, host);
+            }
+        }
+        Command::SpawnNpc { x, y, name, dialogue } => host.spawn_npc(*x, *y, name, dialogue),
+        Command::SpawnPortal { x, y, destination } => host.spawn_portal(*x, *y, *destination),
+        Command::NewMap { destination } => host.change_map(*destination),
+        Command::GiveXp(amount) => host.give_xp(*amount),
+        Command::Battle(enemies) => host.start_battle(enemies),
+        Command::ResetPuzzle => host.reset_puzzle(),
+        Command::StartQuest(quest) => host.start_quest(quest),
+        Command::Cutscene(name) => host.play_cutscene(name),
+        Command::SetTime { hour, minute } => host.set_time(*hour, *minute),
+        Command::TimeScale(scale) => host.set_time_scale(*scale),
+    }
+}
+
+fn holds(condition: &Condition, host: &dyn ScriptHost) -> bool {
+    match condition {
+        Condition::Flag(flag) => host.flag(flag),
+        Condition::Compare { name, op, value } => {
+            // A variable that was never set counts as zero
+            let current = host.var(name).unwrap_or(Value::Int(0));
+            current.compare(*op, value)
+        }
+    }
+}
+
+/// Reads `@name` sections of one command per line, the layout shared by
+/// scripts and cutscenes. Blank lines and `#` comments are skipped.
+pub fn parse_sections<T>(
+    source: &str,
+    parse_line: impl Fn(&[String]) -> Result<T, String>,
+) -> Result<HashMap<String, Vec<T>>, String> {
+    let mut sections = HashMap::new();
+    let mut current: Option<(String, Vec<T>)> = None;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('@') {
+            if let Some((name, lines)) = current.take() {
+                sections.insert(name, lines);
+            }
+            let name = name.trim();
+            if sections.contains_key(name) {
+                return Err(format!("line {}: section '{}' is defined twice", index + 1, name));
+            }
+            current = Some((name.to_string(), Vec::new()));
+            continue;
+        }
+
+        let Some((_, lines)) = current.as_mut() else {
+            return Err(format!("line {}: command outside of a section", index + 1));
+        };
+        let tokens = tokenize(line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        let parsed = parse_line(&tokens).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        lines.push(parsed);
+    }
+
+    if let Some((name, lines)) = current.take() {
+        sections.insert(name, lines);
+    }
+
+    Ok(sections)
+}
+
+/// Splits a line on whitespace, keeping "quoted strings" together.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+pub fn parse_int(token: Option<&String>, what: &str) -> Result<i32, String> {
+    token
+        .ok_or_else(|| format!("missing {}", what))?
+        .parse()
+        .map_err(|_| format!("invalid {}", what))
+}
+
+pub fn parse_text(token: Option<&String>, what: &str) -> Result<String, String> {
+    token.cloned().ok_or_else(|| format!("missing {}", what))
+}
+
+fn parse_command(tokens: &[String]) -> Result<Command, String> {
+    let Some(keyword) = tokens.first() else {
+        return Err("empty command".to_string());
+    };
+    let args = &tokens[1..];
+
+    // `if` and `unless` check the command they run themselves, and `battle`
+    // takes any number of enemies
+    let max_args = match keyword.as_str() {
+        "reset_puzzle" => 0,
+        "face" | "clear" | "give_xp" | "start_quest" | "cutscene" | "time_scale" => 1,
+        "popup" | "move" | "set" | "add" | "new_map" | "set_time" => 2,
+        "spawn_npc" | "spawn_portal" => 4,
+        _ => usize::MAX,
+    };
+    if args.len() > max_args {
+        return Err("too many arguments".to_string());
+    }
+
+    let command = match keyword.as_str() {
+        "popup" => Command::Popup {
+            text: parse_text(args.first(), "popup text")?,
+            duration: match args.get(1) {
+                Some(secs) => secs.parse().map_err(|_| "invalid popup duration".to_string())?,
+                None => 2.0,
+            },
+        },
+        "move" => Command::MovePlayer {
+            x: parse_int(args.first(), "x")?,
+            y: parse_int(args.get(1), "y")?,
+        },
+        "face" => Command::FacePlayer(
+            args.first()
+                .and_then(|d| Direction::from_name(d))
+                .ok_or("expected up, down, left or right")?,
+        ),
+        "set" => Command::Set {
+            name: parse_text(args.first(), "variable name")?,
+            value: args.get(1).map_or(Value::Bool(true), |token| Value::parse(token)),
+        },
+        "clear" => Command::Clear(parse_text(args.first(), "variable name")?),
+        "add" => Command::Add {
+            name: parse_text(args.first(), "variable name")?,
+            amount: parse_int(args.get(1), "amount")?,
+        },
+        "if" | "unless" => {
+            let name = parse_text(args.first(), "flag name")?;
+            // Either `if <flag> <command>` or `if <variable> <op> <value> <command>`
+            let (condition, rest) = match args.get(1).and_then(|token| CompareOp::from_token(token)) {
+                Some(op) => {
+                    let value = Value::parse(&parse_text(args.get(2), "value to compare with")?);
+                    (Condition::Compare { name, op, value }, &args[3.min(args.len())..])
+                }
+                None => (Condition::Flag(name), &args[1.min(args.len())..]),
+            };
+            Command::If {
+                condition,
+                expected: keyword == "if",
+                then: Box::new(parse_command(rest)?),
+            }
+        }
+        "spawn_npc" => Command::SpawnNpc {
+            x: parse_int(args.first(), "x")?,
+            y: parse_int(args.get(1), "y")?,
+            name: parse_text(args.get(2), "NPC name")?,
+            dialogue: parse_text(args.get(3), "NPC dialogue")?,
+        },
+        "spawn_portal" => Command::SpawnPortal {
+            x: parse_int(args.first(), "x")?,
+            y: parse_int(args.get(1), "y")?,
+            destination: (
+                parse_int(args.get(2), "destination x")?,
+                parse_int(args.get(3), "destination y")?,
+            ),
+        },
+        "new_map" => Command::NewMap {
+            destination: match args.len() {
+                0 => None,
+                _ => Some((parse_int(args.first(), "x")?, parse_int(args.get(1), "y")?)),
+            },
+        },
+        "give_xp" => Command::GiveXp(
+            parse_int(args.first(), "experience amount")?
+                .try_into()
+                .map_err(|_| "experience amount must not be negative".to_string())?,
+        ),
+        "battle" => {
+            if args.is_empty() {
+                return Err("battle needs at least one enemy".to_string());
+            }
+            Command::Battle(args.to_vec())
+        }
+        "reset_puzzle" => Command::ResetPuzzle,
+        "start_quest" => Command::StartQuest(parse_text(args.first(), "quest id")?),
+        "cutscene" => Command::Cutscene(parse_text(args.first(), "cutscene name")?),
+        "set_time" => {
+            let hour = parse_int(args.first(), "hour")?;
+            let minute = match args.len() {
+                1 => 0,
+                _ => parse_int(args.get(1), "minute")?,
+            };
+            if !(0..24).contains(&hour) || !(0..60).contains(&minute) {
+                return Err("set_time needs an hour from 0 to 23 and a minute from 0 to 59".to_string());
+            }
+            Command::SetTime { hour: hour as u32, minute: minute as u32 }
+        }
+        "time_scale" => Command::TimeScale(match args.first().map(|token| token.parse::<f64>()) {
+            Some(Ok(scale)) if scale >= 0.0 => scale,
+            _ => return Err("time_scale needs a number of game minutes per second, 0 or more".to_string()),
+        }),
+        other => return Err(format!("unknown command '{}'", other)),
+    };
+
+    Ok(command)
+}
+
+/// A story variable: a flag, a counter or a bit of text.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Int(i32),
+    Text(String),
+}
+
+impl Value {
+    /// Reads a script token: `true` or `false`, a whole number, or else text.
+    pub fn parse(token: &str) -> Value {
+        match token {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => token.parse().map_or_else(|_| Value::Text(token.to_string()), Value::Int),
+        }
+    }
+
+    /// Whether the value counts as set: true, non-zero or non-empty.
+    pub fn is_set(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            Value::Int(value) => *value != 0,
+            Value::Text(text) => !text.is_empty(),
+        }
+    }
+
+    /// The value as a number, with true as 1 and text as 0.
+    pub fn as_int(&self) -> i32 {
+        match self {
+            Value::Bool(value) => *value as i32,
+            Value::Int(value) => *value,
+            Value::Text(_) => 0,
+        }
+    }
+
+    /// Text is only ever equal or not equal to text; anything else compares
+    /// as a number.
+    pub fn compare(&self, op: CompareOp, other: &Value) -> bool {
+        let ordering = match (self, other) {
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            (Value::Text(_), _) | (_, Value::Text(_)) => return op == CompareOp::NotEqual,
+            _ => self.as_int().cmp(&other.as_int()),
+        };
+        match op {
+            CompareOp::Equal => ordering.is_eq(),
+            CompareOp::NotEqual => ordering.is_ne(),
+            CompareOp::Less => ordering.is_lt(),
+            CompareOp::LessOrEqual => ordering.is_le(),
+            CompareOp::Greater => ordering.is_gt(),
+            CompareOp::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+/// Story variables set by scripts and quests. Most are flags, which are set
+/// or not, but they can also count things or hold text.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Flags {
+    values: HashMap<String, Value>,
+}
+
+impl Flags {
+    pub fn get(&self, flag: &str) -> bool {
+        self.values.get(flag).is_some_and(Value::is_set)
+    }
+
+    pub fn set(&mut self, flag: &str, value: bool) {
+        if value {
+            self.values.insert(flag.to_string(), Value::Bool(true));
+        } else {
+            self.values.remove(flag);
+        }
+    }
+
+    pub fn value(&self, name: &str) -> Option<&Value> {
+        self.values.get(name)
+    }
+
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        self.values.insert(name.to_string(), value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_one(line: &str) -> Result<Command, String> {
+        let library = ScriptLibrary::parse(&format!("@test\n{}\n", line))?;
+        Ok(library.get("test").unwrap()[0].clone())
+    }
+
+    fn popup(text: &str, duration: f64) -> Command {
+        Command::Popup { text: text.to_string(), duration }
+    }
+
+    #[test]
+    fn sections_skip_blank_lines_and_comments() {
+        let library = ScriptLibrary::parse(
+            "# A comment before any section\n\
+             @first\n\
+             popup \"One\"\n\
+             \n\
+             \x20   # An indented comment\n\
+             set seen\n\
+             @second\n\
+             @third\n\
+             give_xp 5\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            library.get("first"),
+            Some(&vec![popup("One", 2.0), Command::Set { name: "seen".to_string(), value: Value::Bool(true) }])
+        );
+        assert_eq!(library.get("second"), Some(&Vec::new()));
+        assert_eq!(library.get("third"), Some(&vec![Command::GiveXp(5)]));
+        assert_eq!(library.get("fourth"), None);
+    }
+
+    #[test]
+    fn quotes_keep_text_together() {
+        assert_eq!(parse_one("popup \"Hello,  # world\" 3"), Ok(popup("Hello,  # world", 3.0)));
+        assert_eq!(parse_one("popup \"\""), Ok(popup("", 2.0)));
+        assert_eq!(
+            parse_one("spawn_npc 1 -2 \"OLD MAN\" \"It's dangerous.\""),
+            Ok(Command::SpawnNpc { x: 1, y: -2, name: "OLD MAN".to_string(), dialogue: "It's dangerous.".to_string() })
+        );
+    }
+
+    #[test]
+    fn conditions_wrap_the_command_they_run() {
+        assert_eq!(
+            parse_one("unless met_odo popup \"Hi\""),
+            Ok(Command::If {
+                condition: Condition::Flag("met_odo".to_string()),
+                expected: false,
+                then: Box::new(popup("Hi", 2.0)),
+            })
+        );
+        assert_eq!(
+            parse_one("if visits >= 3 if met_odo give_xp 1"),
+            Ok(Command::If {
+                condition: Condition::Compare {
+                    name: "visits".to_string(),
+                    op: CompareOp::GreaterOrEqual,
+                    value: Value::Int(3),
+                },
+                expected: true,
+                then: Box::new(Command::If {
+                    condition: Condition::Flag("met_odo".to_string()),
+                    expected: true,
+                    then: Box::new(Command::GiveXp(1)),
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn bad_input_is_rejected_with_its_line() {
+        let error = |source: &str| ScriptLibrary::parse(source).unwrap_err();
+
+        assert_eq!(error("popup \"Lost\""), "line 1: command outside of a section");
+        assert_eq!(error("@a\npopup \"Unfinished"), "line 2: unterminated string");
+        assert_eq!(error("@a\ndance"), "line 2: unknown command 'dance'");
+        assert_eq!(error("@a\nset x\n@b\n@a\n"), "line 4: section 'a' is defined twice");
+        assert_eq!(error("@a\nmove 1 2 3"), "line 2: too many arguments");
+        assert_eq!(error("@a\npopup \"hi\" 2 junk"), "line 2: too many arguments");
+        assert_eq!(error("@a\nif met_odo move 1 2 3"), "line 2: too many arguments");
+        assert_eq!(error("@a\nreset_puzzle now"), "line 2: too many arguments");
+        assert_eq!(error("@a\nmove 1"), "line 2: missing y");
+        assert_eq!(error("@a\nmove one 2"), "line 2: invalid x");
+        assert_eq!(error("@a\nface north"), "line 2: expected up, down, left or right");
+        assert_eq!(error("@a\ngive_xp -5"), "line 2: experience amount must not be negative");
+        assert_eq!(error("@a\nbattle"), "line 2: battle needs at least one enemy");
+        assert_eq!(error("@a\nif met_odo"), "line 2: empty command");
+        assert!(error("@a\nset_time 24").starts_with("line 2: set_time needs"));
+    }
+
+    /// Keeps story variables and ignores everything else scripts ask for.
+    #[derive(Default)]
+    struct VarHost {
+        flags: Flags,
+    }
+
+    impl ScriptHost for VarHost {
+        fn show_popup(&mut self, _text: &str, _duration: f64) {}
+        fn move_player(&mut self, _x: i32, _y: i32) {}
+        fn face_player(&mut self, _direction: Direction) {}
+        fn set_flag(&mut self, flag: &str, value: bool) {
+            self.flags.set(flag, value);
+        }
+        fn flag(&self, flag: &str) -> bool {
+            self.flags.get(flag)
+        }
+        fn set_var(&mut self, name: &str, value: Value) {
+            self.flags.set_value(name, value);
+        }
+        fn var(&self, name: &str) -> Option<Value> {
+            self.flags.value(name).cloned()
+        }
+        fn spawn_npc(&mut self, _x: i32, _y: i32, _name: &str, _dialogue: &str) {}
+        fn spawn_portal(&mut self, _x: i32, _y: i32, _destination: (i32, i32)) {}
+        fn change_map(&mut self, _destination: Option<(i32, i32)>) {}
+        fn give_xp(&mut self, _amount: u32) {}
+        fn start_battle(&mut self, _enemies: &[String]) {}
+        fn reset_puzzle(&mut self) {}
+        fn start_quest(&mut self, _quest: &str) {}
+        fn play_cutscene(&mut self, _name: &str) {}
+        fn set_time(&mut self, _hour: u32, _minute: u32) {}
+        fn set_time_scale(&mut self, _scale: f64) {}
+    }
+
+    fn run(source: &str, host: &mut VarHost) {
+        let library = ScriptLibrary::parse(&format!("@test\n{}\n", source)).unwrap();
+        library.run("test", host);
+    }
+
+    #[test]
+    fn values_read_from_tokens() {
+        assert_eq!(Value::parse("true"), Value::Bool(true));
+        assert_eq!(Value::parse("false"), Value::Bool(false));
+        assert_eq!(Value::parse("-12"), Value::Int(-12));
+        assert_eq!(Value::parse("12a"), Value::Text("12a".to_string()));
+        assert!(!Value::Int(0).is_set());
+        assert!(!Value::Text(String::new()).is_set());
+        assert!(Value::Text("x".to_string()).is_set());
+    }
+
+    #[test]
+    fn values_compare_as_numbers_unless_text() {
+        use CompareOp::*;
+        let (one, two) = (Value::Int(1), Value::Int(2));
+        assert!(one.compare(Less, &two) && one.compare(LessOrEqual, &two) && one.compare(NotEqual, &two));
+        assert!(two.compare(Greater, &one) && two.compare(GreaterOrEqual, &two) && two.compare(Equal, &two));
+        assert!(!two.compare(Less, &one));
+
+        // Flags count as 0 or 1
+        assert!(Value::Bool(true).compare(Equal, &one));
+        assert!(Value::Bool(false).compare(Less, &one));
+
+        let (red, blue) = (Value::Text("red".to_string()), Value::Text("blue".to_string()));
+        assert!(red.compare(Equal, &red.clone()) && red.compare(NotEqual, &blue));
+        // Text is never equal to, or ordered against, a number
+        let three = Value::Text("3".to_string());
+        assert!(!three.compare(Equal, &Value::Int(3)));
+        assert!(three.compare(NotEqual, &Value::Int(3)));
+        assert!(!three.compare(Less, &Value::Int(9)) && !three.compare(Greater, &Value::Int(0)));
+    }
+
+    #[test]
+    fn add_counts_up_from_whatever_is_there() {
+        let mut host = VarHost::default();
+        run("add visits 2\nadd visits 3", &mut host);
+        assert_eq!(host.var("visits"), Some(Value::Int(5)));
+
+        // Flags count as 0 or 1, text as 0
+        run("set met\nadd met 1\nset name \"ODO\"\nadd name 4\nclear gone\nadd gone -1", &mut host);
+        assert_eq!(host.var("met"), Some(Value::Int(2)));
+        assert_eq!(host.var("name"), Some(Value::Int(4)));
+        assert_eq!(host.var("gone"), Some(Value::Int(-1)));
+
+        run("set big 2147483000\nadd big 2147483000\nset small -2147483000\nadd small -2147483000", &mut host);
+        assert_eq!(host.var("big"), Some(Value::Int(i32::MAX)));
+        assert_eq!(host.var("small"), Some(Value::Int(i32::MIN)));
+    }
+
+    #[test]
+    fn conditions_check_variables() {
+        let mut host = VarHost::default();
+        run(
+            "add visits 3\n\
+             if visits >= 3 set many\n\
+             if visits < 3 set few\n\
+             unless never set unset_is_false\n\
+             if never == 0 set unset_is_zero\n\
+             set name ODO\n\
+             if name == ODO set named",
+            &mut host,
+        );
+        for flag in ["many", "unset_is_false", "unset_is_zero", "named"] {
+            assert!(host.flag(flag), "{} should be set", flag);
+        }
+        assert!(!host.flag("few"));
+    }
+
+    #[test]
+    fn builtin_scripts_parse() {
+        let library = ScriptLibrary::builtin();
+        assert!(library.get("odo_talk").is_some());
+    }
+}
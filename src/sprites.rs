@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::path::Path;
+use graphics::{Context, Image, ImageSize};
+use opengl_graphics::{Filter, GlGraphics, Texture, TextureSettings};
+use serde::Deserialize;
+
+/// A PNG in `assets/` cut into equally sized frames, numbered left to right,
+/// top to bottom.
+#[derive(Clone, Debug, Deserialize)]
+pub struct AtlasDef {
+    pub id: String,
+    pub image: String,
+    pub frame_width: u32,
+    pub frame_height: u32,
+}
+
+/// A named frame of an atlas.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpriteDef {
+    pub id: String,
+    pub atlas: String,
+    pub frame: u32,
+}
+
+/// Which atlases exist and what is on them, loaded from `assets/data/sprites.json`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SpriteData {
+    pub atlases: Vec<AtlasDef>,
+    pub sprites: Vec<SpriteDef>,
+}
+
+impl SpriteData {
+    pub fn builtin() -> Self {
+        SpriteData::from_json(include_str!("../assets/data/sprites.json"))
+            .unwrap_or_else(|e| panic!("Invalid built-in sprites: {}", e))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let data: SpriteData = serde_json::from_str(source).map_err(|e| e.to_string())?;
+
+        for (index, atlas) in data.atlases.iter().enumerate() {
+            if atlas.frame_width == 0 || atlas.frame_height == 0 {
+                return Err(format!("atlas '{}' has an empty frame size", atlas.id));
+            }
+            if data.atlases[..index].iter().any(|other| other.id == atlas.id) {
+                return Err(format!("atlas '{}' is defined twice", atlas.id));
+            }
+        }
+        for (index, sprite) in data.sprites.iter().enumerate() {
+            if !data.atlases.iter().any(|atlas| atlas.id == sprite.atlas) {
+                return Err(format!("sprite '{}' uses unknown atlas '{}'", sprite.id, sprite.atlas));
+            }
+            if data.sprites[..index].iter().any(|other| other.id == sprite.id) {
+                return Err(format!("sprite '{}' is defined twice", sprite.id));
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+struct Atlas {
+    texture: Texture,
+    columns: u32,
+    rows: u32,
+    frame_width: u32,
+    frame_height: u32,
+}
+
+/// The loaded atlases. Sprites whose image is missing are left out, so
+/// callers fall back to drawing shapes for them.
+pub struct SpriteSheet {
+    atlases: HashMap<String, Atlas>,
+    /// Atlas id and source rectangle of every sprite that can be drawn
+    frames: HashMap<String, (String, [f64; 4])>,
+}
+
+impl SpriteSheet {
+    /// Loads every atlas image from `assets_dir`, skipping those that can't be read.
+    pub fn load(data: &SpriteData, assets_dir: &Path) -> Self {
+        let settings = TextureSettings::new().filter(Filter::Nearest);
+        let mut atlases = HashMap::new();
+
+        for def in &data.atlases {
+            let path = assets_dir.join(&def.image);
+            match Texture::from_path(&path, &settings) {
+                Ok(texture) => {
+                    let (width, height) = texture.get_size();
+                    atlases.insert(
+                        def.id.clone(),
+                        Atlas {
+                            texture,
+                            columns: width / def.frame_width,
+                            rows: height / def.frame_height,
+                            frame_width: def.frame_width,
+                            frame_height: def.frame_height,
+                        },
+                    );
+                }
+                Err(e) => eprintln!("Could not load atlas '{}' from {:?}: {}", def.id, path, e),
+            }
+        }
+
+        let mut frames = HashMap::new();
+        for sprite in &data.sprites {
+            let Some(atlas) = atlases.get(&sprite.atlas) else {
+                continue;
+            };
+            if sprite.frame >= atlas.columns * atlas.rows {
+                eprintln!("Sprite '{}' uses frame {} beyond the end of atlas '{}'", sprite.id, sprite.frame, sprite.atlas);
+                continue;
+            }
+
+            let column = sprite.frame % atlas.columns;
+            let row = sprite.frame / atlas.columns;
+            let source = [
+                (column * atlas.frame_width) as f64,
+                (row * atlas.frame_height) as f64,
+                atlas.frame_width as f64,
+                atlas.frame_height as f64,
+            ];
+            frames.insert(sprite.id.clone(), (sprite.atlas.clone(), source));
+        }
+
+        SpriteSheet { atlases, frames }
+    }
+
+    /// Columns and rows of frames on a loaded atlas.
+    pub fn atlas_grid(&self, atlas: &str) -> Option<(u32, u32)> {
+        self.atlases.get(atlas).map(|atlas| (atlas.columns, atlas.rows))
+    }
+
+    pub fn has(&self, id: &str) -> bool {
+        self.frames.contains_key(id)
+    }
+
+    /// Draws the sprite stretched over `rect` (x, y, width, height).
+    /// Returns false if there is no such sprite, so the caller can draw something else.
+    pub fn draw(&self, id: &str, rect: [f64; 4], c: &Context, g: &mut GlGraphics) -> bool {
+        let Some((atlas, source)) = self.frames.get(id) else {
+            return false;
+        };
+        let atlas = &self.atlases[atlas];
+
+        Image::new()
+            .src_rect(*source)
+            .rect(rect)
+            .draw(&atlas.texture, &c.draw_state, c.transform, g);
+        true
+    }
+}
@@ -0,0 +1,60 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::clock::Clock;
+use crate::combat::{Battle, EnemyDb, Outcome, SkillDb};
+use crate::encounters::EncounterDb;
+use crate::items::{Inventory, ItemDb, ItemStack};
+use crate::quests::{QuestDb, QuestLog};
+use crate::script::Flags;
+use crate::stats::{Character, Growth};
+
+/// Progress that outlives a single map and is shared between screens.
+pub struct GameState {
+    pub items: ItemDb,
+    pub enemies: EnemyDb,
+    pub skills: SkillDb,
+    pub encounter_tables: EncounterDb,
+    pub growth: Growth,
+    pub quests: QuestDb,
+    pub player: Character,
+    pub inventory: Inventory,
+    pub flags: Flags,
+    pub quest_log: QuestLog,
+    pub clock: Clock,
+    /// Items dropped from the inventory screen, waiting to be placed on the map
+    pub dropped: Vec<ItemStack>,
+    /// The fight shown on the battle screen, if one is in progress
+    pub battle: Option<Battle>,
+    /// How the last battle ended, waiting for the game screen to react to it
+    pub battle_outcome: Option<Outcome>,
+}
+
+pub type SharedState = Rc<RefCell<GameState>>;
+
+impl GameState {
+    pub fn new() -> Self {
+        let growth = Growth::builtin();
+        let enemies = EnemyDb::builtin();
+        let items = ItemDb::builtin();
+        GameState {
+            quests: QuestDb::builtin(&items, &enemies),
+            items,
+            encounter_tables: EncounterDb::builtin(&enemies),
+            enemies,
+            skills: SkillDb::builtin(),
+            player: Character::new_player("HERO", &growth),
+            growth,
+            inventory: Inventory::default(),
+            flags: Flags::default(),
+            quest_log: QuestLog::default(),
+            clock: Clock::new(),
+            dropped: Vec::new(),
+            battle: None,
+            battle_outcome: None,
+        }
+    }
+
+    pub fn shared() -> SharedState {
+        Rc::new(RefCell::new(GameState::new()))
+    }
+}
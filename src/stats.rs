@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Stats {
+    pub max_hp: i32,
+    pub max_mp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub speed: i32,
+}
+
+impl Stats {
+    fn add(self, other: Stats) -> Stats {
+        Stats {
+            max_hp: self.max_hp + other.max_hp,
+            max_mp: self.max_mp + other.max_mp,
+            attack: self.attack + other.attack,
+            defense: self.defense + other.defense,
+            speed: self.speed + other.speed,
+        }
+    }
+}
+
+/// How the player's stats and experience requirements grow, loaded from
+/// `assets/data/growth.json`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Growth {
+    pub base: Stats,
+    pub per_level: Stats,
+    pub xp_base: f64,
+    pub xp_exponent: f64,
+    pub max_level: u32,
+}
+
+impl Growth {
+    pub fn builtin() -> Self {
+        Growth::from_json(include_str!("../assets/data/growth.json"))
+            .unwrap_or_else(|e| panic!("Invalid built-in growth curve: {}", e))
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, String> {
+        let growth: Growth = serde_json::from_str(source).map_err(|e| e.to_string())?;
+        if growth.max_level == 0 {
+            return Err("max_level must be at least 1".to_string());
+        }
+        if growth.xp_base <= 0.0 {
+            return Err("xp_base must be positive".to_string());
+        }
+        Ok(growth)
+    }
+
+    /// Experience needed to go from `level` to the next one.
+    pub fn xp_to_next(&self, level: u32) -> u32 {
+        (self.xp_base * (level as f64).powf(self.xp_exponent)).round() as u32
+    }
+}
+
+/// Something that can fight: the player, or later enemies and party members.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Character {
+    pub name: String,
+    pub level: u32,
+    /// Experience gathered towards the next level
+    pub xp: u32,
+    pub hp: i32,
+    pub mp: i32,
+    pub stats: Stats,
+}
+
+impl Character {
+    pub fn new_player(name: &str, growth: &Growth) -> Self {
+        Character {
+            name: name.to_string(),
+            level: 1,
+            xp: 0,
+            hp: growth.base.max_hp,
+            mp: growth.base.max_mp,
+            stats: growth.base,
+        }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.hp > 0
+    }
+
+    pub fn take_damage(&mut self, amount: i32) {
+        self.hp = (self.hp - amount.max(0)).max(0);
+    }
+
+    /// Restores up to `amount` HP, returning how much was actually healed.
+    pub fn heal(&mut self, amount: i32) -> i32 {
+        let healed = amount.min(self.stats.max_hp - self.hp).max(0);
+        self.hp += healed;
+        healed
+    }
+
+    /// Restores up to `amount` MP, returning how much was actually restored.
+    pub fn restore_mp(&mut self, amount: i32) -> i32 {
+        let restored = amount.min(self.stats.max_mp - self.mp).max(0);
+        self.mp += restored;
+        restored
+    }
+
+    /// Adds experience and applies any level ups. Returns how many levels were gained.
+    pub fn gain_xp(&mut self, amount: u32, growth: &Growth) -> u32 {
+        let mut gained = 0;
+        self.xp = self.xp.saturating_add(amount);
+
+        while self.level < growth.max_level && self.xp >= growth.xp_to_next(self.level) {
+            self.xp -= growth.xp_to_next(self.level);
+            self.level += 1;
+            self.stats = self.stats.add(growth.per_level);
+            // Levelling up also tops the character off
+            self.hp = self.stats.max_hp;
+            self.mp = self.stats.max_mp;
+            gained += 1;
+        }
+
+        if self.level == growth.max_level {
+            self.xp = 0;
+        }
+
+        gained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn growth(xp_base: f64, max_level: u32) -> Growth {
+        Growth {
+            base: Stats { max_hp: 30, max_mp: 10, attack: 6, defense: 4, speed: 5 },
+            per_level: Stats { max_hp: 5, max_mp: 2, attack: 1, defense: 1, speed: 0 },
+            xp_base,
+            xp_exponent: 2.0,
+            max_level,
+        }
+    }
+
+    #[test]
+    fn each_level_needs_more_experience() {
+        let growth = growth(10.0, 10);
+        assert_eq!(growth.xp_to_next(1), 10);
+        assert_eq!(growth.xp_to_next(2), 40);
+        assert_eq!(growth.xp_to_next(3), 90);
+    }
+
+    #[test]
+    fn experience_can_bring_several_levels_at_once() {
+        let growth = growth(10.0, 10);
+        let mut hero = Character::new_player("HERO", &growth);
+        hero.hp = 1;
+
+        assert_eq!(hero.gain_xp(9, &growth), 0);
+        assert_eq!((hero.level, hero.xp, hero.hp), (1, 9, 1));
+
+        // 10 to reach level 2 and 40 more for level 3, with 5 to spare
+        assert_eq!(hero.gain_xp(46, &growth), 2);
+        assert_eq!((hero.level, hero.xp), (3, 5));
+        assert_eq!(hero.stats, Stats { max_hp: 40, max_mp: 14, attack: 8, defense: 6, speed: 5 });
+        assert_eq!((hero.hp, hero.mp), (40, 14));
+    }
+
+    #[test]
+    fn levels_stop_at_the_cap() {
+        let growth = growth(10.0, 3);
+        let mut hero = Character::new_player("HERO", &growth);
+        assert_eq!(hero.gain_xp(1000, &growth), 2);
+        assert_eq!((hero.level, hero.xp), (3, 0));
+        assert_eq!(hero.gain_xp(1000, &growth), 0);
+        assert_eq!((hero.level, hero.xp), (3, 0));
+    }
+
+    #[test]
+    fn huge_grants_do_not_overflow() {
+        // The first level takes more experience than fits in a u32
+        let growth = growth(1e12, 2);
+        let mut hero = Character::new_player("HERO", &growth);
+        assert_eq!(hero.gain_xp(u32::MAX - 1, &growth), 0);
+        assert_eq!(hero.gain_xp(u32::MAX, &growth), 1);
+        assert_eq!(hero.level, 2);
+    }
+
+    #[test]
+    fn builtin_growth_loads() {
+        let growth = Growth::builtin();
+        assert!(growth.xp_to_next(2) > growth.xp_to_next(1));
+    }
+}
@@ -0,0 +1,1539 @@
+//! Maps made in the Tiled editor (https://www.mapeditor.org), read from JSON
+//! (`.tmj`) or XML (`.tmx`) exports.
+//!
+//! Tile layers are named after the map layer they fill: `ground`,
+//! `decoration`, `overhead` or `collision`. Every tileset tile drawn on the
+//! first three needs a `tile` property naming a tile type such as `wall`; any
+//! tile at all on `collision` blocks movement. Without a collision layer,
+//! collision follows from the tiles. Tilesets become sprite atlases, with the
+//! first tile of each tile type as its sprite.
+//!
+//! Objects are told apart by their class:
+//!
+//! | class            | properties                                                         |
+//! |------------------|--------------------------------------------------------------------|
+//! | `spawn`          | none; where the player arrives, exactly one per map                |
+//! | `portal`         | `destination_map`, `destination_x`, `destination_y`, `on_interact` |
+//! |                  | or, for a generated map, `generator` and `seed` (random if unset)  |
+//! | `npc`            | `name` (defaults to the object name), `dialogue`, `on_interact`,   |
+//! |                  | `schedule` (comma separated `hh:mm x y` stops, e.g. `7:00 10 7`)   |
+//! | `pickup`         | `item`, `count`                                                    |
+//! | `prop`           | `kind`, `solid` (a bool, true if left out)                         |
+//! | `foe`            | `enemies` (comma separated), `behaviour`, `radius`, `interval`     |
+//! | `door`           | `open` (a bool, false if left out); `key` (an item) or `flag` lock |
+//! |                  | a closed door, which either of them then opens                     |
+//! | `crate`          | `kind` (`crate` if left out); pushed around by the player          |
+//! | `plate`, `lever` | `link`; a link's gates and bridges work while all of its plates    |
+//! |                  | are pressed and all of its levers pulled                           |
+//! | `gate`, `bridge` | `link`; a door opened, or a way across raised, by the link         |
+//! | `trigger`        | one of `on_step` (a script), `warp_map` and/or `warp_x`, `warp_y`, |
+//! |                  | `damage` (HP) or `slide` (a direction, or `ice`); plus `once` (a   |
+//! |                  | bool) and `requires` (a flag)                                      |
+//! | `region`         | `on_enter`; a rectangle                                            |
+//! | `encounter_zone` | `table`; a rectangle                                               |
+//!
+//! The map itself may have an `on_enter` property, and `origin_x` and
+//! `origin_y` giving the grid position of its bottom-left tile (0, 0 if left
+//! out). Unknown classes and properties are errors, so typos show up on import
+//! rather than in play.
+//!
+//! [`save`] writes maps back out in the JSON format, so maps touched up in the
+//! in-game editor open in Tiled again.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use serde_json::json;
+use crate::ai::{Behaviour, Stop};
+use crate::encounters::EncounterZone;
+use crate::entity::Lock;
+use crate::map::{self, Layer, TileMap, EMPTY, WALL};
+use crate::mapgen::{GeneratorKind, MapGen};
+use crate::script::{MapScripts, Region};
+use crate::triggers::{Slide, Trigger, TriggerAction};
+use crate::sprites::{AtlasDef, SpriteData, SpriteDef};
+
+/// Bits of a tile id that say how the tile is flipped or rotated.
+const FLIP_FLAGS: u32 = 0xF000_0000;
+
+// Lowest and highest corner of a rectangle of tiles
+type Area = ((i32, i32), (i32, i32));
+
+const DEFAULT_FOE_RADIUS: i64 = 4;
+const DEFAULT_FOE_INTERVAL: f64 = 0.8;
+
+/// Something an object layer puts on the map.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MapObject {
+    Portal {
+        destination_map: Option<String>,
+        destination_position: Option<(i32, i32)>,
+        generator: Option<MapGen>,
+        on_interact: Option<String>,
+    },
+    Npc {
+        name: String,
+        dialogue: String,
+        on_interact: Option<String>,
+        /// Where the NPC goes through the day, sorted by time; empty if it stays put
+        schedule: Vec<Stop>,
+    },
+    Pickup { item: String, count: u32 },
+    Prop { kind: String, solid: bool },
+    Foe { enemies: Vec<String>, behaviour: Behaviour, interval: f64 },
+    Door { open: bool, lock: Option<Lock> },
+    Crate { kind: String },
+    Plate { link: String },
+    Lever { link: String },
+    Gate { link: String },
+    Bridge { link: String },
+}
+
+/// A map read from or written to a Tiled export.
+pub struct TiledMap {
+    pub tiles: TileMap,
+    pub spawn: (i32, i32),
+    pub objects: Vec<((i32, i32), MapObject)>,
+    pub scripts: MapScripts,
+    pub encounter_zones: Vec<EncounterZone>,
+    /// Tileset atlases, with image paths already joined to the map's directory
+    pub sprites: SpriteData,
+}
+
+/// Reads a `.tmj` or `.tmx` file, and any external tilesets it uses.
+pub fn load(path: &Path) -> Result<TiledMap, String> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let raw = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tmj" | "json") => parse_json(&source, dir),
+        Some("tmx" | "xml") => parse_tmx(&source, dir),
+        _ => Err("expected a .tmj or .tmx file".to_string()),
+    };
+    raw.and_then(build).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// The tileset written into saved maps, cut from one of the game's atlases.
+pub struct TilesetDef {
+    pub name: String,
+    /// Image path relative to the map file
+    pub image: String,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// The frame showing each tile type
+    pub frames: Vec<(u8, u32)>,
+}
+
+/// Writes a map as a Tiled JSON export that [`load`] reads back.
+pub fn save(path: &Path, map: &TiledMap, tileset: &TilesetDef) -> Result<(), String> {
+    let json = to_json(map, tileset)?;
+    fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn to_json(map: &TiledMap, tileset: &TilesetDef) -> Result<String, String> {
+    let tiles = &map.tiles;
+    let gid = |tile: u8| -> Result<u32, String> {
+        if tile == EMPTY {
+            return Ok(0);
+        }
+        tileset
+            .frames
+            .iter()
+            .find(|(frame_tile, _)| *frame_tile == tile)
+            .map(|(_, frame)| frame + 1)
+            .ok_or_else(|| format!("tileset '{}' has nothing to show tile {}", tileset.name, tile))
+    };
+    // Anything on the collision layer blocks, so mark it with walls to make it stand out in Tiled
+    let solid_gid = gid(WALL)?;
+
+    // Layer data goes in afterwards, one row per line the way Tiled writes it
+    let mut layer_data = Vec::new();
+    let mut layers = Vec::new();
+    let tile_layers = [
+        ("ground", Some(Layer::Ground)),
+        ("decoration", Some(Layer::Decoration)),
+        ("overhead", Some(Layer::Overhead)),
+        ("collision", None),
+    ];
+    for (name, layer) in tile_layers {
+        let mut data = Vec::new();
+        for (x, y) in tiles.positions() {
+            data.push(match layer {
+                Some(layer) => gid(tiles.tile(layer, x, y))?,
+                None if tiles.is_solid(x, y) => solid_gid,
+                None => 0,
+            });
+        }
+        layers.push(json!({
+            "id": layers.len() + 1,
+            "name": name,
+            "type": "tilelayer",
+            "x": 0,
+            "y": 0,
+            "width": tiles.width(),
+            "height": tiles.height(),
+            "opacity": 1,
+            "visible": layer.is_some(),
+            "data": format!("@layer_data_{}@", layer_data.len()),
+        }));
+        layer_data.push(data);
+    }
+
+    let (tile_width, tile_height) = (tileset.tile_width as f64, tileset.tile_height as f64);
+    let pixel_x = |x: i32| (x - tiles.left()) as f64 * tile_width;
+    let pixel_y = |y: i32| (tiles.top() - y) as f64 * tile_height;
+    let mut objects = Vec::new();
+    let mut point = |class: &str, name: &str, (x, y): (i32, i32), properties: Vec<serde_json::Value>| {
+        let mut object = json!({
+            "id": objects.len() + 1,
+            "name": name,
+            "type": class,
+            "x": pixel_x(x) + tile_width / 2.0,
+            "y": pixel_y(y) + tile_height / 2.0,
+            "width": 0,
+            "height": 0,
+            "rotation": 0,
+            "point": true,
+            "visible": true,
+        });
+        if !properties.is_empty() {
+            object["properties"] = properties.into();
+        }
+        objects.push(object);
+    };
+
+    point("spawn", "", map.spawn, Vec::new());
+    for (position, object) in &map.objects {
+        match object {
+            MapObject::Portal { destination_map, destination_position, generator, on_interact } => {
+                let mut properties = Vec::new();
+                if let Some(name) = destination_map {
+                    properties.push(property("destination_map", "string", json!(name)));
+                }
+                if let Some(generator) = generator {
+                    properties.push(property("generator", "string", json!(generator.kind.name())));
+                    if let Some(seed) = generator.seed {
+                        // Tiled ints are signed; the bits come back the same on load
+                        properties.push(property("seed", "int", json!(seed as i64)));
+                    }
+                }
+                if let Some((x, y)) = destination_position {
+                    properties.push(property("destination_x", "int", json!(x)));
+                    properties.push(property("destination_y", "int", json!(y)));
+                }
+                if let Some(script) = on_interact {
+                    properties.push(property("on_interact", "string", json!(script)));
+                }
+                point("portal", "", *position, properties);
+            }
+            MapObject::Npc { name, dialogue, on_interact, schedule } => {
+                let mut properties = vec![property("dialogue", "string", json!(dialogue))];
+                if let Some(script) = on_interact {
+                    properties.push(property("on_interact", "string", json!(script)));
+                }
+                if !schedule.is_empty() {
+                    let stops: Vec<String> = schedule
+                        .iter()
+                        .map(|stop| {
+                            let (x, y) = stop.position;
+                            format!("{}:{:02} {} {}", stop.from / 60, stop.from % 60, x, y)
+                        })
+                        .collect();
+                    properties.push(property("schedule", "string", json!(stops.join(", "))));
+                }
+                point("npc", name, *position, properties);
+            }
+            MapObject::Pickup { item, count } => {
+                let properties = vec![property("item", "string", json!(item)), property("count", "int", json!(count))];
+                point("pickup", "", *position, properties);
+            }
+            MapObject::Prop { kind, solid } => {
+                let properties = vec![property("kind", "string", json!(kind)), property("solid", "bool", json!(solid))];
+                point("prop", "", *position, properties);
+            }
+            MapObject::Foe { enemies, behaviour, interval } => {
+                let mut properties = vec![property("enemies", "string", json!(enemies.join(",")))];
+                match behaviour {
+                    Behaviour::Wander => properties.push(property("behaviour", "string", json!("wander"))),
+                    Behaviour::Chase { radius } => {
+                        properties.push(property("behaviour", "string", json!("chase")));
+                        properties.push(property("radius", "int", json!(radius)));
+                    }
+                    Behaviour::Flee { radius } => {
+                        properties.push(property("behaviour", "string", json!("flee")));
+                        properties.push(property("radius", "int", json!(radius)));
+                    }
+                    Behaviour::Patrol { .. } => return Err("patrolling foes can't be saved to Tiled".to_string()),
+                    Behaviour::Schedule { .. } => return Err("foes on a schedule can't be saved to Tiled".to_string()),
+                }
+                properties.push(property("interval", "float", json!(interval)));
+                point("foe", "", *position, properties);
+            }
+            MapObject::Door { open, lock } => {
+                let mut properties = vec![property("open", "bool", json!(open))];
+                if let Some(Lock { key, flag }) = lock {
+                    if let Some(key) = key {
+                        properties.push(property("key", "string", json!(key)));
+                    }
+                    if let Some(flag) = flag {
+                        properties.push(property("flag", "string", json!(flag)));
+                    }
+                }
+                point("door", "", *position, properties);
+            }
+            MapObject::Crate { kind } => point("crate", "", *position, vec![property("kind", "string", json!(kind))]),
+            MapObject::Plate { link } => point("plate", "", *position, vec![property("link", "string", json!(link))]),
+            MapObject::Lever { link } => point("lever", "", *position, vec![property("link", "string", json!(link))]),
+            MapObject::Gate { link } => point("gate", "", *position, vec![property("link", "string", json!(link))]),
+            MapObject::Bridge { link } => point("bridge", "", *position, vec![property("link", "string", json!(link))]),
+        }
+    }
+
+    // Triggers in a stable order, so saving twice gives the same file
+    let mut triggers: Vec<_> = map.scripts.tiles.iter().collect();
+    triggers.sort_by_key(|(position, _)| **position);
+    for (position, trigger) in triggers {
+        let mut properties = Vec::new();
+        match &trigger.action {
+            TriggerAction::Script(script) => properties.push(property("on_step", "string", json!(script))),
+            TriggerAction::Warp { map, position } => {
+                if let Some(name) = map {
+                    properties.push(property("warp_map", "string", json!(name)));
+                }
+                if let Some((x, y)) = position {
+                    properties.push(property("warp_x", "int", json!(x)));
+                    properties.push(property("warp_y", "int", json!(y)));
+                }
+            }
+            TriggerAction::Damage(amount) => properties.push(property("damage", "int", json!(amount))),
+            TriggerAction::Slide(slide) => properties.push(property("slide", "string", json!(slide.name()))),
+        }
+        if trigger.once {
+            properties.push(property("once", "bool", json!(true)));
+        }
+        if let Some(flag) = &trigger.requires {
+            properties.push(property("requires", "string", json!(flag)));
+        }
+        point("trigger", "", *position, properties);
+    }
+
+    let areas = map
+        .scripts
+        .regions
+        .iter()
+        .map(|region| ("region", region.min, region.max, property("on_enter", "string", json!(region.on_enter))))
+        .chain(
+            map.encounter_zones
+                .iter()
+                .map(|zone| ("encounter_zone", zone.min, zone.max, property("table", "string", json!(zone.table)))),
+        );
+    for (class, min, max, property) in areas {
+        objects.push(json!({
+            "id": objects.len() + 1,
+            "name": "",
+            "type": class,
+            "x": pixel_x(min.0),
+            "y": pixel_y(max.1),
+            "width": (max.0 - min.0 + 1) as f64 * tile_width,
+            "height": (max.1 - min.1 + 1) as f64 * tile_height,
+            "rotation": 0,
+            "visible": true,
+            "properties": [property],
+        }));
+    }
+
+    let mut frames = tileset.frames.clone();
+    frames.sort_by_key(|(_, frame)| *frame);
+    let tileset_tiles: Vec<_> = frames
+        .iter()
+        .filter_map(|(tile, frame)| {
+            let name = map::tile_name(*tile)?;
+            Some(json!({ "id": frame, "properties": [property("tile", "string", json!(name))] }))
+        })
+        .collect();
+
+    let mut properties = vec![
+        property("origin_x", "int", json!(tiles.left())),
+        property("origin_y", "int", json!(tiles.bottom())),
+    ];
+    if let Some(script) = &map.scripts.on_enter {
+        properties.insert(0, property("on_enter", "string", json!(script)));
+    }
+
+    layers.push(json!({
+        "id": layers.len() + 1,
+        "name": "objects",
+        "type": "objectgroup",
+        "draworder": "topdown",
+        "x": 0,
+        "y": 0,
+        "opacity": 1,
+        "visible": true,
+        "objects": objects,
+    }));
+    let document = json!({
+        "type": "map",
+        "version": "1.10",
+        "orientation": "orthogonal",
+        "renderorder": "right-down",
+        "infinite": false,
+        "width": tiles.width(),
+        "height": tiles.height(),
+        "tilewidth": tileset.tile_width,
+        "tileheight": tileset.tile_height,
+        "nextlayerid": layers.len() + 1,
+        "nextobjectid": objects.len() + 1,
+        "properties": properties,
+        "tilesets": [{
+            "firstgid": 1,
+            "name": tileset.name,
+            "image": tileset.image,
+            "imagewidth": tileset.columns * tileset.tile_width,
+            "imageheight": tileset.rows * tileset.tile_height,
+            "tilewidth": tileset.tile_width,
+            "tileheight": tileset.tile_height,
+            "columns": tileset.columns,
+            "tilecount": tileset.columns * tileset.rows,
+            "margin": 0,
+            "spacing": 0,
+            "tiles": tileset_tiles,
+        }],
+        "layers": layers,
+    });
+
+    let mut json = serde_json::to_string_pretty(&document).map_err(|e| e.to_string())?;
+    for (index, data) in layer_data.iter().enumerate() {
+        let rows: Vec<String> = data
+            .chunks(tiles.width())
+            .map(|row| row.iter().map(|gid| gid.to_string()).collect::<Vec<_>>().join(","))
+            .collect();
+        let placeholder = format!("\"@layer_data_{}@\"", index);
+        json = json.replace(&placeholder, &format!("[\n        {}\n      ]", rows.join(",\n        ")));
+    }
+    json.push('\n');
+    Ok(json)
+}
+
+fn property(name: &str, kind: &str, value: serde_json::Value) -> serde_json::Value {
+    json!({ "name": name, "type": kind, "value": value })
+}
+
+// What both export formats have in common, before it is checked and turned into a map
+
+#[derive(Clone, Debug)]
+enum Value {
+    String(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+#[derive(Clone, Debug)]
+struct Property {
+    name: String,
+    value: Value,
+}
+
+struct RawTileset {
+    first_gid: u32,
+    name: String,
+    image: Option<PathBuf>,
+    tile_width: u32,
+    tile_height: u32,
+    tiles: Vec<(u32, Vec<Property>)>,
+}
+
+enum RawLayer {
+    Tiles { name: String, data: Vec<u32>, properties: Vec<Property> },
+    Objects { name: String, objects: Vec<RawObject>, properties: Vec<Property> },
+}
+
+struct RawObject {
+    id: u32,
+    class: String,
+    name: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    /// Set on tile objects, which hang up from their bottom-left corner
+    gid: Option<u32>,
+    properties: Vec<Property>,
+}
+
+struct RawMap {
+    width: usize,
+    height: usize,
+    tile_width: u32,
+    tile_height: u32,
+    properties: Vec<Property>,
+    layers: Vec<RawLayer>,
+    tilesets: Vec<RawTileset>,
+}
+
+fn check_orthogonal(orientation: &str, infinite: bool) -> Result<(), String> {
+    if !orientation.is_empty() && orientation != "orthogonal" {
+        return Err(format!("{} maps are not supported, only orthogonal ones", orientation));
+    }
+    if infinite {
+        return Err("infinite maps are not supported".to_string());
+    }
+    Ok(())
+}
+
+fn property_value(name: &str, kind: &str, text: &str) -> Result<Value, String> {
+    let invalid = |e: String| format!("property '{}': invalid {} value '{}': {}", name, kind, text, e);
+    match kind {
+        "" | "string" | "color" | "file" => Ok(Value::String(text.to_string())),
+        "int" | "object" => text.parse().map(Value::Int).map_err(|e| invalid(format!("{}", e))),
+        "float" => text.parse().map(Value::Float).map_err(|e| invalid(format!("{}", e))),
+        "bool" => text.parse().map(Value::Bool).map_err(|e| invalid(format!("{}", e))),
+        _ => Err(format!("property '{}' has unsupported type '{}'", name, kind)),
+    }
+}
+
+/// Reads an external tileset, in whichever format its extension says.
+fn load_tileset(path: &Path, first_gid: u32) -> Result<RawTileset, String> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let tileset = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("tsj" | "json") => serde_json::from_str::<JsonTileset>(&source)
+            .map_err(|e| e.to_string())
+            .and_then(|tileset| json_tileset(tileset, first_gid, dir)),
+        Some("tsx" | "xml") => roxmltree::Document::parse(&source)
+            .map_err(|e| e.to_string())
+            .and_then(|document| tmx_tileset(document.root_element(), first_gid, dir)),
+        _ => Err("expected a .tsj or .tsx tileset".to_string()),
+    };
+    tileset.map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// JSON exports
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: usize,
+    height: usize,
+    tilewidth: u32,
+    tileheight: u32,
+    #[serde(default)]
+    orientation: String,
+    #[serde(default)]
+    infinite: bool,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    value: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    data: serde_json::Value,
+    #[serde(default)]
+    objects: Vec<JsonObject>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonObject {
+    id: u32,
+    #[serde(default)]
+    name: String,
+    #[serde(default, rename = "type")]
+    kind: String,
+    #[serde(default)]
+    class: String,
+    x: f64,
+    y: f64,
+    #[serde(default)]
+    width: f64,
+    #[serde(default)]
+    height: f64,
+    #[serde(default)]
+    gid: Option<u32>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(default)]
+    firstgid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    image: Option<String>,
+    #[serde(default)]
+    tilewidth: u32,
+    #[serde(default)]
+    tileheight: u32,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+fn parse_json(source: &str, dir: &Path) -> Result<RawMap, String> {
+    let map: JsonMap = serde_json::from_str(source).map_err(|e| e.to_string())?;
+    check_orthogonal(&map.orientation, map.infinite)?;
+
+    let mut layers = Vec::new();
+    json_layers(map.layers, &mut layers)?;
+
+    let mut tilesets = Vec::new();
+    for tileset in map.tilesets {
+        tilesets.push(match &tileset.source {
+            Some(source) => load_tileset(&dir.join(source), tileset.firstgid)?,
+            None => json_tileset(tileset, 0, dir)?,
+        });
+    }
+
+    Ok(RawMap {
+        width: map.width,
+        height: map.height,
+        tile_width: map.tilewidth,
+        tile_height: map.tileheight,
+        properties: json_properties(map.properties)?,
+        layers,
+        tilesets,
+    })
+}
+
+fn json_properties(properties: Vec<JsonProperty>) -> Result<Vec<Property>, String> {
+    properties
+        .into_iter()
+        .map(|property| {
+            let text = match &property.value {
+                serde_json::Value::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            let value = property_value(&property.name, &property.kind, &text)?;
+            Ok(Property { name: property.name, value })
+        })
+        .collect()
+}
+
+/// Flattens group layers into `out`, in drawing order.
+fn json_layers(layers: Vec<JsonLayer>, out: &mut Vec<RawLayer>) -> Result<(), String> {
+    for layer in layers {
+        match layer.kind.as_str() {
+            "tilelayer" => {
+                if layer.encoding.as_deref().is_some_and(|encoding| encoding != "csv") {
+                    return Err(format!("layer '{}' is compressed; export tile layers as CSV", layer.name));
+                }
+                let data = layer
+                    .data
+                    .as_array()
+                    .ok_or_else(|| format!("layer '{}' has no tile data", layer.name))?
+                    .iter()
+                    .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                    .collect::<Option<Vec<u32>>>()
+                    .ok_or_else(|| format!("layer '{}' has an invalid tile id", layer.name))?;
+                out.push(RawLayer::Tiles {
+                    name: layer.name,
+                    data,
+                    properties: json_properties(layer.properties)?,
+                });
+            }
+            "objectgroup" => {
+                let mut objects = Vec::new();
+                for object in layer.objects {
+                    objects.push(RawObject {
+                        id: object.id,
+                        class: if object.class.is_empty() { object.kind } else { object.class },
+                        name: object.name,
+                        x: object.x,
+                        y: object.y,
+                        width: object.width,
+                        height: object.height,
+                        gid: object.gid,
+                        properties: json_properties(object.properties)?,
+                    });
+                }
+                out.push(RawLayer::Objects {
+                    name: layer.name,
+                    objects,
+                    properties: json_properties(layer.properties)?,
+                });
+            }
+            "group" => json_layers(layer.layers, out)?,
+            other => return Err(format!("layer '{}': {} layers are not supported", layer.name, other)),
+        }
+    }
+    Ok(())
+}
+
+/// Converts an embedded tileset, or an external one given its `first_gid`.
+fn json_tileset(tileset: JsonTileset, first_gid: u32, dir: &Path) -> Result<RawTileset, String> {
+    let mut tiles = Vec::new();
+    for tile in tileset.tiles {
+        tiles.push((tile.id, json_properties(tile.properties)?));
+    }
+
+    Ok(RawTileset {
+        first_gid: first_gid.max(tileset.firstgid),
+        name: tileset.name,
+        image: tileset.image.map(|image| dir.join(image)),
+        tile_width: tileset.tilewidth,
+        tile_height: tileset.tileheight,
+        tiles,
+    })
+}
+
+// XML exports
+
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<Option<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    node.attribute(name)
+        .map(|text| text.parse().map_err(|e| format!("<{}> attribute '{}': {}", node.tag_name().name(), name, e)))
+        .transpose()
+}
+
+fn required<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, String>
+where
+    T::Err: std::fmt::Display,
+{
+    attribute(node, name)?.ok_or_else(|| format!("<{}> is missing '{}'", node.tag_name().name(), name))
+}
+
+fn children<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    tag: &'static str,
+) -> impl Iterator<Item = roxmltree::Node<'a, 'input>> {
+    node.children().filter(move |child| child.has_tag_name(tag))
+}
+
+fn parse_tmx(source: &str, dir: &Path) -> Result<RawMap, String> {
+    let document = roxmltree::Document::parse(source).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("map") {
+        return Err("expected a <map> element".to_string());
+    }
+    check_orthogonal(root.attribute("orientation").unwrap_or(""), root.attribute("infinite") == Some("1"))?;
+
+    let mut tilesets = Vec::new();
+    for tileset in children(root, "tileset") {
+        let first_gid = required(tileset, "firstgid")?;
+        tilesets.push(match tileset.attribute("source") {
+            Some(source) => load_tileset(&dir.join(source), first_gid)?,
+            None => tmx_tileset(tileset, first_gid, dir)?,
+        });
+    }
+
+    let mut layers = Vec::new();
+    tmx_layers(root, &mut layers)?;
+
+    Ok(RawMap {
+        width: required(root, "width")?,
+        height: required(root, "height")?,
+        tile_width: required(root, "tilewidth")?,
+        tile_height: required(root, "tileheight")?,
+        properties: tmx_properties(root)?,
+        layers,
+        tilesets,
+    })
+}
+
+fn tmx_properties(node: roxmltree::Node) -> Result<Vec<Property>, String> {
+    let mut properties = Vec::new();
+    for list in children(node, "properties") {
+        for property in children(list, "property") {
+            let name: String = required(property, "name")?;
+            // Multi-line strings are stored as the element's text
+            let text = property.attribute("value").or(property.text()).unwrap_or("");
+            let value = property_value(&name, property.attribute("type").unwrap_or(""), text)?;
+            properties.push(Property { name, value });
+        }
+    }
+    Ok(properties)
+}
+
+/// Flattens group layers into `out`, in drawing order.
+fn tmx_layers(node: roxmltree::Node, out: &mut Vec<RawLayer>) -> Result<(), String> {
+    for layer in node.children().filter(|child| child.is_element()) {
+        let name = layer.attribute("name").unwrap_or("").to_string();
+        match layer.tag_name().name() {
+            "layer" => {
+                let data = children(layer, "data")
+                    .next()
+                    .ok_or_else(|| format!("layer '{}' has no tile data", name))?;
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => data
+                        .text()
+                        .unwrap_or("")
+                        .split(',')
+                        .map(|gid| gid.trim().parse::<u32>())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|e| format!("layer '{}': {}", name, e))?,
+                    None => children(data, "tile")
+                        .map(|tile| attribute(tile, "gid").map(|gid| gid.unwrap_or(0)))
+                        .collect::<Result<Vec<u32>, _>>()?,
+                    Some(_) => return Err(format!("layer '{}' is compressed; export tile layers as CSV", name)),
+                };
+                out.push(RawLayer::Tiles {
+                    name,
+                    data: gids,
+                    properties: tmx_properties(layer)?,
+                });
+            }
+            "objectgroup" => {
+                let mut objects = Vec::new();
+                for object in children(layer, "object") {
+                    objects.push(RawObject {
+                        id: required(object, "id")?,
+                        class: object.attribute("class").or(object.attribute("type")).unwrap_or("").to_string(),
+                        name: object.attribute("name").unwrap_or("").to_string(),
+                        x: required(object, "x")?,
+                        y: required(object, "y")?,
+                        width: attribute(object, "width")?.unwrap_or(0.0),
+                        height: attribute(object, "height")?.unwrap_or(0.0),
+                        gid: attribute(object, "gid")?,
+                        properties: tmx_properties(object)?,
+                    });
+                }
+                out.push(RawLayer::Objects {
+                    name,
+                    objects,
+                    properties: tmx_properties(layer)?,
+                });
+            }
+            "group" => tmx_layers(layer, out)?,
+            "properties" | "tileset" | "editorsettings" => {}
+            other => return Err(format!("layer '{}': <{}> layers are not supported", name, other)),
+        }
+    }
+    Ok(())
+}
+
+fn tmx_tileset(tileset: roxmltree::Node, first_gid: u32, dir: &Path) -> Result<RawTileset, String> {
+    let image = children(tileset, "image")
+        .next()
+        .map(|image| required::<String>(image, "source"))
+        .transpose()?;
+
+    let mut tiles = Vec::new();
+    for tile in children(tileset, "tile") {
+        tiles.push((required(tile, "id")?, tmx_properties(tile)?));
+    }
+
+    Ok(RawTileset {
+        first_gid,
+        name: tileset.attribute("name").unwrap_or("").to_string(),
+        image: image.map(|image| dir.join(image)),
+        tile_width: attribute(tileset, "tilewidth")?.unwrap_or(0),
+        tile_height: attribute(tileset, "tileheight")?.unwrap_or(0),
+        tiles,
+    })
+}
+
+// Turning the raw export into a map
+
+/// Checks a property list against what its owner allows, and hands out values by name.
+struct Properties<'a> {
+    owner: String,
+    properties: &'a [Property],
+}
+
+impl<'a> Properties<'a> {
+    fn new(owner: String, properties: &'a [Property], allowed: &[&str]) -> Result<Self, String> {
+        if let Some(unknown) = properties.iter().find(|property| !allowed.contains(&property.name.as_str())) {
+            return Err(format!("{}: unknown property '{}'", owner, unknown.name));
+        }
+        Ok(Properties { owner, properties })
+    }
+
+    fn get(&self, name: &str) -> Option<&'a Value> {
+        self.properties.iter().find(|property| property.name == name).map(|property| &property.value)
+    }
+
+    fn string(&self, name: &str) -> Result<Option<String>, String> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.clone())),
+            Some(_) => Err(format!("{}: property '{}' should be a string", self.owner, name)),
+        }
+    }
+
+    fn required_string(&self, name: &str) -> Result<String, String> {
+        self.string(name)?
+            .filter(|text| !text.is_empty())
+            .ok_or_else(|| format!("{}: missing property '{}'", self.owner, name))
+    }
+
+    fn int(&self, name: &str) -> Result<Option<i64>, String> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(Value::Int(value)) => Ok(Some(*value)),
+            Some(_) => Err(format!("{}: property '{}' should be an int", self.owner, name)),
+        }
+    }
+
+    fn bool(&self, name: &str) -> Result<Option<bool>, String> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(Value::Bool(value)) => Ok(Some(*value)),
+            Some(_) => Err(format!("{}: property '{}' should be a bool", self.owner, name)),
+        }
+    }
+
+    fn float(&self, name: &str) -> Result<Option<f64>, String> {
+        match self.get(name) {
+            None => Ok(None),
+            Some(Value::Float(value)) => Ok(Some(*value)),
+            Some(Value::Int(value)) => Ok(Some(*value as f64)),
+            Some(_) => Err(format!("{}: property '{}' should be a float", self.owner, name)),
+        }
+    }
+}
+
+fn build(raw: RawMap) -> Result<TiledMap, String> {
+    if raw.width == 0 || raw.height == 0 || raw.tile_width == 0 || raw.tile_height == 0 {
+        return Err("the map has no size".to_string());
+    }
+    let properties = Properties::new("map".to_string(), &raw.properties, &["on_enter", "origin_x", "origin_y"])?;
+    let left = properties.int("origin_x")?.unwrap_or(0) as i32;
+    let top = properties.int("origin_y")?.unwrap_or(0) as i32 + raw.height as i32 - 1;
+    let mut tiles = TileMap::new(left, top, raw.width, raw.height);
+    let mut scripts = MapScripts {
+        on_enter: properties.string("on_enter")?,
+        ..MapScripts::default()
+    };
+
+    // Tile types and sprites from the tilesets
+    let mut tile_types = Vec::new();
+    let mut sprites = SpriteData { atlases: Vec::new(), sprites: Vec::new() };
+    for tileset in &raw.tilesets {
+        let atlas = format!("tiled_{}", tileset.name);
+        if let Some(image) = &tileset.image {
+            sprites.atlases.push(AtlasDef {
+                id: atlas.clone(),
+                image: image.to_string_lossy().into_owned(),
+                frame_width: if tileset.tile_width > 0 { tileset.tile_width } else { raw.tile_width },
+                frame_height: if tileset.tile_height > 0 { tileset.tile_height } else { raw.tile_height },
+            });
+        }
+
+        for (id, properties) in &tileset.tiles {
+            let owner = format!("tileset '{}' tile {}", tileset.name, id);
+            let properties = Properties::new(owner.clone(), properties, &["tile"])?;
+            let Some(name) = properties.string("tile")? else {
+                continue;
+            };
+            let tile = map::tile_from_name(&name).ok_or_else(|| format!("{}: unknown tile type '{}'", owner, name))?;
+            tile_types.push((tileset.first_gid + id, tile));
+
+            if tileset.image.is_some() && !sprites.sprites.iter().any(|sprite| sprite.id == name) {
+                sprites.sprites.push(SpriteDef { id: name, atlas: atlas.clone(), frame: *id });
+            }
+        }
+    }
+
+    let mut spawn = None;
+    let mut objects = Vec::new();
+    let mut encounter_zones = Vec::new();
+    let mut has_collision = false;
+
+    for layer in &raw.layers {
+        match layer {
+            RawLayer::Tiles { name, data, properties } => {
+                Properties::new(format!("layer '{}'", name), properties, &[])?;
+                if data.len() != raw.width * raw.height {
+                    return Err(format!("layer '{}' has {} tiles, expected {}", name, data.len(), raw.width * raw.height));
+                }
+                let target = match name.as_str() {
+                    "ground" => Some(Layer::Ground),
+                    "decoration" => Some(Layer::Decoration),
+                    "overhead" => Some(Layer::Overhead),
+                    "collision" => None,
+                    _ => {
+                        return Err(format!(
+                            "unknown tile layer '{}', expected ground, decoration, overhead or collision",
+                            name
+                        ))
+                    }
+                };
+
+                for (index, &gid) in data.iter().enumerate() {
+                    let gid = gid & !FLIP_FLAGS;
+                    let (x, y) = (left + (index % raw.width) as i32, top - (index / raw.width) as i32);
+                    let Some(target) = target else {
+                        has_collision = true;
+                        if gid != 0 {
+                            tiles.set_solid(x, y, true);
+                        }
+                        continue;
+                    };
+
+                    // Later layers of the same name paint over earlier ones,
+                    // except on the ground, which is filled in full
+                    let tile = if gid == 0 {
+                        EMPTY
+                    } else {
+                        tile_types
+                            .iter()
+                            .find(|(tile_gid, _)| *tile_gid == gid)
+                            .map(|(_, tile)| *tile)
+                            .ok_or_else(|| format!("layer '{}' uses tile {} which has no 'tile' property", name, gid))?
+                    };
+                    if tile != EMPTY || target == Layer::Ground {
+                        tiles.set_tile(target, x, y, tile);
+                    }
+                }
+            }
+            RawLayer::Objects { name, objects: layer_objects, properties } => {
+                Properties::new(format!("layer '{}'", name), properties, &[])?;
+                for object in layer_objects {
+                    let owner = format!("object {} ({})", object.id, object.class);
+                    match object.class.as_str() {
+                        "spawn" => {
+                            Properties::new(owner.clone(), &object.properties, &[])?;
+                            if spawn.is_some() {
+                                return Err(format!("{}: the map already has a spawn point", owner));
+                            }
+                            spawn = Some(object_tile(&raw, &tiles, object, &owner)?);
+                        }
+                        "trigger" => {
+                            let position = object_tile(&raw, &tiles, object, &owner)?;
+                            if scripts.tiles.contains_key(&position) {
+                                return Err(format!("{}: the tile already has a trigger", owner));
+                            }
+                            scripts.tiles.insert(position, trigger(object, owner)?);
+                        }
+                        "region" => {
+                            let properties = Properties::new(owner.clone(), &object.properties, &["on_enter"])?;
+                            let (min, max) = object_area(&raw, &tiles, object, &owner)?;
+                            scripts.regions.push(Region { min, max, on_enter: properties.required_string("on_enter")? });
+                        }
+                        "encounter_zone" => {
+                            let properties = Properties::new(owner.clone(), &object.properties, &["table"])?;
+                            let (min, max) = object_area(&raw, &tiles, object, &owner)?;
+                            encounter_zones.push(EncounterZone { min, max, table: properties.required_string("table")? });
+                        }
+                        _ => {
+                            let position = object_tile(&raw, &tiles, object, &owner)?;
+                            objects.push((position, map_object(object, owner)?));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    if !has_collision {
+        tiles.derive_collision();
+    }
+    let spawn = spawn.ok_or("the map has no 'spawn' object")?;
+
+    Ok(TiledMap {
+        tiles,
+        spawn,
+        objects,
+        scripts,
+        encounter_zones,
+        sprites,
+    })
+}
+
+/// Reads an NPC schedule such as `7:00 10 7, 20:30 5 5`: the time of day
+/// each stop starts, and the grid position to be at.
+fn parse_schedule(schedule: &str) -> Result<Vec<Stop>, String> {
+    let mut stops = Vec::new();
+    for entry in schedule.split(',') {
+        let invalid = || format!("invalid schedule stop '{}', expected 'hh:mm x y'", entry.trim());
+        let parts: Vec<&str> = entry.split_whitespace().collect();
+        let [time, x, y] = parts[..] else {
+            return Err(invalid());
+        };
+        let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+        let hour: u32 = hour.parse().map_err(|_| invalid())?;
+        let minute: u32 = minute.parse().map_err(|_| invalid())?;
+        if hour >= 24 || minute >= 60 {
+            return Err(invalid());
+        }
+        stops.push(Stop {
+            from: hour * 60 + minute,
+            position: (x.parse().map_err(|_| invalid())?, y.parse().map_err(|_| invalid())?),
+        });
+    }
+    stops.sort_by_key(|stop| stop.from);
+    Ok(stops)
+}
+
+/// Everything but spawn points, triggers and areas, which go elsewhere.
+fn map_object(object: &RawObject, owner: String) -> Result<MapObject, String> {
+    match object.class.as_str() {
+        "portal" => {
+            let properties = Properties::new(
+                owner.clone(),
+                &object.properties,
+                &["destination_map", "destination_x", "destination_y", "generator", "seed", "on_interact"],
+            )?;
+            let destination_position = match (properties.int("destination_x")?, properties.int("destination_y")?) {
+                (Some(x), Some(y)) => Some((x as i32, y as i32)),
+                (None, None) => None,
+                _ => return Err(format!("{}: set both destination_x and destination_y, or neither", owner)),
+            };
+            let destination_map = properties.string("destination_map")?.filter(|name| !name.is_empty());
+            let seed = properties.int("seed")?.map(|seed| seed as u64);
+            let generator = match properties.string("generator")? {
+                Some(_) if destination_map.is_some() => {
+                    return Err(format!("{}: a portal leads to destination_map or a generated map, not both", owner))
+                }
+                Some(name) => match GeneratorKind::from_name(&name) {
+                    Some(kind) => Some(MapGen { kind, seed }),
+                    None => return Err(format!("{}: unknown generator '{}'", owner, name)),
+                },
+                None if seed.is_some() => return Err(format!("{}: a seed needs a generator", owner)),
+                None => None,
+            };
+            Ok(MapObject::Portal {
+                destination_map,
+                destination_position,
+                generator,
+                on_interact: properties.string("on_interact")?,
+            })
+        }
+        "npc" => {
+            let properties =
+                Properties::new(owner.clone(), &object.properties, &["name", "dialogue", "on_interact", "schedule"])?;
+            let name = match properties.string("name")? {
+                Some(name) => name,
+                None if !object.name.is_empty() => object.name.clone(),
+                None => return Err(format!("{}: missing property 'name'", owner)),
+            };
+            Ok(MapObject::Npc {
+                name,
+                dialogue: properties.required_string("dialogue")?,
+                on_interact: properties.string("on_interact")?,
+                schedule: match properties.string("schedule")? {
+                    Some(schedule) => parse_schedule(&schedule).map_err(|e| format!("{}: {}", owner, e))?,
+                    None => Vec::new(),
+                },
+            })
+        }
+        "pickup" => {
+            let properties = Properties::new(owner.clone(), &object.properties, &["item", "count"])?;
+            let count = properties.int("count")?.unwrap_or(1);
+            if count < 1 {
+                return Err(format!("{}: count must be at least 1", owner));
+            }
+            Ok(MapObject::Pickup {
+                item: properties.required_string("item")?,
+                count: count as u32,
+            })
+        }
+        "prop" => {
+            let properties = Properties::new(owner, &object.properties, &["kind", "solid"])?;
+            Ok(MapObject::Prop {
+                kind: properties.required_string("kind")?,
+                solid: properties.bool("solid")?.unwrap_or(true),
+            })
+        }
+        "foe" => {
+            let properties =
+                Properties::new(owner.clone(), &object.properties, &["enemies", "behaviour", "radius", "interval"])?;
+            let enemies = properties
+                .required_string("enemies")?
+                .split(',')
+                .map(|enemy| enemy.trim().to_string())
+                .filter(|enemy| !enemy.is_empty())
+                .collect();
+            let radius = properties.int("radius")?.unwrap_or(DEFAULT_FOE_RADIUS) as i32;
+            let behaviour = match properties.string("behaviour")?.as_deref() {
+                None | Some("wander") => Behaviour::Wander,
+                Some("chase") => Behaviour::Chase { radius },
+                Some("flee") => Behaviour::Flee { radius },
+                Some(other) => return Err(format!("{}: unknown behaviour '{}'", owner, other)),
+            };
+            let interval = properties.float("interval")?.unwrap_or(DEFAULT_FOE_INTERVAL);
+            if interval <= 0.0 {
+                return Err(format!("{}: interval must be positive", owner));
+            }
+            Ok(MapObject::Foe { enemies, behaviour, interval })
+        }
+        "door" => {
+            let properties = Properties::new(owner.clone(), &object.properties, &["open", "key", "flag"])?;
+            let open = properties.bool("open")?.unwrap_or(false);
+            let lock = match (properties.string("key")?, properties.string("flag")?) {
+                (None, None) => None,
+                _ if open => return Err(format!("{}: an open door can't be locked", owner)),
+                (key, flag) => Some(Lock { key, flag }),
+            };
+            Ok(MapObject::Door { open, lock })
+        }
+        "crate" => {
+            let properties = Properties::new(owner, &object.properties, &["kind"])?;
+            Ok(MapObject::Crate {
+                kind: properties.string("kind")?.unwrap_or_else(|| "crate".to_string()),
+            })
+        }
+        "plate" | "lever" | "gate" | "bridge" => {
+            let properties = Properties::new(owner, &object.properties, &["link"])?;
+            let link = properties.required_string("link")?;
+            Ok(match object.class.as_str() {
+                "plate" => MapObject::Plate { link },
+                "lever" => MapObject::Lever { link },
+                "gate" => MapObject::Gate { link },
+                _ => MapObject::Bridge { link },
+            })
+        }
+        "" => Err(format!("object {} has no class", object.id)),
+        _ => Err(format!("{}: unknown object class", owner)),
+    }
+}
+
+fn trigger(object: &RawObject, owner: String) -> Result<Trigger, String> {
+    let properties = Properties::new(
+        owner.clone(),
+        &object.properties,
+        &["on_step", "warp_map", "warp_x", "warp_y", "damage", "slide", "once", "requires"],
+    )?;
+
+    let warp_position = match (properties.int("warp_x")?, properties.int("warp_y")?) {
+        (Some(x), Some(y)) => Some((x as i32, y as i32)),
+        (None, None) => None,
+        _ => return Err(format!("{}: set both warp_x and warp_y, or neither", owner)),
+    };
+    let warp_map = properties.string("warp_map")?.filter(|name| !name.is_empty());
+    let mut actions = Vec::new();
+    if let Some(script) = properties.string("on_step")? {
+        actions.push(TriggerAction::Script(script));
+    }
+    if warp_map.is_some() || warp_position.is_some() {
+        actions.push(TriggerAction::Warp { map: warp_map, position: warp_position });
+    }
+    if let Some(amount) = properties.int("damage")? {
+        if amount < 1 {
+            return Err(format!("{}: damage must be at least 1", owner));
+        }
+        actions.push(TriggerAction::Damage(amount as i32));
+    }
+    if let Some(name) = properties.string("slide")? {
+        match Slide::from_name(&name) {
+            Some(slide) => actions.push(TriggerAction::Slide(slide)),
+            None => return Err(format!("{}: slide must be up, down, left, right or ice, not '{}'", owner, name)),
+        }
+    }
+
+    let action = match actions.len() {
+        0 => return Err(format!("{}: a trigger needs on_step, a warp, damage or slide", owner)),
+        1 => actions.remove(0),
+        _ => return Err(format!("{}: a trigger does one thing; use a script for more", owner)),
+    };
+    Ok(Trigger {
+        once: properties.bool("once")?.unwrap_or(false),
+        requires: properties.string("requires")?,
+        ..Trigger::new(action)
+    })
+}
+
+/// Grid position of the tile under an object's centre.
+fn object_tile(raw: &RawMap, tiles: &TileMap, object: &RawObject, owner: &str) -> Result<(i32, i32), String> {
+    let center_x = object.x + object.width / 2.0;
+    let center_y = if object.gid.is_some() {
+        object.y - object.height / 2.0
+    } else {
+        object.y + object.height / 2.0
+    };
+    let column = (center_x / raw.tile_width as f64).floor();
+    let row = (center_y / raw.tile_height as f64).floor();
+
+    if column < 0.0 || row < 0.0 || column >= raw.width as f64 || row >= raw.height as f64 {
+        return Err(format!("{}: lies outside the map", owner));
+    }
+    Ok((tiles.left() + column as i32, tiles.top() - row as i32))
+}
+
+/// Grid corners (min, max) of the tiles a rectangle object covers.
+fn object_area(raw: &RawMap, tiles: &TileMap, object: &RawObject, owner: &str) -> Result<Area, String> {
+    if object.width <= 0.0 || object.height <= 0.0 || object.gid.is_some() {
+        return Err(format!("{}: should be a rectangle", owner));
+    }
+    let first_column = (object.x / raw.tile_width as f64).floor() as i32;
+    let first_row = (object.y / raw.tile_height as f64).floor() as i32;
+    let last_column = ((object.x + object.width) / raw.tile_width as f64).ceil() as i32 - 1;
+    let last_row = ((object.y + object.height) / raw.tile_height as f64).ceil() as i32 - 1;
+
+    if first_column < 0 || first_row < 0 || last_column >= raw.width as i32 || last_row >= raw.height as i32 {
+        return Err(format!("{}: lies outside the map", owner));
+    }
+    let (left, top) = (tiles.left(), tiles.top());
+    Ok(((left + first_column, top - last_row), (left + last_column, top - first_row)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::FLOOR;
+
+    /// A 3 by 2 map of floor and walls with a spawn point, a door and a
+    /// trigger, which `edit` can change before it is read.
+    fn json_map(edit: impl FnOnce(&mut serde_json::Value)) -> Result<TiledMap, String> {
+        let tile = |id: u32, name: &str| json!({ "id": id, "properties": [property("tile", "string", json!(name))] });
+        let open = property("open", "bool", json!(true));
+        let damage = property("damage", "int", json!(2));
+        let mut map = json!({
+            "type": "map",
+            "orientation": "orthogonal",
+            "width": 3,
+            "height": 2,
+            "tilewidth": 16,
+            "tileheight": 16,
+            "tilesets": [{ "firstgid": 1, "name": "tiles", "tiles": [tile(0, "floor"), tile(1, "wall")] }],
+            "layers": [
+                { "type": "tilelayer", "name": "ground", "data": [1, 1, 2, 1, 2, 1] },
+                {
+                    "type": "objectgroup",
+                    "name": "objects",
+                    "objects": [
+                        { "id": 1, "type": "spawn", "x": 8, "y": 24 },
+                        { "id": 2, "type": "door", "x": 40, "y": 24, "properties": [open] },
+                        { "id": 3, "type": "trigger", "x": 8, "y": 8, "properties": [damage] },
+                    ],
+                },
+            ],
+        });
+        edit(&mut map);
+        parse_json(&map.to_string(), Path::new("")).and_then(build)
+    }
+
+    /// The same map as `json_map`, as XML with `edit` applied to the text.
+    fn tmx_map(edit: impl FnOnce(String) -> String) -> Result<TiledMap, String> {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.10" orientation="orthogonal" width="3" height="2" tilewidth="16" tileheight="16">
+ <tileset firstgid="1" name="tiles" tilewidth="16" tileheight="16">
+  <tile id="0"><properties><property name="tile" value="floor"/></properties></tile>
+  <tile id="1"><properties><property name="tile" value="wall"/></properties></tile>
+ </tileset>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,1,2,
+1,2,1
+</data>
+ </layer>
+ <objectgroup id="2" name="objects">
+  <object id="1" class="spawn" x="8" y="24"><point/></object>
+  <object id="2" class="door" x="40" y="24">
+   <properties><property name="open" type="bool" value="true"/></properties>
+   <point/>
+  </object>
+  <object id="3" class="trigger" x="8" y="8">
+   <properties><property name="damage" type="int" value="2"/></properties>
+   <point/>
+  </object>
+ </objectgroup>
+</map>
+"#;
+        parse_tmx(&edit(source.to_string()), Path::new("")).and_then(build)
+    }
+
+    fn error(result: Result<TiledMap, String>) -> String {
+        match result {
+            Ok(_) => panic!("the map loaded"),
+            Err(e) => e,
+        }
+    }
+
+    /// The objects list of the JSON map.
+    fn objects(map: &mut serde_json::Value) -> &mut Vec<serde_json::Value> {
+        map["layers"][1]["objects"].as_array_mut().unwrap()
+    }
+
+    /// Every layer and the collision of each tile, row by row.
+    fn layers(map: &TileMap) -> Vec<[u8; 4]> {
+        map.positions()
+            .map(|(x, y)| {
+                let layers = [Layer::Ground, Layer::Decoration, Layer::Overhead].map(|layer| map.tile(layer, x, y));
+                [layers[0], layers[1], layers[2], u8::from(map.is_solid(x, y))]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn both_formats_read_the_same_map() {
+        for map in [json_map(|_| {}), tmx_map(|source| source)] {
+            let map = map.unwrap();
+            // The bottom row is y = 0, so the top-left tile is (0, 1)
+            assert_eq!((map.tiles.left(), map.tiles.top()), (0, 1));
+            assert_eq!(map.tiles.ground(2, 1), WALL);
+            assert_eq!(map.tiles.ground(0, 0), FLOOR);
+            assert!(map.tiles.is_solid(1, 0) && !map.tiles.is_solid(2, 0));
+            assert_eq!(map.spawn, (0, 0));
+            assert_eq!(map.objects, vec![((2, 0), MapObject::Door { open: true, lock: None })]);
+            assert_eq!(map.scripts.tiles[&(0, 1)], Trigger::new(TriggerAction::Damage(2)));
+        }
+    }
+
+    #[test]
+    fn mistakes_in_json_maps_are_rejected() {
+        let unknown_property = json_map(|map| {
+            objects(map)[1]["properties"].as_array_mut().unwrap().push(property("colour", "string", json!("red")));
+        });
+        assert!(error(unknown_property).contains("unknown property 'colour'"));
+
+        let unknown_class = json_map(|map| objects(map)[1]["type"] = json!("dragon"));
+        assert_eq!(error(unknown_class), "object 2 (dragon): unknown object class");
+
+        let unknown_layer = json_map(|map| map["layers"][0]["name"] = json!("grund"));
+        assert!(error(unknown_layer).starts_with("unknown tile layer 'grund'"));
+
+        let unknown_tile = json_map(|map| map["tilesets"][0]["tiles"][1]["properties"][0]["value"] = json!("lava"));
+        assert!(error(unknown_tile).contains("unknown tile type 'lava'"));
+
+        let short_layer = json_map(|map| map["layers"][0]["data"] = json!([1, 1, 2, 1, 2]));
+        assert_eq!(error(short_layer), "layer 'ground' has 5 tiles, expected 6");
+
+        let second_spawn = json_map(|map| objects(map).push(json!({ "id": 4, "type": "spawn", "x": 24, "y": 8 })));
+        assert_eq!(error(second_spawn), "object 4 (spawn): the map already has a spawn point");
+
+        let no_spawn = json_map(|map| {
+            objects(map).remove(0);
+        });
+        assert_eq!(error(no_spawn), "the map has no 'spawn' object");
+    }
+
+    #[test]
+    fn mistakes_in_tmx_maps_are_rejected() {
+        let unknown_property = tmx_map(|source| source.replace("name=\"open\"", "name=\"opne\""));
+        assert!(error(unknown_property).contains("unknown property 'opne'"));
+
+        let unknown_class = tmx_map(|source| source.replace("class=\"door\"", "class=\"dragon\""));
+        assert_eq!(error(unknown_class), "object 2 (dragon): unknown object class");
+
+        let unknown_layer = tmx_map(|source| source.replace("name=\"ground\"", "name=\"floor\""));
+        assert!(error(unknown_layer).starts_with("unknown tile layer 'floor'"));
+
+        let unknown_tile = tmx_map(|source| source.replace("value=\"wall\"", "value=\"lava\""));
+        assert!(error(unknown_tile).contains("unknown tile type 'lava'"));
+
+        let long_layer = tmx_map(|source| source.replace("1,2,1\n", "1,2,1,1\n"));
+        assert_eq!(error(long_layer), "layer 'ground' has 7 tiles, expected 6");
+
+        let spawn = "<object id=\"1\" class=\"spawn\" x=\"8\" y=\"24\"><point/></object>";
+        let second_spawn = tmx_map(|source| {
+            source.replace(spawn, &format!("{}\n  <object id=\"4\" class=\"spawn\" x=\"24\" y=\"8\"/>", spawn))
+        });
+        assert_eq!(error(second_spawn), "object 4 (spawn): the map already has a spawn point");
+    }
+
+    #[test]
+    fn saved_maps_load_back_the_same() {
+        let mut tiles = TileMap::new(-2, 3, 4, 3);
+        tiles.set_tile(Layer::Ground, -2, 3, WALL);
+        tiles.set_tile(Layer::Decoration, 0, 2, map::TREE);
+        tiles.set_tile(Layer::Overhead, 0, 3, map::CANOPY);
+        tiles.derive_collision();
+        // Collision that doesn't follow from the tiles has to survive too
+        tiles.set_solid(1, 1, true);
+
+        let objects = vec![
+            (
+                (-1, 2),
+                MapObject::Npc {
+                    name: "Odo".to_string(),
+                    dialogue: "odo_hello".to_string(),
+                    on_interact: None,
+                    schedule: vec![
+                        Stop { from: 7 * 60, position: (1, 2) },
+                        Stop { from: 20 * 60 + 30, position: (-1, 2) },
+                    ],
+                },
+            ),
+            (
+                (1, 3),
+                MapObject::Portal {
+                    destination_map: None,
+                    destination_position: None,
+                    generator: Some(MapGen { kind: GeneratorKind::Caves, seed: Some(u64::MAX) }),
+                    on_interact: Some("cave_warning".to_string()),
+                },
+            ),
+            (
+                (-2, 1),
+                MapObject::Foe {
+                    enemies: vec!["rat".to_string()],
+                    behaviour: Behaviour::Chase { radius: 3 },
+                    interval: 0.5,
+                },
+            ),
+            ((1, 2), MapObject::Door { open: false, lock: Some(Lock { key: Some("key".to_string()), flag: None }) }),
+            ((-1, 1), MapObject::Pickup { item: "apple".to_string(), count: 2 }),
+            ((0, 1), MapObject::Gate { link: "a".to_string() }),
+        ];
+
+        let mut scripts = MapScripts { on_enter: Some("village_enter".to_string()), ..MapScripts::default() };
+        let warp = TriggerAction::Warp { map: Some("cave".to_string()), position: Some((4, -4)) };
+        scripts.tiles.insert((-1, 3), Trigger::new(warp));
+        let ambush = Trigger {
+            once: true,
+            requires: Some("met_odo".to_string()),
+            ..Trigger::new(TriggerAction::Script("ambush".to_string()))
+        };
+        scripts.tiles.insert((0, 1), ambush);
+        scripts.tiles.insert((1, 1), Trigger::new(TriggerAction::Slide(Slide::Ice)));
+        scripts.regions.push(Region { min: (-2, 1), max: (-1, 2), on_enter: "meadow".to_string() });
+
+        let map = TiledMap {
+            tiles,
+            spawn: (0, 3),
+            objects,
+            scripts,
+            encounter_zones: vec![EncounterZone { min: (0, 1), max: (1, 3), table: "grass".to_string() }],
+            sprites: SpriteData { atlases: Vec::new(), sprites: Vec::new() },
+        };
+        let tileset = TilesetDef {
+            name: "tiles".to_string(),
+            image: "tiles.png".to_string(),
+            tile_width: 16,
+            tile_height: 16,
+            columns: 4,
+            rows: 1,
+            frames: vec![(FLOOR, 0), (WALL, 1), (map::TREE, 2), (map::CANOPY, 3)],
+        };
+
+        let path = std::env::temp_dir().join(format!("tiled_round_trip_{}.tmj", std::process::id()));
+        save(&path, &map, &tileset).unwrap();
+        let loaded = load(&path);
+        let _ = fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+
+        assert_eq!((loaded.tiles.left(), loaded.tiles.top()), (-2, 3));
+        assert_eq!(layers(&loaded.tiles), layers(&map.tiles));
+        assert_eq!(loaded.spawn, map.spawn);
+        assert_eq!(loaded.objects, map.objects);
+        assert_eq!(loaded.scripts.on_enter, map.scripts.on_enter);
+        assert_eq!(loaded.scripts.tiles, map.scripts.tiles);
+        assert_eq!(loaded.scripts.regions, map.scripts.regions);
+        assert_eq!(loaded.encounter_zones, map.encounter_zones);
+    }
+}
@@ -0,0 +1,87 @@
+//! Tiles that do something as soon as the player steps onto them, rather than
+//! when they press E on something: run a script, warp them elsewhere, hurt
+//! them or send them sliding.
+//!
+//! A trigger can carry conditions: `once` triggers fire a single time, and a
+//! trigger with `requires` only fires while that story flag is set. Triggers
+//! chain, so a warp or slide that lands the player on another trigger fires
+//! that one too (see `MAX_CHAINED_TRIGGERS`).
+
+use serde::{Deserialize, Serialize};
+use crate::entity::Direction;
+use crate::script::Flags;
+
+/// How many triggers may fire off one step before the chain is cut off, so
+/// two warps leading onto each other can't loop forever.
+pub const MAX_CHAINED_TRIGGERS: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TriggerAction {
+    /// Runs a script, such as one starting a cutscene
+    Script(String),
+    /// Takes the player to `position` on this map, or onto a map file
+    /// (at its spawn point if no position is given)
+    Warp { map: Option<String>, position: Option<(i32, i32)> },
+    /// Takes this much HP off the player
+    Damage(i32),
+    Slide(Slide),
+}
+
+/// Ground the player can't stop on, which carries them on a tile at a time
+/// until they reach solid footing or run into something.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Slide {
+    /// Always moves the player the same way
+    Conveyor(Direction),
+    /// Keeps the player going the way they came onto it
+    Ice,
+}
+
+impl Slide {
+    /// Tiled name of the slide: a direction for a conveyor, or `ice`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Slide::Conveyor(direction) => direction.name(),
+            Slide::Ice => "ice",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Slide> {
+        match name {
+            "ice" => Some(Slide::Ice),
+            _ => Direction::from_name(name).map(Slide::Conveyor),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trigger {
+    pub action: TriggerAction,
+    /// Fires only the first time the player steps on it
+    pub once: bool,
+    /// Story flag that has to be set for it to fire
+    pub requires: Option<String>,
+    /// Whether it has fired before, which matters for `once` triggers
+    pub fired: bool,
+}
+
+impl Trigger {
+    pub fn new(action: TriggerAction) -> Self {
+        Trigger {
+            action,
+            once: false,
+            requires: None,
+            fired: false,
+        }
+    }
+
+    /// A trigger that runs a script every time it is stepped on.
+    pub fn script(name: &str) -> Self {
+        Trigger::new(TriggerAction::Script(name.to_string()))
+    }
+
+    /// Whether stepping on it now would set it off.
+    pub fn is_armed(&self, flags: &Flags) -> bool {
+        !(self.once && self.fired) && self.requires.as_deref().is_none_or(|flag| flags.get(flag))
+    }
+}